@@ -0,0 +1,97 @@
+/*!
+Benchmarks comparing `FontData`'s lookup-table glyph selection
+(`.pixel()`/`.pixel_inv()`) against directly searching the character
+map (`.pixel_uncached()`/`.pixel_inv_uncached()`).
+
+Run with `cargo bench -p ascii_art`.
+*/
+use std::hint::black_box;
+use std::io::BufReader;
+
+use ascii_art::{printable_ascii, FontData, Image};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const FONT_PATH: &str = "test/LiberationMono-Regular.ttf";
+const IMAGE_PATHS: &[&str] = &[
+    "test/griffin_sm.jpg",
+    "test/griffin.jpg",
+    "test/rust-social-sm.jpg",
+    "test/rust-social.jpg",
+];
+
+fn test_font(size: f32) -> FontData {
+    let font_bytes = std::fs::read(FONT_PATH).unwrap();
+    let chars = printable_ascii();
+    FontData::from_font_bytes(&font_bytes, size, &chars)
+        .unwrap()
+        .unwrap()
+}
+
+/*
+Every pixel of the image at `path`, as normalized intensities.
+
+This is the worst case for glyph selection: one lookup per source pixel
+rather than one per output character.
+*/
+fn intensities(path: &str) -> Vec<f32> {
+    image::open(path).unwrap().to_luma32f().into_raw()
+}
+
+/*
+Look up the glyph for every value in `vals` with `f`, making sure the
+optimizer can't skip any of the work.
+*/
+fn select_all<F: Fn(f32) -> char>(vals: &[f32], f: F) {
+    for v in vals.iter() {
+        black_box(f(black_box(*v)));
+    }
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let font = test_font(12.0);
+
+    let mut group = c.benchmark_group("sweep");
+    let levels: Vec<f32> = (0..65536).map(|n| (n as f32) / 65535.0).collect();
+    group.bench_function("pixel", |b| {
+        b.iter(|| select_all(&levels, |v| font.pixel(v)))
+    });
+    group.bench_function("pixel_uncached", |b| {
+        b.iter(|| select_all(&levels, |v| font.pixel_uncached(v)))
+    });
+    group.bench_function("pixel_inv", |b| {
+        b.iter(|| select_all(&levels, |v| font.pixel_inv(v)))
+    });
+    group.bench_function("pixel_inv_uncached", |b| {
+        b.iter(|| select_all(&levels, |v| font.pixel_inv_uncached(v)))
+    });
+    group.finish();
+
+    for path in IMAGE_PATHS.iter() {
+        let pixels = intensities(path);
+        let mut group = c.benchmark_group(*path);
+        group.bench_function("pixel", |b| {
+            b.iter(|| select_all(&pixels, |v| font.pixel(v)))
+        });
+        group.bench_function("pixel_uncached", |b| {
+            b.iter(|| select_all(&pixels, |v| font.pixel_uncached(v)))
+        });
+        group.finish();
+    }
+}
+
+fn bench_render(c: &mut Criterion) {
+    let font = test_font(8.0);
+
+    let mut group = c.benchmark_group("write");
+    for path in IMAGE_PATHS.iter() {
+        let f = std::fs::File::open(path).unwrap();
+        let img = Image::auto(BufReader::new(f)).unwrap();
+        group.bench_function(*path, |b| {
+            b.iter(|| ascii_art::write(&img, &font, std::io::sink()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lookup, bench_render);
+criterion_main!(benches);
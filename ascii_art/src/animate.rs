@@ -0,0 +1,425 @@
+/*!
+Rendering consecutive frames of an animation so that they hang together.
+
+Rendering each frame on its own makes cells whose intensity sits near the
+boundary between two glyphs flip back and forth between them with every
+bit of noise. An `Animator` remembers what it drew last time, and can
+
+  * keep a cell's previous glyph unless a new one is better by some
+    margin (hysteresis),
+  * blend each cell's intensity with its intensities in previous frames
+    (smoothing), and
+  * produce output that redraws only the cells that changed.
+*/
+
+use std::collections::HashMap;
+
+use crate::{text, FontData, Image, RenderOptions};
+
+/**
+Options controlling how an `Animator` carries each frame over to the
+next. The defaults render every frame exactly as `write_with()` would.
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnimationOptions {
+    /// How much better (in coverage, from 0.0 to 1.0) a new glyph has to
+    /// match a cell than the glyph already there before it replaces it.
+    pub hysteresis: f32,
+    /// How much of each cell's intensity in the previous frame carries
+    /// over into the next (from 0.0, none, to nearly 1.0, where the
+    /// image barely changes at all).
+    pub smoothing: f32,
+    /// Only redraw the cells that changed since the previous frame.
+    pub diff: bool,
+}
+
+/**
+Renders the frames of an animation one after another, as specified by
+`RenderOptions` and `AnimationOptions`.
+
+```
+use ascii_art::{AnimationOptions, Animator, FrameReader, RenderOptions};
+# let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+# let font = ascii_art::FontData::from_font_bytes(
+#     &bytes, 12.0, &ascii_art::printable_ascii()
+# ).unwrap().unwrap();
+
+let anim = AnimationOptions {
+    hysteresis: 0.05,
+    smoothing: 0.5,
+    diff: true,
+};
+let mut animator = Animator::new(&font, &RenderOptions::terminal(), &anim);
+
+// Two identical frames: the second one doesn't need to redraw anything.
+let data = vec![128u8; 2 * 64 * 64];
+let mut frames = FrameReader::raw_gray(&data[..], 64, 64);
+let first = animator.update(&frames.next_frame().unwrap().unwrap());
+let second = animator.update(&frames.next_frame().unwrap().unwrap());
+assert!(first.starts_with("\x1b[H"));
+assert_eq!(second, "");
+```
+*/
+pub struct Animator<'a> {
+    font: &'a FontData,
+    opts: RenderOptions,
+    anim: AnimationOptions,
+    // The coverage of each third of each glyph.
+    patterns: HashMap<char, [f32; 3]>,
+    // Samples per cell: 3 when rendering subpixel, otherwise 1.
+    samples: usize,
+    // The size (in cells) of the previous frame, its (smoothed)
+    // intensities, and its glyphs. `glyphs` is empty before the first
+    // frame.
+    size: (u32, u32),
+    smoothed: Vec<f32>,
+    glyphs: Vec<char>,
+}
+
+impl<'a> Animator<'a> {
+    /// Create an `Animator` for rendering frames in `font`.
+    pub fn new(font: &'a FontData, opts: &RenderOptions, anim: &AnimationOptions) -> Animator<'a> {
+        let subpixel = opts.subpixel && font.has_subpixel();
+        Animator {
+            font,
+            opts: opts.clone(),
+            anim: anim.clone(),
+            patterns: font.coverage_thirds(subpixel).into_iter().collect(),
+            samples: if subpixel { 3 } else { 1 },
+            size: (0, 0),
+            smoothed: Vec::new(),
+            glyphs: Vec::new(),
+        }
+    }
+
+    /// The width and height (in characters) of the most recent frame.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /**
+    Render the next frame, returning the text of each of its rows.
+    */
+    pub fn render(&mut self, img: &Image) -> Vec<String> {
+        let (img_w, img_h) = img.geometry();
+        let (w, h) = crate::grid_size(img_w, img_h, self.opts.cell_size(self.font));
+        if w == 0 {
+            self.size = (w, h);
+            self.glyphs.clear();
+            return vec![String::new(); h as usize];
+        }
+
+        let k = self.samples;
+        let vals = crate::resize_nearest(&img.buff, k as u32 * w, h).into_raw();
+        let alpha = self.anim.smoothing.clamp(0.0, 1.0);
+        if (w, h) != self.size || self.glyphs.is_empty() {
+            // Nothing to carry over from.
+            self.size = (w, h);
+            self.smoothed = vals;
+            self.glyphs.clear();
+        } else if alpha > 0.0 {
+            for (s, v) in self.smoothed.iter_mut().zip(vals.iter()) {
+                *s = alpha * *s + (1.0 - alpha) * v;
+            }
+        } else {
+            self.smoothed = vals;
+        }
+
+        let mut glyphs: Vec<char> = self
+            .smoothed
+            .chunks_exact(k)
+            .enumerate()
+            .map(|(n, s)| self.choose(s, self.glyphs.get(n).copied()))
+            .collect();
+        if self.opts.preserve_text {
+            // Text in this frame is drawn exactly as `write_with()` would
+            // draw it, whatever hysteresis and smoothing made of it.
+            let rows = glyphs
+                .chunks_exact(w as usize)
+                .map(|row| row.iter().collect())
+                .collect();
+            glyphs = text::sharpen_text(img, self.font, &self.opts, rows)
+                .concat()
+                .chars()
+                .collect();
+        }
+        self.glyphs = glyphs;
+
+        self.glyphs
+            .chunks_exact(w as usize)
+            .map(|row| row.iter().collect())
+            .collect()
+    }
+
+    /*
+    Choose the glyph for a cell with the given intensity samples, which
+    had the glyph `prev` in the previous frame.
+    */
+    fn choose(&self, samples: &[f32], prev: Option<char>) -> char {
+        let new = match (samples, self.opts.invert) {
+            ([v], false) => self.font.pixel(*v),
+            ([v], true) => self.font.pixel_inv(*v),
+            ([a, b, c], false) => self.font.subpixel([*a, *b, *c]),
+            ([a, b, c], true) => self.font.subpixel_inv([*a, *b, *c]),
+            _ => unreachable!(),
+        };
+
+        let prev = match prev {
+            Some(p) if p != new && self.anim.hysteresis > 0.0 => p,
+            _ => return new,
+        };
+
+        // How far (root-mean-square) a glyph's coverage is from the
+        // coverage the cell calls for.
+        let error = |chr: char| {
+            let pattern = self.patterns.get(&chr).copied().unwrap_or_default();
+            let sum: f32 = samples
+                .iter()
+                .zip(pattern.iter())
+                .map(|(v, p)| {
+                    let target = if self.opts.invert { 1.0 - v } else { *v };
+                    (target - p) * (target - p)
+                })
+                .sum();
+            (sum / samples.len() as f32).sqrt()
+        };
+
+        if error(prev) - error(new) < self.anim.hysteresis {
+            prev
+        } else {
+            new
+        }
+    }
+
+    /**
+    Render the next frame, returning the text (with terminal escape
+    sequences) that brings a terminal showing the previous frame up to
+    date.
+
+    That's the whole frame, drawn from the top left corner, unless
+    `AnimationOptions::diff` is set, in which case (after the first
+    frame) it's only the cells that changed, each run of them preceded by
+    an escape sequence moving the cursor there. The cursor is left at
+    the end of whatever was drawn last.
+    */
+    pub fn update(&mut self, img: &Image) -> String {
+        let prev_size = self.size;
+        let prev = if self.anim.diff {
+            self.glyphs.clone()
+        } else {
+            Vec::new()
+        };
+        let rows = self.render(img);
+
+        if prev.is_empty() || prev_size != self.size {
+            return format!("\x1b[H{}", rows.join("\r\n"));
+        }
+
+        let w = self.size.0 as usize;
+        let mut out = String::new();
+        for (y, (old, new)) in prev
+            .chunks_exact(w)
+            .zip(self.glyphs.chunks_exact(w))
+            .enumerate()
+        {
+            let mut x = 0;
+            while x < w {
+                if old[x] == new[x] {
+                    x += 1;
+                    continue;
+                }
+                // Extend the run over short stretches of unchanged cells,
+                // which are cheaper to redraw than to jump past.
+                let start = x;
+                let mut end = x + 1;
+                let mut next = end;
+                while next < w && next - end <= MAX_GAP {
+                    if old[next] != new[next] {
+                        end = next + 1;
+                    }
+                    next += 1;
+                }
+                out.push_str(&format!("\x1b[{};{}H", y + 1, start + 1));
+                out.extend(&new[start..end]);
+                x = end;
+            }
+        }
+        out
+    }
+}
+
+// The longest stretch of unchanged cells `Animator::update()` will redraw
+// rather than moving the cursor past.
+const MAX_GAP: usize = 4;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FrameReader;
+
+    fn font() -> FontData {
+        let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+        FontData::from_font_bytes(&bytes, 12.0, &crate::printable_ascii())
+            .unwrap()
+            .unwrap()
+    }
+
+    fn frame(shades: &[u8], width: u32) -> Image {
+        FrameReader::raw_gray(shades, width, 1)
+            .next()
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn defaults_match_write() {
+        let font = font();
+        let opts = RenderOptions::default();
+        let mut animator = Animator::new(&font, &opts, &AnimationOptions::default());
+
+        let f = std::fs::File::open("test/griffin_sm.jpg").unwrap();
+        let img = Image::auto(std::io::BufReader::new(f)).unwrap();
+        let mut expected: Vec<u8> = Vec::new();
+        crate::write_with(&img, &font, &opts, &mut expected).unwrap();
+        let expected = String::from_utf8(expected).unwrap();
+
+        for _ in 0..2 {
+            let rows = animator.render(&img);
+            assert_eq!(rows.join("\n") + "\n", expected);
+        }
+    }
+
+    #[test]
+    fn preserve_text() {
+        let font = font();
+        let opts = RenderOptions {
+            preserve_text: true,
+            ..RenderOptions::default()
+        };
+        // A gradient with a band of thin, closely-spaced white strokes on
+        // black (which looks like text) across the middle.
+        let buff = image::ImageBuffer::from_fn(300, 120, |x, y| {
+            if (42..84).contains(&y) && (10..290).contains(&x) {
+                let stroke = x.is_multiple_of(4) || (y - 42).is_multiple_of(6);
+                image::Luma([if stroke { 1.0f32 } else { 0.0 }])
+            } else {
+                image::Luma([x as f32 / 300.0])
+            }
+        });
+        let img = Image { buff };
+        let expected = crate::render_with(&img, &font, &opts);
+        assert_ne!(
+            expected,
+            crate::render_with(&img, &font, &Default::default())
+        );
+
+        let mut animator = Animator::new(&font, &opts, &AnimationOptions::default());
+        for _ in 0..2 {
+            assert_eq!(animator.render(&img), expected);
+        }
+
+        // Text stays sharp whatever hysteresis and smoothing do to the
+        // rest of the frame.
+        let anim = AnimationOptions {
+            hysteresis: 0.2,
+            smoothing: 0.9,
+            diff: true,
+        };
+        let mut animator = Animator::new(&font, &opts, &anim);
+        let blank = Image {
+            buff: image::ImageBuffer::from_pixel(300, 120, image::Luma([0.5f32])),
+        };
+        animator.update(&blank);
+        animator.update(&img);
+        let w = expected[0].chars().count();
+        let text_row = (63.0 / opts.cell_size(&font).1) as usize;
+        let plain = crate::render_with(&img, &font, &Default::default());
+        let rows = animator.render(&img);
+        let sharpened: Vec<usize> = (0..w)
+            .filter(|x| expected[text_row].chars().nth(*x) != plain[text_row].chars().nth(*x))
+            .collect();
+        assert!(!sharpened.is_empty());
+        for x in sharpened {
+            assert_eq!(
+                rows[text_row].chars().nth(x),
+                expected[text_row].chars().nth(x),
+                "column {}",
+                x
+            );
+        }
+    }
+
+    #[test]
+    fn hysteresis_and_smoothing() {
+        let font = font();
+        // One cell per pixel.
+        let opts = RenderOptions {
+            cell_width: Some(1.0),
+            cell_height: Some(1.0),
+            ..RenderOptions::default()
+        };
+        let shades: Vec<u8> = (0..=255).step_by(5).collect();
+        let w = shades.len() as u32;
+        let nudged: Vec<u8> = shades.iter().map(|s| s.saturating_add(2)).collect();
+
+        let plain = |s: &[u8]| {
+            let mut a = Animator::new(&font, &opts, &AnimationOptions::default());
+            a.render(&frame(s, w))
+        };
+
+        // Without hysteresis, a small nudge changes some glyphs; with it,
+        // they stay put.
+        assert_ne!(plain(&shades), plain(&nudged));
+        let anim = AnimationOptions {
+            hysteresis: 0.05,
+            ..AnimationOptions::default()
+        };
+        let mut a = Animator::new(&font, &opts, &anim);
+        let first = a.render(&frame(&shades, w));
+        assert_eq!(a.render(&frame(&nudged, w)), first);
+        // But a big change still comes through.
+        let dark = vec![0u8; w as usize];
+        assert_eq!(a.render(&frame(&dark, w)), plain(&dark));
+
+        // Smoothing halfway from black to white lands on gray.
+        let anim = AnimationOptions {
+            smoothing: 0.5,
+            ..AnimationOptions::default()
+        };
+        let mut a = Animator::new(&font, &opts, &anim);
+        a.render(&frame(&[0], 1));
+        let gray = a.render(&frame(&[255], 1));
+        assert_eq!(gray, vec![font.pixel(0.5).to_string()]);
+    }
+
+    #[test]
+    fn diffs() {
+        let font = font();
+        let opts = RenderOptions {
+            cell_width: Some(1.0),
+            cell_height: Some(1.0),
+            ..RenderOptions::default()
+        };
+        let anim = AnimationOptions {
+            diff: true,
+            ..AnimationOptions::default()
+        };
+        let mut a = Animator::new(&font, &opts, &anim);
+        let (lo, hi) = (font.pixel(0.0), font.pixel(1.0));
+
+        let mut shades = vec![0u8; 20];
+        let full = a.update(&frame(&shades, 20));
+        assert_eq!(full, format!("\x1b[H{}", lo.to_string().repeat(20)));
+
+        // Two changes close together are one run; one far away is another.
+        shades[1] = 255;
+        shades[3] = 255;
+        shades[15] = 255;
+        let diff = a.update(&frame(&shades, 20));
+        assert_eq!(diff, format!("\x1b[1;2H{}{}{}\x1b[1;16H{}", hi, lo, hi, hi));
+
+        // A frame of a different size is drawn in full.
+        let full = a.update(&frame(&shades[..10], 10));
+        assert!(full.starts_with("\x1b[H"));
+    }
+}
@@ -0,0 +1,157 @@
+/*!
+Writing animations as asciinema (asciicast v2) recordings.
+
+An asciicast v2 file is a line of JSON describing the terminal (its width
+and height, at least), followed by a line of JSON for each chunk of
+output: `[time, "o", data]`, where `time` is the number of seconds since
+the start of the recording.
+*/
+
+use std::io::Write;
+use std::time::Duration;
+
+use serde_derive::Serialize;
+
+use crate::Error;
+
+fn io_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::IOError(format!("{}", &e))
+}
+
+#[derive(Serialize)]
+struct Header<'a> {
+    version: u8,
+    width: usize,
+    height: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+}
+
+/**
+Writes frames of text to an asciicast v2 (`.cast`) file, for replaying
+(or embedding) with asciinema.
+
+The header, with the size of the terminal needed, is written along with
+the first frame, so the terminal is sized to fit it; every frame should
+be the same size.
+
+```
+use std::time::Duration;
+use ascii_art::AsciicastWriter;
+
+let mut cast = AsciicastWriter::new(Vec::new());
+cast.frame("ab\ncd\n", Duration::from_millis(100)).unwrap();
+cast.frame("dc\nba\n", Duration::from_millis(100)).unwrap();
+let data = String::from_utf8(cast.finish().unwrap()).unwrap();
+
+let lines: Vec<&str> = data.lines().collect();
+assert_eq!(lines[0], r#"{"version":2,"width":2,"height":2}"#);
+assert_eq!(lines[1], r#"[0.0,"o","\u001b[Hab\r\ncd"]"#);
+assert_eq!(lines[2], r#"[0.1,"o","\u001b[Hdc\r\nba"]"#);
+```
+*/
+pub struct AsciicastWriter<W: Write> {
+    writer: W,
+    title: Option<String>,
+    started: bool,
+    // When the next frame starts.
+    time: Duration,
+}
+
+impl<W: Write> AsciicastWriter<W> {
+    /// Start a recording that will be written to `writer`.
+    pub fn new(writer: W) -> AsciicastWriter<W> {
+        AsciicastWriter {
+            writer,
+            title: None,
+            started: false,
+            time: Duration::ZERO,
+        }
+    }
+
+    /// Give the recording a title (which asciinema shows when playing it).
+    pub fn with_title(mut self, title: &str) -> AsciicastWriter<W> {
+        self.title = Some(title.to_owned());
+        self
+    }
+
+    /**
+    Add a frame of `text` (lines separated by `\n`s) to the recording, to
+    be shown for `delay` before the next frame.
+    */
+    pub fn frame(&mut self, text: &str, delay: Duration) -> Result<(), Error> {
+        let rows: Vec<&str> = text.lines().collect();
+        self.frame_rows(&rows, delay)
+    }
+
+    pub(crate) fn frame_rows<S: AsRef<str>>(
+        &mut self,
+        rows: &[S],
+        delay: Duration,
+    ) -> Result<(), Error> {
+        let width = rows
+            .iter()
+            .map(|r| r.as_ref().chars().count())
+            .max()
+            .unwrap_or(0);
+
+        // Draw over the previous frame from the top left. There's no
+        // newline after the last row, as that would scroll the terminal.
+        let mut data = String::from("\x1b[H");
+        for (n, row) in rows.iter().enumerate() {
+            if n > 0 {
+                data.push_str("\r\n");
+            }
+            data.push_str(row.as_ref());
+        }
+        self.frame_data((width, rows.len()), &data, delay)
+    }
+
+    /*
+    Add a frame that's drawn by writing `data` (text and escape sequences)
+    to a terminal of the given `size` showing the previous frame.
+    */
+    pub(crate) fn frame_data(
+        &mut self,
+        size: (usize, usize),
+        data: &str,
+        delay: Duration,
+    ) -> Result<(), Error> {
+        if !self.started {
+            let header = Header {
+                version: 2,
+                width: size.0,
+                height: size.1,
+                title: self.title.as_deref(),
+            };
+            let header = serde_json::to_string(&header).map_err(io_error)?;
+            writeln!(self.writer, "{}", header).map_err(io_error)?;
+            self.started = true;
+        }
+
+        self.event(data)?;
+        self.time += delay;
+        Ok(())
+    }
+
+    fn event(&mut self, data: &str) -> Result<(), Error> {
+        // Microseconds are plenty, and keep the numbers short.
+        let time = (self.time.as_secs_f64() * 1e6).round() / 1e6;
+        let event = serde_json::to_string(&(time, "o", data)).map_err(io_error)?;
+        writeln!(self.writer, "{}", event).map_err(io_error)
+    }
+
+    /**
+    Finish the recording, returning the underlying writer.
+
+    An empty event is added at the end of the last frame's delay, so that
+    the last frame is shown for as long as the others.
+    */
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.started {
+            self.event("")?;
+        }
+        self.writer.flush().map_err(io_error)?;
+        Ok(self.writer)
+    }
+}
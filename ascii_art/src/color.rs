@@ -0,0 +1,766 @@
+/*!
+Colored output for targets that only support a handful of colors.
+
+The characters are chosen exactly as they are for monochrome output;
+additionally, the color of each character cell is quantized to a
+`Palette` (by perceptual distance, optionally with dithering), and the
+output is decorated with whatever codes the target (a terminal, an IRC
+client, a forum, a Minecraft chat box) uses to change colors.
+*/
+
+use std::io::{BufRead, BufWriter, Seek, Write};
+
+use image::{DynamicImage, ImageFormat, RgbImage};
+
+use crate::{Error, FontData, Image, RenderOptions};
+
+pub(crate) fn io_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::IOError(format!("{}", &e))
+}
+
+/**
+An `Image` that also remembers the colors of its pixels, for use with
+`write_color()`.
+*/
+pub struct ColorImage {
+    image: Image,
+    rgb: RgbImage,
+}
+
+impl ColorImage {
+    /**
+    Create a new `ColorImage`, attempting to guess the format of the data
+    in the `Read`er.
+    */
+    pub fn auto<R: BufRead + Seek>(r: R) -> Result<ColorImage, Error> {
+        let img = image::io::Reader::new(r)
+            .with_guessed_format()
+            .map_err(io_error)?
+            .decode()
+            .map_err(io_error)?;
+        Ok(ColorImage::from_decoded(&img))
+    }
+
+    /**
+    Create a new `ColorImage`, attempting to decode the data in `r` from
+    the provided `format`.
+    */
+    pub fn with_format<R: BufRead + Seek>(r: R, format: ImageFormat) -> Result<ColorImage, Error> {
+        let img = image::io::Reader::with_format(r, format)
+            .decode()
+            .map_err(io_error)?;
+        Ok(ColorImage::from_decoded(&img))
+    }
+
+    pub(crate) fn from_decoded(img: &DynamicImage) -> ColorImage {
+        ColorImage {
+            image: Image::from_decoded(img),
+            rgb: img.to_rgb8(),
+        }
+    }
+
+    /// The intensities of the image's pixels, as used for monochrome
+    /// output.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /**
+    Return a copy of the image with the contrast of its intensities
+    multiplied by `factor` (as by `Image::with_contrast()`). The colors
+    are left as they are.
+    */
+    pub fn with_contrast(&self, factor: f32) -> ColorImage {
+        ColorImage {
+            image: self.image.with_contrast(factor),
+            rgb: self.rgb.clone(),
+        }
+    }
+
+    pub(crate) fn rgb(&self) -> &RgbImage {
+        &self.rgb
+    }
+}
+
+/*
+Convert an sRGB color (with components from 0.0 to 1.0) to CIELAB (with
+a D65 white point).
+*/
+fn to_lab(rgb: [f32; 3]) -> [f32; 3] {
+    let lin = rgb.map(|c| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let [r, g, b] = lin;
+
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+
+    const DELTA: f32 = 6.0 / 29.0;
+    let f = |t: f32| {
+        if t > DELTA * DELTA * DELTA {
+            t.cbrt()
+        } else {
+            t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+pub(crate) fn to_unit(rgb: [u8; 3]) -> [f32; 3] {
+    rgb.map(|c| c as f32 / 255.0)
+}
+
+/**
+A set of colors to which the colors of an image get reduced.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+    lab: Vec<[f32; 3]>,
+}
+
+impl Palette {
+    /**
+    Create a palette of the given (8-bit sRGB) colors.
+
+    When used with a target that has a fixed set of colors (see
+    `ColorTarget`), the `n`th color here is output as the target's `n`th
+    color; this way, a palette can describe what a particular terminal
+    (or IRC client) actually displays for each of its colors.
+    */
+    pub fn new(colors: &[[u8; 3]]) -> Result<Palette, Error> {
+        if colors.is_empty() {
+            return Err(Error::InvalidPalette("palette has no colors".to_owned()));
+        }
+        Ok(Palette {
+            colors: colors.to_vec(),
+            lab: colors.iter().map(|c| to_lab(to_unit(*c))).collect(),
+        })
+    }
+
+    /// The colors in the palette.
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    /// The number of colors in the palette.
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    /// Palettes always have at least one color; this is always `false`.
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /**
+    Return the index of the palette color perceptually nearest to the
+    given sRGB color (with components from 0.0 to 1.0), as measured by
+    (CIE76) distance in CIELAB space.
+    */
+    pub fn nearest(&self, rgb: [f32; 3]) -> usize {
+        let lab = to_lab(rgb);
+        let dist = |c: &[f32; 3]| {
+            c.iter()
+                .zip(lab.iter())
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum::<f32>()
+        };
+
+        let mut best = 0;
+        let mut best_dist = f32::INFINITY;
+        for (n, c) in self.lab.iter().enumerate() {
+            let d = dist(c);
+            if d < best_dist {
+                best = n;
+                best_dist = d;
+            }
+        }
+        best
+    }
+
+    /**
+    Reduce the `width`-pixel-wide image in `pixels` (sRGB, components from
+    0.0 to 1.0) to this palette, returning the index of the palette color
+    for each pixel.
+    */
+    pub fn quantize(&self, pixels: &[[f32; 3]], width: usize, dither: Dither) -> Vec<usize> {
+        match dither {
+            Dither::None => pixels.iter().map(|p| self.nearest(*p)).collect(),
+            Dither::FloydSteinberg => self.floyd_steinberg(pixels, width),
+        }
+    }
+
+    fn floyd_steinberg(&self, pixels: &[[f32; 3]], width: usize) -> Vec<usize> {
+        if width == 0 {
+            return Vec::new();
+        }
+
+        let mut buff = pixels.to_vec();
+        let mut out = Vec::with_capacity(pixels.len());
+        let height = pixels.len() / width;
+
+        for y in 0..height {
+            for x in 0..width {
+                let p = buff[y * width + x].map(|c| c.clamp(0.0, 1.0));
+                let n = self.nearest(p);
+                out.push(n);
+
+                let chosen = to_unit(self.colors[n]);
+                let err = [p[0] - chosen[0], p[1] - chosen[1], p[2] - chosen[2]];
+                let mut spread = |x: usize, y: usize, frac: f32| {
+                    if x < width && y < height {
+                        let q = &mut buff[y * width + x];
+                        for (c, e) in q.iter_mut().zip(err.iter()) {
+                            *c += e * frac;
+                        }
+                    }
+                };
+                spread(x + 1, y, 7.0 / 16.0);
+                if x > 0 {
+                    spread(x - 1, y + 1, 3.0 / 16.0);
+                }
+                spread(x, y + 1, 5.0 / 16.0);
+                spread(x + 1, y + 1, 1.0 / 16.0);
+            }
+        }
+
+        out
+    }
+}
+
+/**
+Whether (and how) to dither when reducing an image to a `Palette`.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Every pixel just gets the nearest color.
+    #[default]
+    None,
+    /// Diffuse each pixel's error to its neighbors to the right and below.
+    FloydSteinberg,
+}
+
+const ANSI_COLORS: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0xcd, 0x00, 0x00],
+    [0x00, 0xcd, 0x00],
+    [0xcd, 0xcd, 0x00],
+    [0x00, 0x00, 0xee],
+    [0xcd, 0x00, 0xcd],
+    [0x00, 0xcd, 0xcd],
+    [0xe5, 0xe5, 0xe5],
+    [0x7f, 0x7f, 0x7f],
+    [0xff, 0x00, 0x00],
+    [0x00, 0xff, 0x00],
+    [0xff, 0xff, 0x00],
+    [0x5c, 0x5c, 0xff],
+    [0xff, 0x00, 0xff],
+    [0x00, 0xff, 0xff],
+    [0xff, 0xff, 0xff],
+];
+
+const MIRC_COLORS: [[u8; 3]; 16] = [
+    [0xff, 0xff, 0xff],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x7f],
+    [0x00, 0x93, 0x00],
+    [0xff, 0x00, 0x00],
+    [0x7f, 0x00, 0x00],
+    [0x9c, 0x00, 0x9c],
+    [0xfc, 0x7f, 0x00],
+    [0xff, 0xff, 0x00],
+    [0x00, 0xfc, 0x00],
+    [0x00, 0x93, 0x93],
+    [0x00, 0xff, 0xff],
+    [0x00, 0x00, 0xfc],
+    [0xff, 0x00, 0xff],
+    [0x7f, 0x7f, 0x7f],
+    [0xd2, 0xd2, 0xd2],
+];
+
+const MINECRAFT_COLORS: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0xaa],
+    [0x00, 0xaa, 0x00],
+    [0x00, 0xaa, 0xaa],
+    [0xaa, 0x00, 0x00],
+    [0xaa, 0x00, 0xaa],
+    [0xff, 0xaa, 0x00],
+    [0xaa, 0xaa, 0xaa],
+    [0x55, 0x55, 0x55],
+    [0x55, 0x55, 0xff],
+    [0x55, 0xff, 0x55],
+    [0x55, 0xff, 0xff],
+    [0xff, 0x55, 0x55],
+    [0xff, 0x55, 0xff],
+    [0xff, 0xff, 0x55],
+    [0xff, 0xff, 0xff],
+];
+
+/**
+Where colored text is going, which determines how colors are encoded.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorTarget {
+    /// The 8 basic ANSI terminal colors (SGR 30–37).
+    Ansi8,
+    /// The 16 ANSI terminal colors (SGR 30–37 and 90–97).
+    Ansi16,
+    /// 24-bit ANSI terminal colors (SGR 38;2).
+    AnsiTrueColor,
+    /// The 16 standard mIRC colors (^C codes).
+    Mirc,
+    /// BBCode `[color=#rrggbb]` tags. (The text never uses square
+    /// brackets, so it can't form tags.)
+    BBCode,
+    /// The 16 Minecraft chat colors (§ codes).
+    Minecraft,
+}
+
+impl ColorTarget {
+    /**
+    Return the target's own palette: its fixed set of colors (as commonly
+    displayed), or, for targets that can display any color, `None`.
+    */
+    pub fn palette(&self) -> Option<Palette> {
+        let colors: &[[u8; 3]] = match self {
+            ColorTarget::Ansi8 => &ANSI_COLORS[..8],
+            ColorTarget::Ansi16 => &ANSI_COLORS,
+            ColorTarget::Mirc => &MIRC_COLORS,
+            ColorTarget::Minecraft => &MINECRAFT_COLORS,
+            ColorTarget::AnsiTrueColor | ColorTarget::BBCode => return None,
+        };
+        Some(Palette::new(colors).unwrap())
+    }
+
+    /*
+    The number of colors the target can select by index, if it's limited
+    to a fixed set.
+    */
+    fn max_colors(&self) -> Option<usize> {
+        self.palette().map(|p| p.len())
+    }
+
+    /// Whether the target can set the background color of each character
+    /// as well (see `write_two_color()`).
+    pub fn has_background(&self) -> bool {
+        matches!(
+            self,
+            ColorTarget::Ansi8
+                | ColorTarget::Ansi16
+                | ColorTarget::AnsiTrueColor
+                | ColorTarget::Mirc
+        )
+    }
+
+    /*
+    Write the code to switch to the foreground color `fg` and background
+    color `bg` (each a palette index and the color itself). This is only
+    meaningful for targets that `.has_background()`.
+    */
+    pub(crate) fn start_pair<W: Write>(
+        &self,
+        w: &mut W,
+        fg: (usize, [u8; 3]),
+        bg: (usize, [u8; 3]),
+    ) -> std::io::Result<()> {
+        let ansi = |n: usize, base: usize| if n < 8 { base + n } else { base + 60 + n - 8 };
+        match self {
+            ColorTarget::AnsiTrueColor => {
+                let ([r, g, b], [br, bg, bb]) = (fg.1, bg.1);
+                write!(w, "\x1b[38;2;{};{};{};48;2;{};{};{}m", r, g, b, br, bg, bb)
+            }
+            ColorTarget::Mirc => write!(w, "\x03{:02},{:02}", fg.0, bg.0),
+            _ => write!(w, "\x1b[{};{}m", ansi(fg.0, 30), ansi(bg.0, 40)),
+        }
+    }
+
+    /*
+    Write the code to switch to the `n`th color (which is `rgb`).
+    */
+    fn start<W: Write>(&self, w: &mut W, n: usize, rgb: [u8; 3]) -> std::io::Result<()> {
+        let [r, g, b] = rgb;
+        match self {
+            ColorTarget::Ansi8 | ColorTarget::Ansi16 if n < 8 => write!(w, "\x1b[{}m", 30 + n),
+            ColorTarget::Ansi8 | ColorTarget::Ansi16 => write!(w, "\x1b[{}m", 90 + n - 8),
+            ColorTarget::AnsiTrueColor => write!(w, "\x1b[38;2;{};{};{}m", r, g, b),
+            // Always two digits, so a digit in the text can't be mistaken
+            // for part of the code.
+            ColorTarget::Mirc => write!(w, "\x03{:02}", n),
+            ColorTarget::BBCode => write!(w, "[color=#{:02x}{:02x}{:02x}]", r, g, b),
+            ColorTarget::Minecraft => write!(w, "\u{a7}{:x}", n),
+        }
+    }
+
+    /*
+    Write whatever ends a run of colored text (at the end of a line, or
+    before switching to another color).
+    */
+    pub(crate) fn end<W: Write>(&self, w: &mut W, end_of_line: bool) -> std::io::Result<()> {
+        match self {
+            ColorTarget::BBCode => write!(w, "[/color]"),
+            _ if !end_of_line => Ok(()),
+            ColorTarget::Ansi8 | ColorTarget::Ansi16 | ColorTarget::AnsiTrueColor => {
+                write!(w, "\x1b[0m")
+            }
+            ColorTarget::Mirc => write!(w, "\x0f"),
+            ColorTarget::Minecraft => write!(w, "\u{a7}r"),
+        }
+    }
+}
+
+/**
+How `write_color()` should color its output.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorOptions {
+    /// Which codes to use to change colors.
+    pub target: ColorTarget,
+    /// The colors to reduce the image to. If this is `None`, the target's
+    /// own palette is used (or, for targets that can display any color,
+    /// the image's colors are used as they are).
+    pub palette: Option<Palette>,
+    /// Whether to dither when reducing the image to the palette.
+    pub dither: Dither,
+}
+
+impl ColorOptions {
+    /// Output for `target` in its own palette, without dithering.
+    pub fn new(target: ColorTarget) -> ColorOptions {
+        ColorOptions {
+            target,
+            palette: None,
+            dither: Dither::None,
+        }
+    }
+
+    /*
+    Return the palette to actually reduce colors to, checking that the
+    target can display all of its colors.
+    */
+    pub(crate) fn resolve_palette(&self) -> Result<Option<Palette>, Error> {
+        let palette = match &self.palette {
+            Some(p) => Some(p.clone()),
+            None => self.target.palette(),
+        };
+        if let (Some(p), Some(max)) = (&palette, self.target.max_colors()) {
+            if p.len() > max {
+                return Err(Error::InvalidPalette(format!(
+                    "{:?} only has {} colors, but palette has {}",
+                    self.target,
+                    max,
+                    p.len()
+                )));
+            }
+        }
+        Ok(palette)
+    }
+}
+
+/*
+Sample the color at the center of each cell of a `w` by `h` grid over
+`rgb`, the same way `resize_nearest()` samples intensities.
+*/
+fn sample_colors(rgb: &RgbImage, w: u32, h: u32) -> Vec<[f32; 3]> {
+    let (src_w, src_h) = rgb.dimensions();
+    if src_w == 0 || src_h == 0 {
+        return vec![[0.0; 3]; (w as usize) * (h as usize)];
+    }
+
+    let xs = crate::nearest_indices(src_w, w);
+    let ys = crate::nearest_indices(src_h, h);
+    let mut out = Vec::with_capacity(xs.len() * ys.len());
+    for y in ys.iter() {
+        for x in xs.iter() {
+            out.push(to_unit(rgb.get_pixel(*x as u32, *y as u32).0));
+        }
+    }
+    out
+}
+
+/**
+Given some `FontData`, write the `ColorImage` as colored text to the
+`writer`.
+
+Characters are chosen just as `write_with()` chooses them with the same
+`opts` (except that, for `ColorTarget::BBCode`, `[` and `]` are left out);
+the color of each character is the color of the corresponding part of the
+image, reduced to the palette specified by `color`.
+
+```
+use ascii_art::{ColorImage, ColorOptions, ColorTarget, Dither};
+# let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+# let font = ascii_art::FontData::from_font_bytes(
+#     &bytes, 12.0, &ascii_art::printable_ascii()
+# ).unwrap().unwrap();
+
+let f = std::fs::File::open("test/griffin_sm.jpg").unwrap();
+let img = ColorImage::auto(std::io::BufReader::new(f)).unwrap();
+
+let mut color = ColorOptions::new(ColorTarget::Mirc);
+color.dither = Dither::FloydSteinberg;
+
+let mut out: Vec<u8> = Vec::new();
+ascii_art::write_color(&img, &font, &Default::default(), &color, &mut out).unwrap();
+```
+*/
+pub fn write_color<W: Write>(
+    img: &ColorImage,
+    font: &FontData,
+    opts: &RenderOptions,
+    color: &ColorOptions,
+    writer: W,
+) -> Result<(), Error> {
+    let target = color.target;
+    let palette = color.resolve_palette()?;
+
+    // Runs of brackets and letters could spell out tags like `[b]` or
+    // `[/color]`, and forums escape HTML before reading BBCode (so an
+    // entity like `&#91;` would show as it is), so brackets are left out
+    // altogether.
+    let bbcode_font;
+    let font = match target {
+        ColorTarget::BBCode => {
+            bbcode_font = font.without(&['[', ']']);
+            &bbcode_font
+        }
+        _ => font,
+    };
+
+    let rows = crate::render_rows_with(img.image(), font, opts);
+    let w = rows.first().map(|r| r.chars().count()).unwrap_or(0);
+    let h = rows.len();
+    let samples = sample_colors(&img.rgb, w as u32, h as u32);
+
+    // The palette index (if any) and color of each cell.
+    let cells: Vec<(usize, [u8; 3])> = match &palette {
+        Some(p) => p
+            .quantize(&samples, w, color.dither)
+            .into_iter()
+            .map(|n| (n, p.colors()[n]))
+            .collect(),
+        None => samples
+            .iter()
+            .map(|c| (0, c.map(|v| (v * 255.0).round() as u8)))
+            .collect(),
+    };
+
+    let mut writer = BufWriter::new(writer);
+    for (row, cells) in rows.iter().zip(cells.chunks(w.max(1))) {
+        write_colored_row(&mut writer, target, row, cells).map_err(io_error)?;
+    }
+    writer.flush().map_err(io_error)
+}
+
+fn write_colored_row<W: Write>(
+    w: &mut W,
+    target: ColorTarget,
+    row: &str,
+    cells: &[(usize, [u8; 3])],
+) -> std::io::Result<()> {
+    let mut current: Option<[u8; 3]> = None;
+
+    for (chr, (n, rgb)) in row.chars().zip(cells.iter()) {
+        // Spaces look the same in any color, so don't bother changing.
+        if chr != ' ' && current != Some(*rgb) {
+            if current.is_some() {
+                target.end(w, false)?;
+            }
+            target.start(w, *n, *rgb)?;
+            current = Some(*rgb);
+            // mIRC would read a comma and digit right after a color code
+            // as a background color; a pair of bold toggles separates them.
+            if target == ColorTarget::Mirc && chr == ',' {
+                write!(w, "\x02\x02")?;
+            }
+        }
+        write!(w, "{}", chr)?;
+    }
+
+    if current.is_some() {
+        target.end(w, true)?;
+    }
+    writeln!(w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lab() {
+        let close =
+            |a: [f32; 3], b: [f32; 3]| a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 0.05);
+
+        assert!(close(to_lab([0.0; 3]), [0.0, 0.0, 0.0]));
+        assert!(close(to_lab([1.0; 3]), [100.0, 0.0, 0.0]));
+        assert!(close(to_lab([1.0, 0.0, 0.0]), [53.24, 80.09, 67.20]));
+        assert!(close(to_lab([0.0, 0.0, 1.0]), [32.30, 79.19, -107.86]));
+    }
+
+    #[test]
+    fn palette() {
+        assert!(Palette::new(&[]).is_err());
+
+        let p = ColorTarget::Ansi16.palette().unwrap();
+        for (n, c) in p.colors().iter().enumerate() {
+            assert_eq!(p.nearest(to_unit(*c)), n);
+        }
+        // A dark orange is closer to (ANSI) yellow than to red or white.
+        assert_eq!(p.nearest([0.8, 0.6, 0.1]), 3);
+
+        // A flat 25% gray dithered between black and white should come
+        // out about one quarter white.
+        let bw = Palette::new(&[[0, 0, 0], [255, 255, 255]]).unwrap();
+        let pixels = vec![[0.25f32; 3]; 64 * 64];
+        assert!(bw
+            .quantize(&pixels, 64, Dither::None)
+            .iter()
+            .all(|n| *n == 0));
+        let dithered = bw.quantize(&pixels, 64, Dither::FloydSteinberg);
+        let white = dithered.iter().filter(|n| **n == 1).count() as f32 / dithered.len() as f32;
+        assert!((white - 0.25).abs() < 0.02, "{} white", white);
+    }
+
+    #[test]
+    fn targets() {
+        let cells = [
+            (1, [0xcd, 0, 0]),
+            (1, [0xcd, 0, 0]),
+            (0, [0, 0, 0]),
+            (10, [0, 0xff, 0]),
+        ];
+        let row = |target: ColorTarget, text: &str| {
+            let mut out: Vec<u8> = Vec::new();
+            write_colored_row(&mut out, target, text, &cells).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            row(ColorTarget::Ansi16, "ab c"),
+            "\x1b[31mab \x1b[92mc\x1b[0m\n"
+        );
+        assert_eq!(
+            row(ColorTarget::Ansi16, "abcd"),
+            "\x1b[31mab\x1b[30mc\x1b[92md\x1b[0m\n"
+        );
+        assert_eq!(
+            row(ColorTarget::AnsiTrueColor, "a"),
+            "\x1b[38;2;205;0;0ma\x1b[0m\n"
+        );
+        assert_eq!(
+            row(ColorTarget::Mirc, "ab,d"),
+            "\x0301ab\x0300\x02\x02,\x0310d\x0f\n"
+        );
+        assert_eq!(
+            row(ColorTarget::BBCode, "ab d"),
+            "[color=#cd0000]ab [/color][color=#00ff00]d[/color]\n"
+        );
+        assert_eq!(
+            row(ColorTarget::Minecraft, "abcd"),
+            "\u{a7}1ab\u{a7}0c\u{a7}ad\u{a7}r\n"
+        );
+        assert_eq!(row(ColorTarget::Minecraft, "    "), "    \n");
+    }
+
+    #[test]
+    fn write() {
+        let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+        let font = FontData::from_font_bytes(&bytes, 12.0, &crate::printable_ascii())
+            .unwrap()
+            .unwrap();
+        let f = std::fs::File::open("test/griffin_sm.jpg").unwrap();
+        let img = ColorImage::auto(std::io::BufReader::new(f)).unwrap();
+        let opts = RenderOptions::default();
+
+        let mut plain: Vec<u8> = Vec::new();
+        crate::write(img.image(), &font, &mut plain).unwrap();
+        let plain = String::from_utf8(plain).unwrap();
+
+        for target in [
+            ColorTarget::Ansi8,
+            ColorTarget::BBCode,
+            ColorTarget::Minecraft,
+        ] {
+            let mut out: Vec<u8> = Vec::new();
+            write_color(&img, &font, &opts, &ColorOptions::new(target), &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert_eq!(out.lines().count(), plain.lines().count());
+        }
+
+        // Stripping the codes out leaves the monochrome text.
+        let mut out: Vec<u8> = Vec::new();
+        write_color(
+            &img,
+            &font,
+            &opts,
+            &ColorOptions::new(ColorTarget::Minecraft),
+            &mut out,
+        )
+        .unwrap();
+        let mut stripped = String::new();
+        let mut chars = String::from_utf8(out)
+            .unwrap()
+            .chars()
+            .collect::<Vec<_>>()
+            .into_iter();
+        while let Some(c) = chars.next() {
+            if c == '\u{a7}' {
+                chars.next();
+            } else {
+                stripped.push(c);
+            }
+        }
+        assert_eq!(stripped, plain);
+
+        // BBCode never uses brackets (so every bracket is part of a tag),
+        // and the rest is the nearest the font has without them. (A
+        // gradient uses every character the font can choose.)
+        let gradient = RgbImage::from_fn(1024, 16, |x, _| image::Rgb([(x / 4) as u8; 3]));
+        let img = ColorImage::from_decoded(&DynamicImage::ImageRgb8(gradient));
+        let mut plain: Vec<u8> = Vec::new();
+        crate::write(img.image(), &font, &mut plain).unwrap();
+        let plain = String::from_utf8(plain).unwrap();
+        assert!(plain.contains(']'));
+        let mut out: Vec<u8> = Vec::new();
+        write_color(
+            &img,
+            &font,
+            &opts,
+            &ColorOptions::new(ColorTarget::BBCode),
+            &mut out,
+        )
+        .unwrap();
+        let mut stripped = String::new();
+        for (n, piece) in String::from_utf8(out).unwrap().split('[').enumerate() {
+            if n == 0 {
+                stripped.push_str(piece);
+            } else {
+                let (tag, text) = piece.split_once(']').unwrap();
+                assert!(tag == "/color" || tag.starts_with("color=#"), "{}", tag);
+                stripped.push_str(text);
+            }
+        }
+        assert!(!stripped.contains(']'));
+        let without = font.without(&['[', ']']);
+        assert_eq!(without.ramp().len(), font.ramp().len() - 2);
+        let mut expected: Vec<u8> = Vec::new();
+        crate::write(img.image(), &without, &mut expected).unwrap();
+        assert_eq!(stripped, String::from_utf8(expected).unwrap());
+
+        let mut too_many = ColorOptions::new(ColorTarget::Ansi8);
+        too_many.palette = ColorTarget::Ansi16.palette();
+        assert!(matches!(
+            write_color(&img, &font, &opts, &too_many, std::io::sink()),
+            Err(Error::InvalidPalette(_))
+        ));
+    }
+}
@@ -0,0 +1,415 @@
+/*!
+Writing rendered text as documents and images: an HTML page, an SVG
+drawing, a PNG image of the text as the font draws it, or a JSON grid.
+
+Each of these takes the rows of text (as returned by `render_with()`)
+and the font they were rendered for, and lays the text out in cells the
+size of the font's glyphs, so it keeps the proportions of the image.
+Text is light on a dark background, or, for text rendered with
+`RenderOptions::invert`, dark on a light one.
+*/
+
+use std::io::{BufWriter, Write};
+
+use ab_glyph::{point, Font, ScaleFont};
+use image::GrayImage;
+use serde_derive::Serialize;
+
+use crate::color::io_error;
+use crate::{Error, FontData, FontOptions};
+
+#[derive(Serialize)]
+struct Grid<'a, S: AsRef<str>> {
+    width: usize,
+    height: usize,
+    #[serde(serialize_with = "serialize_rows")]
+    rows: &'a [S],
+}
+
+fn serialize_rows<S: AsRef<str>, T: serde::Serializer>(
+    rows: &&[S],
+    serializer: T,
+) -> Result<T::Ok, T::Error> {
+    serializer.collect_seq(rows.iter().map(|r| r.as_ref()))
+}
+
+/*
+The size of the grid of text, in characters and lines.
+*/
+fn grid_size<S: AsRef<str>>(rows: &[S]) -> (usize, usize) {
+    let width = rows
+        .iter()
+        .map(|r| r.as_ref().chars().count())
+        .max()
+        .unwrap_or(0);
+    (width, rows.len())
+}
+
+/*
+The text and background colors, as CSS colors.
+*/
+fn colors(invert: bool) -> (&'static str, &'static str) {
+    if invert {
+        ("#000", "#fff")
+    } else {
+        ("#fff", "#000")
+    }
+}
+
+/*
+Format a length in pixels with no more precision than it needs.
+*/
+fn px(x: f32) -> String {
+    let s = format!("{:.2}", x);
+    s.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
+
+/*
+Escape `s` for use in XML (or HTML) text, or, if `attr` is set, in a
+(double-quoted) attribute value.
+*/
+fn escape_xml(s: &str, attr: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attr => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/*
+Return a CSS `font-family` value asking for the font's family, falling
+back to any monospaced font.
+*/
+fn font_family(font: &FontData) -> String {
+    match font.family() {
+        Some(family) => {
+            let family = family.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("\"{}\", monospace", family)
+        }
+        None => "monospace".to_owned(),
+    }
+}
+
+/*
+The font's size in pixels (or, if it wasn't recorded, its height).
+*/
+fn font_size(font: &FontData) -> f32 {
+    font.pixel_size().unwrap_or_else(|| font.geometry().1)
+}
+
+/**
+Write `rows` of text rendered for `font` as an HTML page, in a `<pre>`
+element styled with the font's family and size, and a line height of one
+cell.
+
+```
+# let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+# let font = ascii_art::FontData::from_font_bytes(
+#     &bytes, 12.0, &ascii_art::printable_ascii()
+# ).unwrap().unwrap();
+let mut out: Vec<u8> = Vec::new();
+ascii_art::write_html(&["<#>", " & "], &font, false, &mut out).unwrap();
+let html = String::from_utf8(out).unwrap();
+
+assert!(html.contains("<pre>\n&lt;#&gt;\n &amp; \n</pre>"));
+assert!(html.contains("font-family: \"Liberation Mono\", monospace;"));
+```
+*/
+pub fn write_html<S: AsRef<str>, W: Write>(
+    rows: &[S],
+    font: &FontData,
+    invert: bool,
+    writer: W,
+) -> Result<(), Error> {
+    let (fg, bg) = colors(invert);
+    let mut w = BufWriter::new(writer);
+
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<style>\n");
+    html.push_str(&format!("body {{ margin: 0; background: {}; }}\n", bg));
+    html.push_str(&format!(
+        "pre {{ margin: 0; color: {}; font-family: {}; font-size: {}px; line-height: {}px; }}\n",
+        fg,
+        // Nothing in a font name should be able to end the element.
+        font_family(font).replace('<', "\\3c "),
+        px(font_size(font)),
+        px(font.geometry().1)
+    ));
+    html.push_str("</style>\n</head>\n<body>\n");
+    // A newline straight after `<pre>` is ignored.
+    html.push_str("<pre>\n");
+    for row in rows.iter() {
+        html.push_str(&escape_xml(row.as_ref(), false));
+        html.push('\n');
+    }
+    html.push_str("</pre>\n</body>\n</html>\n");
+
+    w.write_all(html.as_bytes()).map_err(io_error)?;
+    w.flush().map_err(io_error)
+}
+
+/**
+Write `rows` of text rendered for `font` as an SVG drawing, one `<tspan>`
+per row, each stretched (by adjusting the spacing between characters) to
+exactly fill its cells, whatever font the viewer ends up using.
+
+```
+# let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+# let font = ascii_art::FontData::from_font_bytes(
+#     &bytes, 12.0, &ascii_art::printable_ascii()
+# ).unwrap().unwrap();
+let mut out: Vec<u8> = Vec::new();
+ascii_art::write_svg(&["ab", "c<"], &font, true, &mut out).unwrap();
+let svg = String::from_utf8(out).unwrap();
+
+assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+assert!(svg.contains(">c&lt;</tspan>"));
+```
+*/
+pub fn write_svg<S: AsRef<str>, W: Write>(
+    rows: &[S],
+    font: &FontData,
+    invert: bool,
+    writer: W,
+) -> Result<(), Error> {
+    let (fg, bg) = colors(invert);
+    let (cell_w, cell_h) = font.geometry();
+    let (cols, lines) = grid_size(rows);
+    let (width, height) = (px(cols as f32 * cell_w), px(lines as f32 * cell_h));
+    // Fonts without recorded metrics get their baseline most of the way
+    // down the cell.
+    let ascent = font.ascent().unwrap_or(0.8 * cell_h);
+    let mut w = BufWriter::new(writer);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+        width, height, width, height
+    );
+    svg.push_str(&format!(
+        "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+        bg
+    ));
+    svg.push_str(&format!(
+        "<text font-family=\"{}\" font-size=\"{}\" fill=\"{}\" xml:space=\"preserve\">\n",
+        escape_xml(&font_family(font), true),
+        px(font_size(font)),
+        fg
+    ));
+    for (n, row) in rows.iter().enumerate() {
+        let row = row.as_ref();
+        let len = row.chars().count();
+        if len == 0 {
+            continue;
+        }
+        let y = px(n as f32 * cell_h + ascent);
+        svg.push_str(&format!(
+            "<tspan x=\"0\" y=\"{}\" textLength=\"{}\" lengthAdjust=\"spacing\">{}</tspan>\n",
+            y,
+            px(len as f32 * cell_w),
+            escape_xml(row, false)
+        ));
+    }
+    svg.push_str("</text>\n</svg>\n");
+
+    w.write_all(svg.as_bytes()).map_err(io_error)?;
+    w.flush().map_err(io_error)
+}
+
+/**
+Write `rows` of text as JSON: an object with the `width` (of the longest
+row, in characters) and `height` (in rows) of the grid, and the `rows`
+themselves.
+
+```
+let mut out: Vec<u8> = Vec::new();
+ascii_art::write_json(&["ab", "cd", "ef"], &mut out).unwrap();
+assert_eq!(
+    String::from_utf8(out).unwrap(),
+    "{\"width\":2,\"height\":3,\"rows\":[\"ab\",\"cd\",\"ef\"]}\n"
+);
+```
+*/
+pub fn write_json<S: AsRef<str>, W: Write>(rows: &[S], writer: W) -> Result<(), Error> {
+    let (width, height) = grid_size(rows);
+    let grid = Grid {
+        width,
+        height,
+        rows,
+    };
+    let mut w = BufWriter::new(writer);
+    serde_json::to_writer(&mut w, &grid).map_err(io_error)?;
+    writeln!(w).map_err(io_error)?;
+    w.flush().map_err(io_error)
+}
+
+/**
+Draw `rows` of text rendered for `font` as an image, using the glyphs of
+the font in `bytes` (the font `font` was made from, loaded as specified
+by `opts`) at the size `font` was analyzed at. Each character is drawn
+in a cell the size of `font`'s glyphs.
+
+```
+let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+let font = ascii_art::FontData::from_font_bytes(
+    &bytes, 12.0, &ascii_art::printable_ascii()
+).unwrap().unwrap();
+
+let img = ascii_art::rasterize(&["@@", "  "], &bytes, &Default::default(), &font, false).unwrap();
+let (w, h) = font.geometry();
+assert_eq!(img.dimensions(), ((2.0 * w).ceil() as u32, (2.0 * h).ceil() as u32));
+// Light text on black: the top row has some ink, and the bottom row none.
+let ink = |y0: u32, y1: u32| (y0..y1).flat_map(|y| (0..img.width()).map(move |x| (x, y)))
+    .map(|(x, y)| img.get_pixel(x, y).0[0] as u32)
+    .sum::<u32>();
+assert!(ink(0, h as u32) > 0);
+assert_eq!(ink(h.ceil() as u32, img.height()), 0);
+```
+*/
+pub fn rasterize<S: AsRef<str>>(
+    rows: &[S],
+    bytes: &[u8],
+    opts: &FontOptions,
+    font: &FontData,
+    invert: bool,
+) -> Result<GrayImage, Error> {
+    let (glyphs, _) = opts.load(bytes)?;
+    let scaled = glyphs.as_scaled(font_size(font));
+    let (cell_w, cell_h) = font.geometry();
+    let (cols, lines) = grid_size(rows);
+    let width = (cols as f32 * cell_w).ceil() as u32;
+    let height = (lines as f32 * cell_h).ceil() as u32;
+
+    // Coverage is accumulated, then turned into ink at the end.
+    let mut cov = vec![0.0f32; (width as usize) * (height as usize)];
+    for (row_n, row) in rows.iter().enumerate() {
+        let baseline = row_n as f32 * cell_h + scaled.ascent();
+        for (col_n, c) in row.as_ref().chars().enumerate() {
+            let mut glyph = scaled.scaled_glyph(c);
+            glyph.position = point(col_n as f32 * cell_w, baseline);
+            let outline = match glyphs.outline_glyph(glyph) {
+                Some(outline) => outline,
+                None => continue,
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|x, y, c| {
+                let x = bounds.min.x as i64 + x as i64;
+                let y = bounds.min.y as i64 + y as i64;
+                if (0..width as i64).contains(&x) && (0..height as i64).contains(&y) {
+                    let p = &mut cov[(y as usize) * (width as usize) + x as usize];
+                    *p = (*p + c).min(1.0);
+                }
+            });
+        }
+    }
+
+    let pixels: Vec<u8> = cov
+        .into_iter()
+        .map(|c| {
+            let v = (c * 255.0).round() as u8;
+            if invert {
+                255 - v
+            } else {
+                v
+            }
+        })
+        .collect();
+    Ok(GrayImage::from_raw(width, height, pixels).unwrap())
+}
+
+/**
+Draw `rows` of text as `rasterize()` does, and write the image to
+`writer` as a PNG.
+*/
+#[cfg(feature = "png")]
+pub fn write_png<S: AsRef<str>, W: Write>(
+    rows: &[S],
+    bytes: &[u8],
+    opts: &FontOptions,
+    font: &FontData,
+    invert: bool,
+    writer: W,
+) -> Result<(), Error> {
+    use image::codecs::png::PngEncoder;
+    use image::{ColorType, ImageEncoder};
+
+    let img = rasterize(rows, bytes, opts, font, invert)?;
+    let mut w = BufWriter::new(writer);
+    PngEncoder::new(&mut w)
+        .write_image(img.as_raw(), img.width(), img.height(), ColorType::L8)
+        .map_err(io_error)?;
+    w.flush().map_err(io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> (Vec<u8>, FontData) {
+        let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+        let font = FontData::from_font_bytes(&bytes, 12.0, &crate::printable_ascii())
+            .unwrap()
+            .unwrap();
+        (bytes, font)
+    }
+
+    #[test]
+    fn svg_layout() {
+        let (_, font) = font();
+        let (cell_w, cell_h) = font.geometry();
+        let mut out: Vec<u8> = Vec::new();
+        write_svg(&["a\"b", "", "cd"], &font, false, &mut out).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+
+        let size = format!(
+            "width=\"{}\" height=\"{}\"",
+            px(3.0 * cell_w),
+            px(3.0 * cell_h)
+        );
+        assert!(svg.contains(&size));
+        assert!(svg.contains("font-family=\"&quot;Liberation Mono&quot;, monospace\""));
+        assert!(svg.contains("fill=\"#000\"/>"));
+        // The empty row gets no `<tspan>`, but still takes up its line.
+        assert_eq!(svg.matches("<tspan").count(), 2);
+        let y = px(2.0 * cell_h + font.ascent().unwrap());
+        assert!(svg.contains(&format!("y=\"{}\"", y)));
+        assert!(svg.contains(">a\"b</tspan>"));
+    }
+
+    #[test]
+    fn rasterize_inverted() {
+        let (bytes, font) = font();
+        let opts = FontOptions::default();
+        let light = rasterize(&["#", " "], &bytes, &opts, &font, false).unwrap();
+        let dark = rasterize(&["#", " "], &bytes, &opts, &font, true).unwrap();
+
+        assert_eq!(light.dimensions(), dark.dimensions());
+        for (l, d) in light.pixels().zip(dark.pixels()) {
+            assert_eq!(l.0[0], 255 - d.0[0]);
+        }
+        assert!(light.pixels().any(|p| p.0[0] > 128));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_round_trip() {
+        let (bytes, font) = font();
+        let opts = FontOptions::default();
+        let rows = ["@#", "%."];
+        let mut out: Vec<u8> = Vec::new();
+        write_png(&rows, &bytes, &opts, &font, false, &mut out).unwrap();
+
+        let decoded = image::load_from_memory_with_format(&out, image::ImageFormat::Png)
+            .unwrap()
+            .to_luma8();
+        assert_eq!(
+            decoded,
+            rasterize(&rows, &bytes, &opts, &font, false).unwrap()
+        );
+    }
+}
@@ -0,0 +1,306 @@
+/*!
+Choosing which face (and which instance of a variable face) of a font
+file gets analyzed.
+*/
+
+use ab_glyph::{FontRef, VariableFont, VariationAxis};
+
+use crate::Error;
+
+// A position in a variable font's design space: a value for each axis.
+type Coords = Vec<([u8; 4], f32)>;
+
+/**
+Options for choosing which font in a font file to analyze (and how) with
+`FontData::from_font_bytes_with()`.
+
+The defaults (what `FontData::from_font_bytes()` uses) select the first
+face in the file, and, if it's a variable font, its default instance.
+
+```
+use ascii_art::{FontData, FontOptions, printable_ascii};
+
+let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+let opts = FontOptions {
+    face_index: 0,
+    // Liberation Mono isn't a variable font, so it doesn't have any
+    // named instances or variation axes.
+    instance: None,
+    variations: Vec::new(),
+    subpixel: false,
+};
+let font = FontData::from_font_bytes_with(&bytes, 12.0, &printable_ascii(), &opts)
+    .unwrap()
+    .unwrap();
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FontOptions {
+    /// Which face of a font collection (.ttc/.otc file) to use; for
+    /// files with only one font in them, this must be 0.
+    pub face_index: u32,
+    /// The name of a named instance of a variable font to use (like
+    /// "Light" or "Bold Condensed"), as listed by
+    /// `FontOptions::named_instances()`.
+    pub instance: Option<String>,
+    /// Values for the given variation axes (like `(*b"wght", 300.0)`).
+    /// These are applied after (and so override) `instance`.
+    pub variations: Vec<([u8; 4], f32)>,
+    /// Also measure the coverage of the left, middle, and right thirds
+    /// of each glyph, for use with `RenderOptions::subpixel`.
+    pub subpixel: bool,
+}
+
+impl FontOptions {
+    /**
+    Return the number of faces in the given font file (which is 1, unless
+    it's a font collection).
+    */
+    pub fn face_count(bytes: &[u8]) -> u32 {
+        ttf_parser::fonts_in_collection(bytes).unwrap_or(1)
+    }
+
+    /**
+    Return the variation axes of the given face of the given font file.
+    This will be empty if it isn't a variable font.
+    */
+    pub fn axes(bytes: &[u8], face_index: u32) -> Result<Vec<VariationAxis>, Error> {
+        Ok(parse(bytes, face_index)?.variations())
+    }
+
+    /**
+    Return the names of the named instances of the given face of the given
+    font file. This will be empty if it isn't a variable font.
+    */
+    pub fn named_instances(bytes: &[u8], face_index: u32) -> Result<Vec<String>, Error> {
+        Ok(instances(bytes, face_index)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    /*
+    Return the font selected by the receiver, along with the font's own
+    name for the selected instance (if one was selected).
+    */
+    pub(crate) fn load<'a>(&self, bytes: &'a [u8]) -> Result<(FontRef<'a>, Option<String>), Error> {
+        let mut font = parse(bytes, self.face_index)?;
+        let mut instance_name: Option<String> = None;
+
+        if let Some(name) = &self.instance {
+            let (found, coords) = instances(bytes, self.face_index)?
+                .into_iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    Error::InvalidFontOption(format!("font has no instance named \"{}\"", name))
+                })?;
+            for (tag, val) in coords.iter() {
+                font.set_variation(tag, *val);
+            }
+            instance_name = Some(found);
+        }
+
+        for (tag, val) in self.variations.iter() {
+            if !font.set_variation(tag, *val) {
+                return Err(Error::InvalidFontOption(format!(
+                    "font has no \"{}\" variation axis",
+                    String::from_utf8_lossy(tag)
+                )));
+            }
+        }
+
+        Ok((font, instance_name))
+    }
+}
+
+fn parse(bytes: &[u8], face_index: u32) -> Result<FontRef<'_>, Error> {
+    let count = FontOptions::face_count(bytes);
+    if face_index >= count {
+        return Err(Error::InvalidFontOption(format!(
+            "face index {} requested, but font file only has {} face(s)",
+            face_index, count
+        )));
+    }
+    FontRef::try_from_slice_and_index(bytes, face_index).map_err(|_| Error::InvalidFontData)
+}
+
+/*
+Return the name and axis coordinates of each named instance in the face.
+*/
+fn instances(bytes: &[u8], face_index: u32) -> Result<Vec<(String, Coords)>, Error> {
+    let face = ttf_parser::Face::parse(bytes, face_index).map_err(|_| Error::InvalidFontData)?;
+    let fvar = match face.raw_face().table(ttf_parser::Tag::from_bytes(b"fvar")) {
+        Some(fvar) => fvar,
+        None => return Ok(Vec::new()),
+    };
+
+    let name = |id: u16| {
+        face.names()
+            .into_iter()
+            .filter(|n| n.name_id == id)
+            .find_map(|n| n.to_string())
+    };
+
+    Ok(parse_fvar_instances(fvar)
+        .into_iter()
+        .filter_map(|(id, coords)| name(id).map(|n| (n, coords)))
+        .collect())
+}
+
+/*
+Pull the (subfamily name ID, axis coordinates) of each named instance out
+of an `fvar` table. `ttf_parser` reads the axes, but not the instances.
+
+Anything malformed just gets skipped.
+*/
+fn parse_fvar_instances(fvar: &[u8]) -> Vec<(u16, Coords)> {
+    let u16_at = |n: usize| fvar.get(n..n + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let fixed_at = |n: usize| {
+        fvar.get(n..n + 4)
+            .map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f32 / 65536.0)
+    };
+
+    let header = (u16_at(4), u16_at(8), u16_at(10), u16_at(12), u16_at(14));
+    let (axes_offset, axis_count, axis_size, instance_count, instance_size) = match header {
+        (Some(a), Some(b), Some(c), Some(d), Some(e)) => {
+            (a as usize, b as usize, c as usize, d as usize, e as usize)
+        }
+        _ => return Vec::new(),
+    };
+
+    let mut tags: Vec<[u8; 4]> = Vec::with_capacity(axis_count);
+    for n in 0..axis_count {
+        match fvar
+            .get(axes_offset + n * axis_size..)
+            .and_then(|b| b.get(..4))
+        {
+            Some(tag) => tags.push([tag[0], tag[1], tag[2], tag[3]]),
+            None => return Vec::new(),
+        }
+    }
+
+    let instances_offset = axes_offset + axis_count * axis_size;
+    let mut instances = Vec::with_capacity(instance_count);
+    for n in 0..instance_count {
+        let start = instances_offset + n * instance_size;
+        let name_id = match u16_at(start) {
+            Some(id) => id,
+            None => break,
+        };
+        let coords: Option<Coords> = tags
+            .iter()
+            .enumerate()
+            .map(|(a, tag)| fixed_at(start + 4 + 4 * a).map(|v| (*tag, v)))
+            .collect();
+        match coords {
+            Some(coords) => instances.push((name_id, coords)),
+            None => break,
+        }
+    }
+
+    instances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fvar() {
+        // Two axes (wght, wdth) and two instances (name IDs 256 and 257).
+        let mut t: Vec<u8> = Vec::new();
+        t.extend_from_slice(&[0, 1, 0, 0, 0, 16, 0, 2]);
+        t.extend_from_slice(&[0, 2, 0, 20, 0, 2, 0, 12]);
+        for tag in [b"wght", b"wdth"] {
+            t.extend_from_slice(tag);
+            t.extend_from_slice(&[0u8; 16]);
+        }
+        for (id, wght, wdth) in [(256u16, 300i32, 100i32), (257, 800, 75)] {
+            t.extend_from_slice(&id.to_be_bytes());
+            t.extend_from_slice(&[0, 0]);
+            t.extend_from_slice(&(wght << 16).to_be_bytes());
+            t.extend_from_slice(&(wdth << 16).to_be_bytes());
+        }
+
+        let instances = parse_fvar_instances(&t);
+        assert_eq!(
+            instances,
+            vec![
+                (256, vec![(*b"wght", 300.0), (*b"wdth", 100.0)]),
+                (257, vec![(*b"wght", 800.0), (*b"wdth", 75.0)]),
+            ]
+        );
+
+        // Truncated tables don't panic, and give up at the damage.
+        assert_eq!(parse_fvar_instances(&t[..t.len() - 1]).len(), 1);
+        assert!(parse_fvar_instances(&t[..10]).is_empty());
+    }
+
+    #[test]
+    fn instances_and_variations() {
+        use crate::FontData;
+
+        // A tiny variable font with a space, an `I`, and a `#`, and a
+        // `wght` axis (100 to 900) that only widens the `I`: 1/4 as wide
+        // as the `#` at its default, Light (100), and 7/8 at Bold (900).
+        // test/make_variable_test_font.py generates it.
+        let bytes = std::fs::read("test/VariableTest.ttf").unwrap();
+        let chars = [' ', 'I', '#'];
+        let coverage = |opts: &FontOptions| -> f32 {
+            let font = FontData::from_font_bytes_with(&bytes, 40.0, &chars, opts)
+                .unwrap()
+                .unwrap();
+            font.ramp().iter().find(|(c, _)| *c == 'I').unwrap().1
+        };
+        let with = |instance: Option<&str>, variations: Vec<([u8; 4], f32)>| FontOptions {
+            instance: instance.map(String::from),
+            variations,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            FontOptions::named_instances(&bytes, 0).unwrap(),
+            vec!["Light", "Bold"]
+        );
+        let axes = FontOptions::axes(&bytes, 0).unwrap();
+        assert_eq!(axes.len(), 1);
+        assert_eq!(&axes[0].tag, b"wght");
+
+        let default = coverage(&FontOptions::default());
+        let light = coverage(&with(Some("light"), Vec::new()));
+        let bold = coverage(&with(Some("Bold"), Vec::new()));
+        assert!((default - 0.25).abs() < 0.02, "default: {}", default);
+        assert_eq!(light, default);
+        assert!((bold - 0.875).abs() < 0.02, "bold: {}", bold);
+
+        // Variations override the instance.
+        assert_eq!(coverage(&with(None, vec![(*b"wght", 900.0)])), bold);
+        assert_eq!(
+            coverage(&with(Some("Bold"), vec![(*b"wght", 100.0)])),
+            default
+        );
+        let medium = coverage(&with(None, vec![(*b"wght", 500.0)]));
+        assert!(default < medium && medium < bold, "medium: {}", medium);
+
+        let font =
+            FontData::from_font_bytes_with(&bytes, 40.0, &chars, &with(Some("Bold"), Vec::new()))
+                .unwrap()
+                .unwrap();
+        assert_eq!(font.style(), Some("Bold"));
+
+        assert!(FontData::from_font_bytes_with(
+            &bytes,
+            40.0,
+            &chars,
+            &with(Some("Black"), Vec::new())
+        )
+        .is_err());
+        assert!(FontData::from_font_bytes_with(
+            &bytes,
+            40.0,
+            &chars,
+            &with(None, vec![(*b"wdth", 75.0)])
+        )
+        .is_err());
+    }
+}
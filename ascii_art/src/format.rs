@@ -0,0 +1,366 @@
+/*!
+The versioned on-disk format for `FontData` (and collections of it).
+
+Everything gets wrapped in the same container:
+
+```text
+offset  size  contents
+     0     4  magic number identifying what's inside (e.g. b"AAFD")
+     4     2  format version (little-endian u16)
+     6     1  payload encoding (0 = binary, 1 = JSON)
+     7     1  reserved; always 0
+     8     4  payload length in bytes (little-endian u32)
+    12     n  payload
+  12+n     4  CRC-32 of everything before it (little-endian u32)
+```
+
+The JSON encoding of a payload is just what `serde_json` makes of it.
+
+The binary encoding is a sequence of fields, each of which is a one-byte
+tag, a little-endian `u32` length, and then that many bytes of data.
+Readers skip fields with tags they don't recognize, so new fields can be
+added without bumping the format version; the version only needs to
+change when existing fields change meaning, and readers refuse versions
+newer than they understand rather than guessing.
+*/
+
+use std::io::{Read, Write};
+
+use crate::Error;
+
+/// The newest version of the container format this crate reads, and
+/// the version it writes.
+pub const FORMAT_VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 12;
+const CHECKSUM_LEN: usize = 4;
+
+/**
+How the payload of a saved file is encoded.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Compact and quick to load; the default choice.
+    Binary,
+    /// Larger and slower, but readable by anything that reads JSON.
+    Json,
+}
+
+impl Encoding {
+    fn to_byte(self) -> u8 {
+        match self {
+            Encoding::Binary => 0,
+            Encoding::Json => 1,
+        }
+    }
+
+    fn from_byte(b: u8) -> Result<Encoding, Error> {
+        match b {
+            0 => Ok(Encoding::Binary),
+            1 => Ok(Encoding::Json),
+            n => Err(corrupt(format!("unknown payload encoding {}", n))),
+        }
+    }
+}
+
+pub(crate) fn corrupt<S: Into<String>>(msg: S) -> Error {
+    Error::CorruptData(msg.into())
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::IOError(format!("{}", &e))
+}
+
+/*
+The contents of a container, or of a file that predates the container
+format entirely.
+*/
+pub(crate) enum Payload {
+    Encoded(Encoding, Vec<u8>),
+    // Bare JSON, as written by `FontData::serialize()` and older versions
+    // of `librarify`.
+    Legacy(Vec<u8>),
+}
+
+/*
+Wrap `payload` in a container with the given `magic` number and write it.
+*/
+pub(crate) fn write_container<W: Write>(
+    mut writer: W,
+    magic: &[u8; 4],
+    encoding: Encoding,
+    payload: &[u8],
+) -> Result<(), Error> {
+    let len: u32 = payload
+        .len()
+        .try_into()
+        .map_err(|_| Error::IOError("payload too large to save".to_owned()))?;
+
+    let mut header = [0u8; HEADER_LEN];
+    header[0..4].copy_from_slice(magic);
+    header[4..6].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header[6] = encoding.to_byte();
+    header[8..12].copy_from_slice(&len.to_le_bytes());
+
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&header);
+    hasher.update(payload);
+    let checksum = hasher.finalize();
+
+    writer.write_all(&header).map_err(io_error)?;
+    writer.write_all(payload).map_err(io_error)?;
+    writer
+        .write_all(&checksum.to_le_bytes())
+        .map_err(io_error)?;
+    writer.flush().map_err(io_error)
+}
+
+/*
+Read a container with the given `magic` number, verify it, and return its
+payload. Anything that starts with a `{` instead is assumed to be legacy
+bare JSON, and handed back as such.
+*/
+pub(crate) fn read_container<R: Read>(mut reader: R, magic: &[u8; 4]) -> Result<Payload, Error> {
+    let mut data: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut data).map_err(io_error)?;
+
+    if data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') {
+        return Ok(Payload::Legacy(data));
+    }
+
+    if data.len() < HEADER_LEN + CHECKSUM_LEN {
+        return Err(corrupt("file too short to contain a header"));
+    }
+    if &data[0..4] != magic {
+        return Err(corrupt(format!(
+            "expected magic number {:?}, found {:?}",
+            String::from_utf8_lossy(magic),
+            String::from_utf8_lossy(&data[0..4]),
+        )));
+    }
+
+    // Check the version before anything else, so that a newer file gets
+    // reported as such, rather than as whatever else about it we might not
+    // understand.
+    let version = u16::from_le_bytes([data[4], data[5]]);
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    let len = u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize;
+    if data.len() != HEADER_LEN + len + CHECKSUM_LEN {
+        return Err(corrupt(format!(
+            "header says payload is {} bytes, but file holds {}",
+            len,
+            data.len().saturating_sub(HEADER_LEN + CHECKSUM_LEN)
+        )));
+    }
+
+    let (body, tail) = data.split_at(HEADER_LEN + len);
+    let expected = u32::from_le_bytes([tail[0], tail[1], tail[2], tail[3]]);
+    if crc32fast::hash(body) != expected {
+        return Err(corrupt("checksum mismatch"));
+    }
+
+    let encoding = Encoding::from_byte(data[6])?;
+    data.truncate(HEADER_LEN + len);
+    data.drain(..HEADER_LEN);
+    Ok(Payload::Encoded(encoding, data))
+}
+
+/*
+Builds up a binary payload a field at a time.
+*/
+#[derive(Default)]
+pub(crate) struct FieldWriter {
+    buff: Vec<u8>,
+}
+
+impl FieldWriter {
+    pub(crate) fn new() -> FieldWriter {
+        FieldWriter::default()
+    }
+
+    pub(crate) fn bytes(&mut self, tag: u8, data: &[u8]) -> &mut FieldWriter {
+        self.buff.push(tag);
+        self.buff
+            .extend_from_slice(&(data.len() as u32).to_le_bytes());
+        self.buff.extend_from_slice(data);
+        self
+    }
+
+    pub(crate) fn f32(&mut self, tag: u8, val: f32) -> &mut FieldWriter {
+        self.bytes(tag, &val.to_le_bytes())
+    }
+
+    pub(crate) fn u16(&mut self, tag: u8, val: u16) -> &mut FieldWriter {
+        self.bytes(tag, &val.to_le_bytes())
+    }
+
+    pub(crate) fn u64(&mut self, tag: u8, val: u64) -> &mut FieldWriter {
+        self.bytes(tag, &val.to_le_bytes())
+    }
+
+    pub(crate) fn str(&mut self, tag: u8, val: &str) -> &mut FieldWriter {
+        self.bytes(tag, val.as_bytes())
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.buff
+    }
+}
+
+/*
+Iterates over the `(tag, data)` fields of a binary payload.
+*/
+pub(crate) struct Fields<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Fields<'a> {
+        Fields { data }
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u8, &'a [u8]), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        if self.data.len() < 5 {
+            self.data = &[];
+            return Some(Err(corrupt("truncated field header")));
+        }
+
+        let tag = self.data[0];
+        let len = u32::from_le_bytes([self.data[1], self.data[2], self.data[3], self.data[4]]);
+        let rest = &self.data[5..];
+        if rest.len() < len as usize {
+            self.data = &[];
+            return Some(Err(corrupt(format!("field {} is truncated", tag))));
+        }
+
+        let (field, rest) = rest.split_at(len as usize);
+        self.data = rest;
+        Some(Ok((tag, field)))
+    }
+}
+
+/*
+Reads fixed-size values from the front of a field's data.
+*/
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        if self.data.len() < N {
+            return Err(corrupt("field is too short"));
+        }
+        let (head, rest) = self.data.split_at(N);
+        self.data = rest;
+        Ok(head.try_into().unwrap())
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, Error> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, Error> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, Error> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    pub(crate) fn f32(&mut self) -> Result<f32, Error> {
+        self.take().map(f32::from_le_bytes)
+    }
+
+    /*
+    The entire remainder of the data as a string.
+    */
+    pub(crate) fn str(&mut self) -> Result<&'a str, Error> {
+        let s = std::str::from_utf8(self.data).map_err(|_| corrupt("field is not valid UTF-8"))?;
+        self.data = &[];
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAGIC: &[u8; 4] = b"TEST";
+
+    fn wrap(payload: &[u8]) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        write_container(&mut data, MAGIC, Encoding::Binary, payload).unwrap();
+        data
+    }
+
+    #[test]
+    fn container() {
+        let data = wrap(b"some payload");
+        assert_eq!(data.len(), HEADER_LEN + 12 + CHECKSUM_LEN);
+        match read_container(data.as_slice(), MAGIC).unwrap() {
+            Payload::Encoded(Encoding::Binary, p) => assert_eq!(p, b"some payload"),
+            _ => panic!("wrong payload type"),
+        }
+
+        match read_container(&b"  {\"a\":1}"[..], MAGIC).unwrap() {
+            Payload::Legacy(p) => assert_eq!(p, b"  {\"a\":1}"),
+            _ => panic!("wrong payload type"),
+        }
+
+        assert!(matches!(
+            read_container(data.as_slice(), b"ELSE"),
+            Err(Error::CorruptData(_))
+        ));
+
+        let mut flipped = data.clone();
+        flipped[HEADER_LEN + 3] ^= 0x20;
+        assert!(matches!(
+            read_container(flipped.as_slice(), MAGIC),
+            Err(Error::CorruptData(_))
+        ));
+
+        let mut newer = data.clone();
+        newer[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            read_container(newer.as_slice(), MAGIC),
+            Err(Error::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1
+        ));
+
+        assert!(read_container(&data[..data.len() - 1], MAGIC).is_err());
+    }
+
+    #[test]
+    fn fields() {
+        let mut w = FieldWriter::new();
+        w.u64(1, 7).str(200, "unknown").f32(2, 0.5);
+        let payload = w.finish();
+
+        let fields: Vec<(u8, &[u8])> = Fields::new(&payload).map(Result::unwrap).collect();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(Reader::new(fields[0].1).u64().unwrap(), 7);
+        assert_eq!(Reader::new(fields[1].1).str().unwrap(), "unknown");
+        assert_eq!(Reader::new(fields[2].1).f32().unwrap(), 0.5);
+
+        let truncated = &payload[..payload.len() - 1];
+        assert!(Fields::new(truncated).any(|f| f.is_err()));
+    }
+}
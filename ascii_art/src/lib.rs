@@ -64,6 +64,41 @@ let mut stdout = std::io::stdout();
 ascii_art::write(&image, &font, &mut stdout).unwrap();
 ```
 
+The output will only have the same proportions as the image if the text
+is displayed with the same line and character spacing it was rendered
+for; `write_with()` takes `RenderOptions` to match the spacing of a
+particular viewer (like a terminal, or a web page with its own
+`line-height`).
+
+For very large images, `write_streaming()` (or the `TextRows` iterator
+that it uses) renders the image a row of text at a time straight from the
+encoded data, so that memory use depends on the width of the output
+rather than the size of the image.
+
+`write_color()` renders a `ColorImage` the same way, but also colors
+each character, reducing the image's colors to a `Palette` for targets
+that can only show a few (ANSI terminals, mIRC, BBCode, Minecraft chat).
+For targets that can also set background colors, `write_two_color()`
+chooses a glyph and both of its colors for each character together.
+
+`render_with()` returns the rows of text instead of writing them, and
+`write_html()`, `write_svg()`, `write_json()`, and `write_png()` (or
+`rasterize()`) lay them out as a web page, a vector drawing, a JSON
+grid, or an image of the text as the font draws it.
+
+`FrameReader` reads video frames from YUV4MPEG2 or raw pixel streams
+(like those `ffmpeg` writes to a pipe) or animated GIFs, PNGs, and WebPs, and
+`write_frames()` renders them one after another, either separated, as a
+terminal playback stream, or as an asciinema recording (see
+`AsciicastWriter`). `write_frames_with()` (or an `Animator`) also keeps
+the text from flickering between frames, and can redraw only what
+changed.
+
+`FontData` can be saved and loaded (`FontData::save()` and
+`FontData::load()`) so that fonts only need to be analyzed once, and
+collections of them for several fonts and sizes can be kept together in
+a `FontLibrary`.
+
 # Features
 
 `ascii_art` depends on the [`image`](https://docs.rs/image/latest/image/)
@@ -93,22 +128,51 @@ The following features must be explicitly enabled:
   * `tga` TARGA (Truevision TGA)
 
 Finally, enabling the `rayon` feature will enable `image`'s support for
-multithreaded JPEG decoding.
+multithreaded JPEG decoding, and will spread converting, resizing, and
+rendering images across all available cores. The output is identical
+either way; it just arrives sooner.
 */
 
 use std::cmp::Ordering;
 use std::io::{BufRead, BufWriter, Read, Seek, Write};
 
-use ab_glyph::{Font, FontRef, ScaleFont};
-use image::{
-    imageops::{resize, FilterType},
-    ImageBuffer, Luma,
-};
+use ab_glyph::{Font, ScaleFont};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Luma};
 use serde_derive::{Deserialize, Serialize};
 
 const SPACE: char = ' ';
 const REPLACE: char = '�'; // unicode replacement character
 const PRINTABLE_ASCII: std::ops::Range<u32> = 0x20..0x7f;
+const FONT_DATA_MAGIC: &[u8; 4] = b"AAFD";
+
+mod animate;
+mod asciicast;
+mod color;
+mod export;
+mod font_options;
+mod format;
+mod library;
+mod options;
+mod par;
+mod stream;
+mod text;
+mod two_color;
+mod video;
+
+pub use ab_glyph::VariationAxis;
+pub use animate::{AnimationOptions, Animator};
+pub use asciicast::AsciicastWriter;
+pub use color::{write_color, ColorImage, ColorOptions, ColorTarget, Dither, Palette};
+#[cfg(feature = "png")]
+pub use export::write_png;
+pub use export::{rasterize, write_html, write_json, write_svg};
+pub use font_options::FontOptions;
+pub use format::{Encoding, FORMAT_VERSION};
+pub use library::{EntryInfo, FontLibrary, LibraryEntry};
+pub use options::RenderOptions;
+pub use stream::{write_streaming, write_streaming_inverted, write_streaming_with, TextRows};
+pub use two_color::write_two_color;
+pub use video::{write_frames, write_frames_with, FrameOutput, FrameReader};
 
 /**
 Return a `Vec<char>` of the printable ASCII characters.
@@ -121,7 +185,6 @@ any font aimed at Latin alphabets will include these glyphs.
 */
 pub fn printable_ascii() -> Vec<char> {
     PRINTABLE_ASCII
-        .into_iter()
         .map(|n| char::try_from(n).unwrap())
         .collect()
 }
@@ -143,6 +206,29 @@ pub enum Error {
     /// Something has gone wrong reading or writing data; the contained
     /// string should contain more details.
     IOError(String),
+
+    /// Saved data was written in a newer version of the file format
+    /// than this version of the crate understands.
+    UnsupportedVersion(u16),
+
+    /// Saved data is damaged or isn't what it was expected to be; the
+    /// contained string should contain more details.
+    CorruptData(String),
+
+    /// The font file doesn't have the face, variation axis, or named
+    /// instance asked for in a `FontOptions`; the contained string says
+    /// which.
+    InvalidFontOption(String),
+
+    /// A `Palette` is empty, or `ColorOptions` ask for something the
+    /// `ColorTarget` can't do (like select more colors than it has, or
+    /// set background colors); the contained string says which.
+    InvalidPalette(String),
+
+    /// `RenderOptions` ask for something the function they were given to
+    /// can't do (like `preserve_text` when streaming); the contained
+    /// string says which.
+    UnsupportedOption(String),
 }
 
 impl std::fmt::Display for Error {
@@ -160,6 +246,25 @@ impl std::fmt::Display for Error {
             Error::IOError(s) => {
                 write!(f, "I/O error: {}", s)
             }
+            Error::UnsupportedVersion(v) => {
+                write!(
+                    f,
+                    "Data is in format version {}; only versions up to {} are supported.",
+                    v, FORMAT_VERSION
+                )
+            }
+            Error::CorruptData(s) => {
+                write!(f, "Corrupt data: {}", s)
+            }
+            Error::InvalidFontOption(s) => {
+                write!(f, "Invalid font option: {}", s)
+            }
+            Error::InvalidPalette(s) => {
+                write!(f, "Invalid palette: {}", s)
+            }
+            Error::UnsupportedOption(s) => {
+                write!(f, "Unsupported option: {}", s)
+            }
         }
     }
 }
@@ -186,6 +291,9 @@ struct UnscaledChar {
     about the rigid box structure of the pixels you're trying to
     represent them with. */
     adv: f32,
+    /*  Coverage of the left, middle, and right thirds of the glyph's
+    advance; these add up to `cov`. */
+    bands: [f32; 3],
 }
 
 impl UnscaledChar {
@@ -200,9 +308,21 @@ impl UnscaledChar {
         }
         let adv = font.h_advance(scaled_glyph.id);
         if let Some(g) = font.outline_glyph(scaled_glyph) {
+            let left = g.px_bounds().min.x;
             let mut cov: f32 = 0.0;
-            g.draw(|_, _, c| cov += c);
-            Some(UnscaledChar { chr, cov, adv })
+            let mut bands = [0.0f32; 3];
+            g.draw(|x, _, c| {
+                cov += c;
+                // Which third of the advance the center of this pixel is in.
+                let third = 3.0 * (left + x as f32 + 0.5) / adv;
+                bands[(third.max(0.0) as usize).min(2)] += c;
+            });
+            Some(UnscaledChar {
+                chr,
+                cov,
+                adv,
+                bands,
+            })
         } else {
             /*
             Evidently space characters don't have "outline glyphs"
@@ -215,6 +335,7 @@ impl UnscaledChar {
                     chr,
                     cov: 0.0f32,
                     adv,
+                    bands: [0.0f32; 3],
                 })
             } else {
                 None
@@ -253,13 +374,13 @@ impl Eq for Char {}
 
 impl PartialOrd for Char {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.val.partial_cmp(&other.val)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Char {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap()
+        self.val.partial_cmp(&other.val).unwrap()
     }
 }
 
@@ -279,20 +400,433 @@ impl Into<(char, f32)> for Char {
     }
 }
 
+/*
+Number of buckets in each of a `FontData`'s lookup tables, spaced evenly
+over intensities 0.0 through 1.0. This is 16 times the number of levels
+in an 8-bit image, so almost every bucket resolves to its answer with no
+further searching.
+*/
+const LUT_SIZE: usize = 4096;
+
+/*
+Precomputed lookup tables for both polarities, so that `FontData::pixel()`
+and `FontData::pixel_inv()` don't need to binary search the whole map
+for every pixel.
+
+Each table has one entry per bucket of intensities. Most buckets fall
+entirely between two adjacent coverage values in the map, and so always
+resolve to the same character; those entries are just that character
+(as a `u32`). The remaining entries have the `Lut::MIXED` bit set, and
+the rest of the bits are the index into the `FontData`'s `values` where
+the answer for the low end of that bucket lives; `FontData::lookup()`
+then walks a step or two from there to the exact answer. This means the
+tables give exactly the same results as searching, just faster.
+
+These are derived entirely from the `FontData`'s `values` and
+`fudge_factor`, so they are never serialized; they get rebuilt whenever
+a `FontData` is constructed, deserialized, or pruned.
+*/
+#[derive(Clone)]
+struct Lut {
+    fwd: Box<[u32]>,
+    inv: Box<[u32]>,
+}
+
+impl Lut {
+    // No `char` has this bit set.
+    const MIXED: u32 = 1 << 31;
+
+    fn index(val: f32) -> usize {
+        // `as` saturates negative numbers to 0 and turns NaN into 0, and
+        // is a whole lot cheaper than `.floor()`.
+        ((val * LUT_SIZE as f32) as usize).min(LUT_SIZE - 1)
+    }
+}
+
+// Dumping 8k table entries every time someone `{:?}`s a `FontData` helps
+// nobody.
+impl std::fmt::Debug for Lut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Lut {{ {} buckets }}", self.fwd.len())
+    }
+}
+
+/*
+Which font a `FontData` was made from, and that font's vertical metrics
+at the size it was made at.
+
+Everything is optional, because `FontData` saved by older versions of
+this crate doesn't have any of it.
+*/
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+struct FontInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    style: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pixel_size: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ascent: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    descent: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    line_gap: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source_hash: Option<u64>,
+}
+
+impl FontInfo {
+    /*
+    Read the family and style names out of the font's `name` table,
+    preferring the "typographic" names (which group all the weights and
+    widths of a family together) where they exist.
+    */
+    fn names(bytes: &[u8], face_index: u32) -> (Option<String>, Option<String>) {
+        use ttf_parser::name_id;
+
+        let face = match ttf_parser::Face::parse(bytes, face_index) {
+            Ok(face) => face,
+            Err(_) => return (None, None),
+        };
+        let find = |id: u16| {
+            face.names()
+                .into_iter()
+                .filter(|n| n.name_id == id)
+                .find_map(|n| n.to_string())
+        };
+
+        let family = find(name_id::TYPOGRAPHIC_FAMILY).or_else(|| find(name_id::FAMILY));
+        let style = find(name_id::TYPOGRAPHIC_SUBFAMILY).or_else(|| find(name_id::SUBFAMILY));
+        (family, style)
+    }
+}
+
+/*
+The parts of a `FontData` that actually get serialized.
+*/
+#[derive(Deserialize, Serialize)]
+struct FontDataRepr {
+    values: Vec<Char>,
+    width: f32,
+    height: f32,
+    fudge_factor: f32,
+    #[serde(default)]
+    info: FontInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bands: Option<Vec<[f32; 3]>>,
+}
+
+impl TryFrom<FontDataRepr> for FontData {
+    type Error = Error;
+
+    fn try_from(repr: FontDataRepr) -> Result<FontData, Error> {
+        if repr.values.is_empty() {
+            return Err(Error::NoUseableGlyphs);
+        }
+        if let Some(bands) = &repr.bands {
+            if bands.len() != repr.values.len() {
+                return Err(Error::CorruptData(format!(
+                    "{} characters, but subpixel coverage for {}",
+                    repr.values.len(),
+                    bands.len()
+                )));
+            }
+        }
+        Ok(FontData::new(
+            repr.values,
+            repr.width,
+            repr.height,
+            repr.fudge_factor,
+            repr.info,
+            repr.bands,
+        ))
+    }
+}
+
+// Field tags for the binary encoding of a `FontDataRepr`.
+const TAG_VALUES: u8 = 1;
+const TAG_WIDTH: u8 = 2;
+const TAG_HEIGHT: u8 = 3;
+const TAG_FUDGE_FACTOR: u8 = 4;
+const TAG_FAMILY: u8 = 5;
+const TAG_STYLE: u8 = 6;
+const TAG_PIXEL_SIZE: u8 = 7;
+const TAG_ASCENT: u8 = 8;
+const TAG_DESCENT: u8 = 9;
+const TAG_LINE_GAP: u8 = 10;
+const TAG_SOURCE_HASH: u8 = 11;
+const TAG_BANDS: u8 = 12;
+
+impl FontData {
+    /*
+    The binary encoding of the serialized parts of the receiver.
+    */
+    fn to_binary(&self) -> Vec<u8> {
+        let mut values: Vec<u8> = Vec::with_capacity(8 * self.values.len());
+        for c in self.values.iter() {
+            values.extend_from_slice(&(c.chr as u32).to_le_bytes());
+            values.extend_from_slice(&c.val.to_le_bytes());
+        }
+
+        let mut w = format::FieldWriter::new();
+        w.bytes(TAG_VALUES, &values)
+            .f32(TAG_WIDTH, self.width)
+            .f32(TAG_HEIGHT, self.height)
+            .f32(TAG_FUDGE_FACTOR, self.fudge_factor);
+
+        let info = &self.info;
+        if let Some(family) = &info.family {
+            w.str(TAG_FAMILY, family);
+        }
+        if let Some(style) = &info.style {
+            w.str(TAG_STYLE, style);
+        }
+        for (tag, val) in [
+            (TAG_PIXEL_SIZE, info.pixel_size),
+            (TAG_ASCENT, info.ascent),
+            (TAG_DESCENT, info.descent),
+            (TAG_LINE_GAP, info.line_gap),
+        ] {
+            if let Some(val) = val {
+                w.f32(tag, val);
+            }
+        }
+        if let Some(hash) = info.source_hash {
+            w.u64(TAG_SOURCE_HASH, hash);
+        }
+        if let Some(bands) = &self.bands {
+            let data: Vec<u8> = bands
+                .iter()
+                .flatten()
+                .flat_map(|b| b.to_le_bytes())
+                .collect();
+            w.bytes(TAG_BANDS, &data);
+        }
+        w.finish()
+    }
+}
+
+impl FontDataRepr {
+    fn from_binary(data: &[u8]) -> Result<FontDataRepr, Error> {
+        let mut values: Option<Vec<Char>> = None;
+        let mut width: Option<f32> = None;
+        let mut height: Option<f32> = None;
+        let mut fudge_factor: Option<f32> = None;
+        let mut info = FontInfo::default();
+        let mut bands: Option<Vec<[f32; 3]>> = None;
+
+        for field in format::Fields::new(data) {
+            let (tag, data) = field?;
+            let mut r = format::Reader::new(data);
+            match tag {
+                TAG_VALUES => {
+                    let mut v: Vec<Char> = Vec::with_capacity(data.len() / 8);
+                    while !r.is_empty() {
+                        let n = r.u32()?;
+                        let chr = char::from_u32(n).ok_or_else(|| {
+                            format::corrupt(format!("{:#x} is not a valid char", n))
+                        })?;
+                        v.push(Char { chr, val: r.f32()? });
+                    }
+                    values = Some(v);
+                }
+                TAG_WIDTH => width = Some(r.f32()?),
+                TAG_HEIGHT => height = Some(r.f32()?),
+                TAG_FUDGE_FACTOR => fudge_factor = Some(r.f32()?),
+                TAG_FAMILY => info.family = Some(r.str()?.to_owned()),
+                TAG_STYLE => info.style = Some(r.str()?.to_owned()),
+                TAG_PIXEL_SIZE => info.pixel_size = Some(r.f32()?),
+                TAG_ASCENT => info.ascent = Some(r.f32()?),
+                TAG_DESCENT => info.descent = Some(r.f32()?),
+                TAG_LINE_GAP => info.line_gap = Some(r.f32()?),
+                TAG_SOURCE_HASH => info.source_hash = Some(r.u64()?),
+                TAG_BANDS => {
+                    let mut v: Vec<[f32; 3]> = Vec::with_capacity(data.len() / 12);
+                    while !r.is_empty() {
+                        v.push([r.f32()?, r.f32()?, r.f32()?]);
+                    }
+                    bands = Some(v);
+                }
+                // Something added by a later version; skip it.
+                _ => {}
+            }
+        }
+
+        let missing = |name: &str| format::corrupt(format!("missing field \"{}\"", name));
+        Ok(FontDataRepr {
+            values: values.ok_or_else(|| missing("values"))?,
+            width: width.ok_or_else(|| missing("width"))?,
+            height: height.ok_or_else(|| missing("height"))?,
+            fudge_factor: fudge_factor.ok_or_else(|| missing("fudge_factor"))?,
+            info,
+            bands,
+        })
+    }
+}
+
+impl From<FontData> for FontDataRepr {
+    fn from(fd: FontData) -> FontDataRepr {
+        FontDataRepr {
+            values: fd.values,
+            width: fd.width,
+            height: fd.height,
+            fudge_factor: fd.fudge_factor,
+            info: fd.info,
+            bands: fd.bands,
+        }
+    }
+}
+
 /**
 The `FontData` struct holds all the information about a font
 (at a given size) to render an image in it: a mapping from
 pixel intensity to characters, plus geometry data.
+
+If it was made with `FontOptions::subpixel` set, it also has the coverage
+of each third (left, middle, and right) of each glyph, which lets
+`RenderOptions::subpixel` rendering match each third of a character cell
+separately; see `.subpixel()`.
+
+It also remembers which font it was made from (see `.family()`,
+`.style()`, `.pixel_size()`, and `.source_hash()`) and that font's
+vertical metrics. These are `None` for `FontData` that was saved by
+versions of this crate that didn't record them.
 */
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "FontDataRepr", into = "FontDataRepr")]
 pub struct FontData {
     values: Vec<Char>,
     width: f32,
     height: f32,
     fudge_factor: f32,
+    info: FontInfo,
+    // Subpixel coverage of each of `values`, in the same order.
+    bands: Option<Vec<[f32; 3]>>,
+    lut: Lut,
 }
 
 impl FontData {
+    /*
+    Assemble a `FontData` from its parts and build its lookup tables.
+    */
+    fn new(
+        values: Vec<Char>,
+        width: f32,
+        height: f32,
+        fudge_factor: f32,
+        info: FontInfo,
+        bands: Option<Vec<[f32; 3]>>,
+    ) -> FontData {
+        let mut fd = FontData {
+            values,
+            width,
+            height,
+            fudge_factor,
+            info,
+            bands,
+            lut: Lut {
+                fwd: Box::new([]),
+                inv: Box::new([]),
+            },
+        };
+        fd.build_lut();
+        fd
+    }
+
+    /*
+    (Re)build the receiver's lookup tables from its current `values`.
+    */
+    fn build_lut(&mut self) {
+        let n = LUT_SIZE as f32;
+        // The edges of each bucket, in terms of intensity. The first and
+        // last buckets also catch everything out of range beyond them.
+        let edges = |b: usize| {
+            let lo = if b == 0 {
+                f32::NEG_INFINITY
+            } else {
+                (b as f32) / n
+            };
+            let hi = if b == LUT_SIZE - 1 {
+                f32::INFINITY
+            } else {
+                ((b + 1) as f32) / n
+            };
+            (lo, hi)
+        };
+
+        // `.pixel()` searches for `val - fudge_factor`, which increases
+        // with `val`; `.pixel_inv()` searches for
+        // `1.0 - (val + fudge_factor)`, which decreases.
+        self.lut.fwd = (0..LUT_SIZE)
+            .map(|b| {
+                let (lo, hi) = edges(b);
+                self.lut_entry(lo - self.fudge_factor, hi - self.fudge_factor)
+            })
+            .collect();
+        self.lut.inv = (0..LUT_SIZE)
+            .map(|b| {
+                let (lo, hi) = edges(b);
+                self.lut_entry(
+                    1.0 - (hi + self.fudge_factor),
+                    1.0 - (lo + self.fudge_factor),
+                )
+            })
+            .collect();
+    }
+
+    /*
+    Return the lookup table entry for a bucket covering search keys from
+    `lo` to `hi`.
+    */
+    fn lut_entry(&self, lo: f32, hi: f32) -> u32 {
+        // Pad the range a little, so rounding when we compute which bucket
+        // a given intensity goes in can never put it in a bucket whose
+        // character is wrong for it.
+        const SLOP: f32 = 1.0e-5;
+        let start = self.search(lo - SLOP);
+        if start == self.search(hi + SLOP) {
+            self.values[start].chr as u32
+        } else {
+            Lut::MIXED | (start as u32)
+        }
+    }
+
+    /*
+    Return the index into `values` of the character with the smallest
+    coverage not less than `key` (or the last character, if there are
+    none).
+    */
+    fn search(&self, key: f32) -> usize {
+        let n = self.values.partition_point(|c| c.val < key);
+        n.min(self.values.len() - 1)
+    }
+
+    /*
+    Return the same character as `self.values[self.search(key)]`, using
+    `table`'s entry for `val` as a shortcut.
+    */
+    fn lookup(&self, table: &[u32], val: f32, key: f32) -> char {
+        let entry = table[Lut::index(val)];
+        if entry & Lut::MIXED == 0 {
+            // Entries without the flag were all made from `char`s.
+            return char::from_u32(entry).unwrap();
+        }
+
+        let last = self.values.len() - 1;
+        let mut n = (entry & !Lut::MIXED) as usize;
+        // Only the step in one of these directions should ever be
+        // necessary, but floating-point rounding at the edges of buckets
+        // makes no promises about which.
+        while n < last && self.values[n].val < key {
+            n += 1;
+        }
+        while n > 0 && self.values[n - 1].val >= key {
+            n -= 1;
+        }
+        self.values[n].chr
+    }
+
     /**
     Analyze a font at a given `size` to produce a `FontData` struct that
     maps pixel intensity to the given set of `chars`. `bytes` should
@@ -365,12 +899,25 @@ impl FontData {
         size: f32,
         chars: &[char],
     ) -> Result<Result<FontData, (FontData, Vec<char>)>, Error> {
-        let font = match FontRef::try_from_slice(bytes) {
-            Ok(f) => f,
-            Err(_) => {
-                return Err(Error::InvalidFontData);
-            }
-        };
+        FontData::from_font_bytes_with(bytes, size, chars, &FontOptions::default())
+    }
+
+    /**
+    Like `FontData::from_font_bytes()`, but analyze the face of the font
+    file, and the instance of that face (if it's a variable font),
+    specified by `opts`.
+
+    Different weights and widths of the same variable font really do
+    cover different amounts of their cells, and so generate different
+    maps.
+    */
+    pub fn from_font_bytes_with(
+        bytes: &[u8],
+        size: f32,
+        chars: &[char],
+        opts: &FontOptions,
+    ) -> Result<Result<FontData, (FontData, Vec<char>)>, Error> {
+        let (font, instance_name) = opts.load(bytes)?;
         let scaled_font = font.as_scaled(size);
 
         let mut reject_chars: Vec<char> = Vec::new();
@@ -406,19 +953,36 @@ impl FontData {
 
         let height = scaled_font.height() + scaled_font.line_gap();
 
+        // Normalized so that the average of a glyph's bands is its value.
+        let bands: Option<Vec<[f32; 3]>> = if opts.subpixel {
+            Some(
+                charz
+                    .iter()
+                    .map(|ch| ch.bands.map(|b| 3.0 * b / max_cov))
+                    .collect(),
+            )
+        } else {
+            None
+        };
         let values: Vec<Char> = charz
             .drain(..)
             .map(|ch| Char::from_unscaled(ch, max_cov))
             .collect();
         let fudge_factor: f32 = 1.0 / (values.len() as f32);
 
-        let dat = FontData {
-            values,
-            width,
-            height,
-            fudge_factor,
+        let (family, style) = FontInfo::names(bytes, opts.face_index);
+        let info = FontInfo {
+            family,
+            style: instance_name.or(style),
+            pixel_size: Some(size),
+            ascent: Some(scaled_font.ascent()),
+            descent: Some(scaled_font.descent()),
+            line_gap: Some(scaled_font.line_gap()),
+            source_hash: Some(FontData::hash_font_bytes(bytes)),
         };
 
+        let dat = FontData::new(values, width, height, fudge_factor, info, bands);
+
         if reject_chars.is_empty() {
             Ok(Ok(dat))
         } else {
@@ -446,11 +1010,55 @@ impl FontData {
             let kf = (k as f32) / nf;
             charz.insert(self.pixel(kf));
         }
+        if let Some(bands) = &mut self.bands {
+            let mut kept = self.values.iter().map(|c| charz.contains(&c.chr));
+            bands.retain(|_| kept.next().unwrap());
+        }
         self.values = self
             .values
             .drain(..)
             .filter(|c| charz.contains(&c.chr))
             .collect();
+        self.build_lut();
+    }
+
+    /*
+    Return a copy of the receiver that never chooses any of `chars` (so
+    each pixel that would get one gets the nearest of the others instead).
+    If that would leave no characters at all, it's just a copy.
+    */
+    pub(crate) fn without(&self, chars: &[char]) -> FontData {
+        let keep: Vec<bool> = self
+            .values
+            .iter()
+            .map(|c| !chars.contains(&c.chr))
+            .collect();
+        if !keep.contains(&true) {
+            return self.clone();
+        }
+        let mut kept = keep.iter();
+        let values: Vec<Char> = self
+            .values
+            .iter()
+            .filter(|_| *kept.next().unwrap())
+            .cloned()
+            .collect();
+        let bands = self.bands.as_ref().map(|bands| {
+            let mut kept = keep.iter();
+            bands
+                .iter()
+                .filter(|_| *kept.next().unwrap())
+                .copied()
+                .collect()
+        });
+        FontData::new(
+            values,
+            self.width,
+            self.height,
+            self.fudge_factor,
+            self.info.clone(),
+            bands,
+        )
     }
 
     /**
@@ -463,15 +1071,7 @@ impl FontData {
     respectively.
     */
     pub fn pixel(&self, val: f32) -> char {
-        let val = val - self.fudge_factor;
-        let dummy = Char { chr: ' ', val };
-
-        let n = match &self.values.binary_search(&dummy) {
-            Ok(n) => *n,
-            Err(n) => *n,
-        };
-
-        self.values[n].chr
+        self.lookup(&self.lut.fwd, val, val - self.fudge_factor)
     }
 
     /**
@@ -483,15 +1083,108 @@ impl FontData {
     minimum or maximum coverage character, respectively.
     */
     pub fn pixel_inv(&self, val: f32) -> char {
-        let val = 1.0 - (val + self.fudge_factor);
-        let dummy = Char { chr: ' ', val };
+        self.lookup(&self.lut.inv, val, 1.0 - (val + self.fudge_factor))
+    }
+
+    /**
+    Whether the receiver has subpixel coverage information, and so can
+    be used for subpixel rendering with `.subpixel()`.
+    */
+    pub fn has_subpixel(&self) -> bool {
+        self.bands.is_some()
+    }
+
+    /**
+    Return the character whose left, middle, and right thirds best match
+    the intensities of the left, middle, and right thirds of a cell (as
+    opposed to `.pixel()`, which only matches the cell's overall
+    intensity). This gives thin vertical features three times the
+    horizontal resolution they'd otherwise get.
+
+    If the receiver doesn't have subpixel coverage information (see
+    `.has_subpixel()`), this is the same as `.pixel()` on the average of
+    the three.
+    */
+    pub fn subpixel(&self, vals: [f32; 3]) -> char {
+        match &self.bands {
+            Some(bands) => self.nearest_bands(bands, vals),
+            None => self.pixel((vals[0] + vals[1] + vals[2]) / 3.0),
+        }
+    }
+
+    /**
+    Like `.subpixel()`, but for rendering _dark_ text on a _light_
+    background, as `.pixel_inv()` is to `.pixel()`.
+    */
+    pub fn subpixel_inv(&self, vals: [f32; 3]) -> char {
+        match &self.bands {
+            Some(bands) => self.nearest_bands(bands, vals.map(|v| 1.0 - v)),
+            None => self.pixel_inv((vals[0] + vals[1] + vals[2]) / 3.0),
+        }
+    }
+
+    /*
+    Return each character with its coverage of the left, middle, and right
+    thirds of a cell: its subpixel coverage, if `subpixel` is set and the
+    receiver has it, otherwise its overall coverage for all three.
+    */
+    pub(crate) fn coverage_thirds(&self, subpixel: bool) -> Vec<(char, [f32; 3])> {
+        match (&self.bands, subpixel) {
+            (Some(bands), true) => self
+                .values
+                .iter()
+                .zip(bands.iter())
+                .map(|(c, b)| (c.chr, b.map(|v| v.clamp(0.0, 1.0))))
+                .collect(),
+            _ => self.values.iter().map(|c| (c.chr, [c.val; 3])).collect(),
+        }
+    }
 
-        let n = match &self.values.binary_search(&dummy) {
-            Ok(n) => *n,
-            Err(n) => *n,
+    /*
+    Return the character whose `bands` are closest (in the least-squares
+    sense) to `vals`.
+    */
+    fn nearest_bands(&self, bands: &[[f32; 3]], vals: [f32; 3]) -> char {
+        let dist = |b: &[f32; 3]| {
+            b.iter()
+                .zip(vals.iter())
+                .map(|(b, v)| (b - v) * (b - v))
+                .sum::<f32>()
         };
 
-        self.values[n].chr
+        let mut best = 0;
+        let mut best_dist = f32::INFINITY;
+        for (n, b) in bands.iter().enumerate() {
+            let d = dist(b);
+            if d < best_dist {
+                best = n;
+                best_dist = d;
+            }
+        }
+        self.values[best].chr
+    }
+
+    /**
+    Return the same character as `.pixel()`, but by binary searching the
+    whole character map instead of starting from the precomputed lookup
+    table. There's no reason to use this except to measure how much
+    faster `.pixel()` is (which is why it's public, but hidden from the
+    docs and not part of the stable API).
+    */
+    #[doc(hidden)]
+    pub fn pixel_uncached(&self, val: f32) -> char {
+        self.values[self.search(val - self.fudge_factor)].chr
+    }
+
+    /**
+    Return the same character as `.pixel_inv()`, but by binary searching
+    the whole character map instead of starting from the precomputed
+    lookup table. There's no reason to use this except to measure how
+    much faster `.pixel_inv()` is (see `.pixel_uncached()`).
+    */
+    #[doc(hidden)]
+    pub fn pixel_inv_uncached(&self, val: f32) -> char {
+        self.values[self.search(1.0 - (val + self.fudge_factor))].chr
     }
 
     /// Return the width and height (in pixels) of a single character
@@ -500,6 +1193,85 @@ impl FontData {
         (self.width, self.height)
     }
 
+    /**
+    Return the characters the receiver chooses from, each with how much
+    of its cell it covers (relative to the character that covers the
+    most), from least covered to most: dark to light, when rendering
+    light text on a dark background.
+
+    ```
+    # use ascii_art::{FontData, printable_ascii};
+    # let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+    let font = FontData::from_font_bytes(&bytes, 12.0, &printable_ascii())
+        .unwrap()
+        .unwrap();
+    let ramp = font.ramp();
+    assert_eq!(ramp.first().unwrap().0, ' ');
+    assert!(ramp.windows(2).all(|w| w[0].1 <= w[1].1));
+    ```
+    */
+    pub fn ramp(&self) -> Vec<(char, f32)> {
+        self.values.iter().map(|c| (c.chr, c.val)).collect()
+    }
+
+    /// The family name of the font this was made from (e.g.
+    /// "Liberation Mono").
+    pub fn family(&self) -> Option<&str> {
+        self.info.family.as_deref()
+    }
+
+    /// The style name of the font this was made from (e.g. "Bold Italic").
+    pub fn style(&self) -> Option<&str> {
+        self.info.style.as_deref()
+    }
+
+    /// The pixel size the font was analyzed at.
+    pub fn pixel_size(&self) -> Option<f32> {
+        self.info.pixel_size
+    }
+
+    /// How far (in pixels) the font extends above the baseline.
+    pub fn ascent(&self) -> Option<f32> {
+        self.info.ascent
+    }
+
+    /// How far (in pixels) the font extends below the baseline; this is
+    /// usually negative.
+    pub fn descent(&self) -> Option<f32> {
+        self.info.descent
+    }
+
+    /// The extra space (in pixels) the font asks for between lines.
+    pub fn line_gap(&self) -> Option<f32> {
+        self.info.line_gap
+    }
+
+    /**
+    The `FontData::hash_font_bytes()` hash of the font file this was made
+    from.
+
+    Compare this with the hash of the font file currently installed to
+    find out whether the font has changed since this was made.
+    */
+    pub fn source_hash(&self) -> Option<u64> {
+        self.info.source_hash
+    }
+
+    /**
+    Hash the contents of a font file, as recorded by `.source_hash()`.
+
+    This is 64-bit FNV-1a, which is fast and good enough for noticing that
+    a file has changed, but not meant to resist deliberate collisions.
+    */
+    pub fn hash_font_bytes(bytes: &[u8]) -> u64 {
+        const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        bytes
+            .iter()
+            .fold(OFFSET, |h, b| (h ^ (*b as u64)).wrapping_mul(PRIME))
+    }
+
     /**
     Serialize the receiver into a chunk of JSON.
 
@@ -527,6 +1299,39 @@ impl FontData {
             Err(e) => Err(Error::IOError(format!("{}", &e))),
         }
     }
+
+    /**
+    Save the receiver in this crate's versioned file format, with its
+    payload encoded as specified.
+
+    Unlike `.serialize()`, the output carries a format version and a
+    checksum, so `FontData::load()` can tell when it's been handed
+    something damaged or too new to understand.
+    */
+    pub fn save<W: Write>(&self, writer: W, encoding: Encoding) -> Result<(), Error> {
+        let payload = match encoding {
+            Encoding::Binary => self.to_binary(),
+            Encoding::Json => {
+                serde_json::to_vec(self).map_err(|e| Error::IOError(format!("{}", &e)))?
+            }
+        };
+        format::write_container(writer, FONT_DATA_MAGIC, encoding, &payload)
+    }
+
+    /**
+    Load some `FontData` that has been previously saved with the
+    `.save()` method (in either encoding), or serialized with the
+    `.serialize()` method.
+    */
+    pub fn load<R: Read>(reader: R) -> Result<FontData, Error> {
+        let repr = match format::read_container(reader, FONT_DATA_MAGIC)? {
+            format::Payload::Encoded(Encoding::Binary, data) => FontDataRepr::from_binary(&data)?,
+            format::Payload::Encoded(Encoding::Json, data) | format::Payload::Legacy(data) => {
+                serde_json::from_slice(&data).map_err(|e| format::corrupt(format!("{}", &e)))?
+            }
+        };
+        FontData::try_from(repr)
+    }
 }
 
 pub use image::ImageFormat;
@@ -560,8 +1365,9 @@ impl Image {
             Ok(x) => x,
         };
 
-        let img = img.to_luma32f();
-        Ok(Image { buff: img })
+        Ok(Image {
+            buff: to_intensities(&img),
+        })
     }
 
     /**
@@ -577,8 +1383,52 @@ impl Image {
             Ok(x) => x,
         };
 
-        let img = img.to_luma32f();
-        Ok(Image { buff: img })
+        Ok(Image {
+            buff: to_intensities(&img),
+        })
+    }
+
+    pub(crate) fn from_decoded(img: &DynamicImage) -> Image {
+        Image {
+            buff: to_intensities(img),
+        }
+    }
+
+    /// The width and height of the image, in pixels.
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.buff.dimensions()
+    }
+
+    /**
+    Return the part of the image `width` by `height` pixels in size with
+    its top left corner at (`x`, `y`), clipped to the bounds of the image.
+
+    ```
+    # let f = std::fs::File::open("test/griffin_sm.jpg").unwrap();
+    # let img = ascii_art::Image::auto(std::io::BufReader::new(f)).unwrap();
+    let (w, h) = img.dimensions();
+    assert_eq!(img.crop(10, 20, 30, 40).dimensions(), (30, 40));
+    assert_eq!(img.crop(w - 5, 0, 30, h + 10).dimensions(), (5, h));
+    ```
+    */
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Image {
+        Image {
+            buff: image::imageops::crop_imm(&self.buff, x, y, width, height).to_image(),
+        }
+    }
+
+    /**
+    Return a copy of the image with its contrast multiplied by `factor`:
+    each intensity's distance from middle gray is scaled by `factor`
+    (and clamped to 0.0 through 1.0), so values over 1.0 increase the
+    contrast and values under 1.0 reduce it.
+    */
+    pub fn with_contrast(&self, factor: f32) -> Image {
+        let mut buff = self.buff.clone();
+        for p in buff.iter_mut() {
+            *p = ((*p - 0.5) * factor + 0.5).clamp(0.0, 1.0);
+        }
+        Image { buff }
     }
 
     fn geometry(&self) -> (f32, f32) {
@@ -587,35 +1437,197 @@ impl Image {
     }
 }
 
-/**
-Given some `FontData`, write the `Image` as text to the `writer`.
+/*
+Number of rows of a decoded image that get converted to intensities
+at a time by `to_intensities()`.
+*/
+const STRIP_HEIGHT: u32 = 64;
 
-This is for writing light text on a dark background (that is, pixel
-intensity values are positively correlated with luminosity.)
+/*
+Convert a decoded image to normalized intensities.
 
-This function looks at the geometry of the `font` and the geometry of the
-`Image` and tries to output a rectangle of text that will match the size
-of the original image. Depending on how the text is viewed, characters and
-lines may have different amounts of spacing between them, resulting in
-an imperfect size match.
+This is exactly `img.to_luma32f()`, but when the `rayon` feature is
+enabled, it's done in horizontal strips so the strips can be converted
+in parallel.
 */
-pub fn write<W: Write>(img: &Image, font: &FontData, writer: W) -> Result<(), Error> {
+fn to_intensities(img: &DynamicImage) -> ImageBuffer<Luma<f32>, Vec<f32>> {
+    if !cfg!(feature = "rayon") {
+        return img.to_luma32f();
+    }
+
+    let (w, h) = img.dimensions();
+    let n_strips = h.div_ceil(STRIP_HEIGHT) as usize;
+    let strips = par::map_range(n_strips, |n| {
+        let y = (n as u32) * STRIP_HEIGHT;
+        let strip_h = STRIP_HEIGHT.min(h - y);
+        img.crop_imm(0, y, w, strip_h).to_luma32f().into_raw()
+    });
+
+    ImageBuffer::from_raw(w, h, strips.concat()).unwrap()
+}
+
+/*
+For each of `new_len` evenly-spaced samples along a line of `len` pixels,
+return the index of the pixel that sample falls in.
+
+This is the same arithmetic `image::imageops::resize()` uses when
+`FilterType::Nearest` is specified.
+*/
+fn nearest_indices(len: u32, new_len: u32) -> Vec<usize> {
+    let ratio = len as f32 / new_len as f32;
+    let max = (len as i64) - 1;
+    (0..new_len)
+        .map(|n| {
+            let x = ((n as f32 + 0.5) * ratio).floor() as i64;
+            x.clamp(0, max) as usize
+        })
+        .collect()
+}
+
+/*
+Resize `buff` to `width` by `height` pixels by nearest-neighbor sampling.
+
+This gives exactly the same result as
+`image::imageops::resize(buff, width, height, FilterType::Nearest)`
+(which is what this crate used to call), but samples a row at a time so
+the rows can be done in parallel.
+*/
+fn resize_nearest(
+    buff: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    width: u32,
+    height: u32,
+) -> ImageBuffer<Luma<f32>, Vec<f32>> {
+    let (src_w, src_h) = buff.dimensions();
+    if (src_w, src_h) == (width, height) {
+        return buff.clone();
+    }
+    if src_w == 0 || src_h == 0 {
+        return ImageBuffer::new(width, height);
+    }
+
+    let xs = nearest_indices(src_w, width);
+    let ys = nearest_indices(src_h, height);
+    let src = buff.as_raw();
+    let mut out = vec![0.0f32; (width as usize) * (height as usize)];
+
+    par::fill_rows(&mut out, width as usize, |y, row| {
+        let src_row = &src[ys[y] * (src_w as usize)..];
+        for (p, x) in row.iter_mut().zip(xs.iter()) {
+            // `image` clamps to the valid range when it resamples.
+            *p = src_row[*x].clamp(0.0, 1.0);
+        }
+    });
+
+    ImageBuffer::from_raw(width, height, out).unwrap()
+}
+
+/*
+Return the width and height (in characters) of the text that will
+represent an image `img_w` by `img_h` pixels in size in `font`.
+*/
+fn grid_size(img_w: f32, img_h: f32, cell: (f32, f32)) -> (u32, u32) {
+    let (cell_w, cell_h) = cell;
+    ((img_w / cell_w) as u32, (img_h / cell_h) as u32)
+}
+
+/*
+Return the range of pixels (along a line of `len` pixels) covered by the
+`n`th `cell`-sized cell: from `n * cell` up to `(n + 1) * cell`, but at
+least one pixel.
+*/
+fn cell_span(n: u32, cell: f32, len: u32) -> (u32, u32) {
+    let start = ((n as f32 * cell) as u32).min(len - 1);
+    let end = (((n + 1) as f32 * cell) as u32).clamp(start + 1, len);
+    (start, end)
+}
+
+/*
+Resize `img` so that each pixel corresponds to one `cell`-sized character,
+and return the text of each row, choosing characters from `font` with
+`glyph` (which should be either `FontData::pixel` or
+`FontData::pixel_inv`).
+*/
+fn render_rows(
+    img: &Image,
+    font: &FontData,
+    cell: (f32, f32),
+    glyph: fn(&FontData, f32) -> char,
+) -> Vec<String> {
+    let (img_wf, img_hf) = img.geometry();
+    let (w, h) = grid_size(img_wf, img_hf, cell);
+
+    if w == 0 {
+        return vec![String::new(); h as usize];
+    }
+
+    let resized = resize_nearest(&img.buff, w, h);
+    par::map_rows(resized.as_raw(), w as usize, |_, row| {
+        row.iter().map(|p| glyph(font, *p)).collect()
+    })
+}
+
+/*
+Like `render_rows()`, but resize `img` so that each character gets three
+pixels side by side, which `glyph` (either `FontData::subpixel` or
+`FontData::subpixel_inv`) matches to a character.
+*/
+fn render_rows_subpixel(
+    img: &Image,
+    font: &FontData,
+    cell: (f32, f32),
+    glyph: fn(&FontData, [f32; 3]) -> char,
+) -> Vec<String> {
     let (img_wf, img_hf) = img.geometry();
-    let (font_wf, font_hf) = font.geometry();
-    let w = (img_wf / font_wf) as u32;
-    let h = (img_hf / font_hf) as u32;
+    let (w, h) = grid_size(img_wf, img_hf, cell);
+
+    if w == 0 {
+        return vec![String::new(); h as usize];
+    }
+
+    let resized = resize_nearest(&img.buff, 3 * w, h);
+    par::map_rows(resized.as_raw(), 3 * w as usize, |_, row| {
+        row.chunks_exact(3)
+            .map(|p| glyph(font, [p[0], p[1], p[2]]))
+            .collect()
+    })
+}
+
+/*
+Return the text of each row of `img` rendered as specified by `opts`.
+*/
+fn render_rows_with(img: &Image, font: &FontData, opts: &RenderOptions) -> Vec<String> {
+    let cell = opts.cell_size(font);
+    let rows = if opts.subpixel && font.has_subpixel() {
+        let glyph = if opts.invert {
+            FontData::subpixel_inv
+        } else {
+            FontData::subpixel
+        };
+        render_rows_subpixel(img, font, cell, glyph)
+    } else {
+        let glyph = if opts.invert {
+            FontData::pixel_inv
+        } else {
+            FontData::pixel
+        };
+        render_rows(img, font, cell, glyph)
+    };
+
+    if opts.preserve_text {
+        text::sharpen_text(img, font, opts, rows)
+    } else {
+        rows
+    }
+}
+
+/*
+Write each of `rows` as a line to `writer`.
+*/
+fn write_rows<W: Write>(rows: &[String], writer: W) -> Result<(), Error> {
     let mut writer = BufWriter::new(writer);
 
-    let resized = resize(&img.buff, w, h, FilterType::Nearest);
-    for row in resized.rows() {
-        for p in row {
-            let g = font.pixel(p.0[0]);
-            if let Err(e) = write!(&mut writer, "{}", g) {
-                let err = format!("{}", &e);
-                return Err(Error::IOError(err));
-            }
-        }
-        if let Err(e) = writeln!(&mut writer) {
+    for row in rows.iter() {
+        if let Err(e) = writeln!(&mut writer, "{}", row) {
             let err = format!("{}", &e);
             return Err(Error::IOError(err));
         }
@@ -631,6 +1643,23 @@ pub fn write<W: Write>(img: &Image, font: &FontData, writer: W) -> Result<(), Er
 /**
 Given some `FontData`, write the `Image` as text to the `writer`.
 
+This is for writing light text on a dark background (that is, pixel
+intensity values are positively correlated with luminosity.)
+
+This function looks at the geometry of the `font` and the geometry of the
+`Image` and tries to output a rectangle of text that will match the size
+of the original image. Depending on how the text is viewed, characters and
+lines may have different amounts of spacing between them, resulting in
+an imperfect size match.
+*/
+pub fn write<W: Write>(img: &Image, font: &FontData, writer: W) -> Result<(), Error> {
+    let rows = render_rows(img, font, font.geometry(), FontData::pixel);
+    write_rows(&rows, writer)
+}
+
+/**
+Given some `FontData`, write the `Image` as text to the `writer`.
+
 This is for writing _dark_ text on a _light_ background (that is, pixel
 intensity values are _negatively_ correlated with luminosity.)
 
@@ -641,32 +1670,50 @@ lines may have different amounts of spacing between them, resulting in
 an imperfect size match.
 */
 pub fn write_inverted<W: Write>(img: &Image, font: &FontData, writer: W) -> Result<(), Error> {
-    let (img_wf, img_hf) = img.geometry();
-    let (font_wf, font_hf) = font.geometry();
-    let w = (img_wf / font_wf) as u32;
-    let h = (img_hf / font_hf) as u32;
-    let mut writer = BufWriter::new(writer);
+    let rows = render_rows(img, font, font.geometry(), FontData::pixel_inv);
+    write_rows(&rows, writer)
+}
 
-    let resized = resize(&img.buff, w, h, FilterType::Nearest);
-    for row in resized.rows() {
-        for p in row {
-            let g = font.pixel_inv(p.0[0]);
-            if let Err(e) = write!(&mut writer, "{}", g) {
-                let err = format!("{}", &e);
-                return Err(Error::IOError(err));
-            }
-        }
-        if let Err(e) = writeln!(&mut writer) {
-            let err = format!("{}", &e);
-            return Err(Error::IOError(err));
-        }
-    }
+/**
+Given some `FontData`, write the `Image` as text to the `writer`, as
+specified by `opts`.
 
-    if let Err(e) = writer.flush() {
-        Err(Error::IOError(format!("{}", &e)))
-    } else {
-        Ok(())
-    }
+This is like `write()` (or `write_inverted()`, if `opts.invert` is set),
+except that the size of the area of the image each character covers can
+be adjusted to match how the text will actually be displayed; see
+`RenderOptions`.
+*/
+pub fn write_with<W: Write>(
+    img: &Image,
+    font: &FontData,
+    opts: &RenderOptions,
+    writer: W,
+) -> Result<(), Error> {
+    let rows = render_rows_with(img, font, opts);
+    write_rows(&rows, writer)
+}
+
+/**
+Render the `Image` as text, as specified by `opts`, and return the text of
+each row (without line endings), rather than writing it out.
+
+```
+# let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+# let font = ascii_art::FontData::from_font_bytes(
+#     &bytes, 12.0, &ascii_art::printable_ascii()
+# ).unwrap().unwrap();
+# let f = std::fs::File::open("test/griffin_sm.jpg").unwrap();
+# let img = ascii_art::Image::auto(std::io::BufReader::new(f)).unwrap();
+let opts = ascii_art::RenderOptions::default();
+let rows = ascii_art::render_with(&img, &font, &opts);
+
+let mut written: Vec<u8> = Vec::new();
+ascii_art::write_with(&img, &font, &opts, &mut written).unwrap();
+assert_eq!(rows.join("\n") + "\n", String::from_utf8(written).unwrap());
+```
+*/
+pub fn render_with(img: &Image, font: &FontData, opts: &RenderOptions) -> Vec<String> {
+    render_rows_with(img, font, opts)
 }
 
 #[cfg(test)]
@@ -695,6 +1742,10 @@ mod tests {
             );
             return Err(e);
         }
+        if lhs.info != rhs.info {
+            let e = format!("infos don't match: {:?} != {:?}", lhs.info, rhs.info);
+            return Err(e);
+        }
         if lhs.fudge_factor != rhs.fudge_factor {
             let e = format!(
                 "fudge factors don't match: {} != {}",
@@ -721,8 +1772,7 @@ mod tests {
         let font_bytes = std::fs::read(FONT_PATH).unwrap();
         // actually both good and bad chars`
         let bad_chars: Vec<char> = PRINTABLE_ASCII
-            .into_iter()
-            .chain(BAD_CHARS.into_iter())
+            .chain(BAD_CHARS)
             .map(|n| char::try_from(n).unwrap())
             .collect();
         match FontData::from_font_bytes(&font_bytes, 12.0f32, &bad_chars) {
@@ -747,7 +1797,6 @@ mod tests {
         let end = 256usize;
         let endf = end as f32;
         let charz: Vec<char> = (0usize..end)
-            .into_iter()
             .map(|n| {
                 let pix_val = (n as f32) / endf;
                 data.pixel(pix_val)
@@ -756,6 +1805,33 @@ mod tests {
         println!("{:?}", &charz);
     }
 
+    #[test]
+    fn lookup_table() {
+        let font = test_font(12.0);
+        let mut pruned = font.clone();
+        pruned.prune_for_n_intensities(16);
+
+        // Every 8-bit level, every 16-bit level, a pile of values right
+        // on bucket boundaries, and some out-of-range values.
+        let levels = (0..=255u32)
+            .map(|n| (n as f32) / 255.0)
+            .chain((0..=65535u32).map(|n| (n as f32) / 65535.0))
+            .chain((0..=LUT_SIZE).map(|n| (n as f32) / (LUT_SIZE as f32)))
+            .chain([-1.0, -0.0001, 1.0001, 2.0]);
+
+        for v in levels {
+            for fd in [&font, &pruned] {
+                assert_eq!(fd.pixel(v), fd.pixel_uncached(v), "pixel({})", v);
+                assert_eq!(
+                    fd.pixel_inv(v),
+                    fd.pixel_inv_uncached(v),
+                    "pixel_inv({})",
+                    v
+                );
+            }
+        }
+    }
+
     #[test]
     fn prune() {
         let mut font = test_font(12.0);
@@ -779,9 +1855,277 @@ mod tests {
         let mut cursor = Cursor::new(data);
         let deserialized = FontData::deserialize(&mut cursor).unwrap();
         assert_font_data_are_eq(&font, &deserialized).unwrap();
+
+        let empty = r#"{"values":[],"width":7.0,"height":14.0,"fudge_factor":0.1}"#;
+        assert!(FontData::deserialize(empty.as_bytes()).is_err());
         Ok(())
     }
 
+    #[test]
+    fn font_info() {
+        let font = test_font(12.0);
+        assert_eq!(font.family(), Some("Liberation Mono"));
+        assert_eq!(font.style(), Some("Regular"));
+        assert_eq!(font.pixel_size(), Some(12.0));
+        assert!(font.ascent().unwrap() > 0.0);
+        assert!(font.descent().unwrap() < 0.0);
+        assert!(font.line_gap().is_some());
+
+        let bytes = std::fs::read(FONT_PATH).unwrap();
+        assert_eq!(font.source_hash(), Some(FontData::hash_font_bytes(&bytes)));
+        assert_ne!(
+            font.source_hash(),
+            Some(FontData::hash_font_bytes(&bytes[1..]))
+        );
+
+        // Data from before any of this was recorded.
+        let old =
+            r#"{"values":[[" ",0.0],["@",1.0]],"width":7.0,"height":14.0,"fudge_factor":0.5}"#;
+        let old = FontData::deserialize(old.as_bytes()).unwrap();
+        assert_eq!(old.family(), None);
+        assert_eq!(old.source_hash(), None);
+    }
+
+    #[test]
+    fn subpixel() {
+        let bytes = std::fs::read(FONT_PATH).unwrap();
+        let opts = FontOptions {
+            subpixel: true,
+            ..FontOptions::default()
+        };
+        let font = FontData::from_font_bytes_with(&bytes, 16.0, &printable_ascii(), &opts)
+            .unwrap()
+            .unwrap();
+        assert!(font.has_subpixel());
+        assert!(!test_font(16.0).has_subpixel());
+
+        let bands = font.bands.as_ref().unwrap();
+        for (c, b) in font.values.iter().zip(bands.iter()) {
+            let mean = (b[0] + b[1] + b[2]) / 3.0;
+            assert!((mean - c.val).abs() < 1e-4, "{:?}: {:?}", c, b);
+        }
+
+        // Light on the left should look different from light on the right.
+        let left = font.subpixel([1.0, 0.0, 0.0]);
+        let right = font.subpixel([0.0, 0.0, 1.0]);
+        assert_ne!(left, right);
+        assert_eq!(font.subpixel_inv([0.0, 1.0, 1.0]), left);
+        assert_eq!(font.subpixel([0.0; 3]), ' ');
+
+        let mut pruned = font.clone();
+        pruned.prune_for_n_intensities(16);
+        assert_eq!(pruned.bands.as_ref().unwrap().len(), pruned.values.len());
+
+        for encoding in [Encoding::Binary, Encoding::Json] {
+            let mut data: Vec<u8> = Vec::new();
+            font.save(&mut data, encoding).unwrap();
+            let loaded = FontData::load(data.as_slice()).unwrap();
+            assert_eq!(loaded.bands, font.bands);
+        }
+    }
+
+    #[test]
+    fn render_options() {
+        let font = test_font(12.0);
+        let (fw, fh) = font.geometry();
+        let f = std::fs::File::open(IMAGE_PATH).unwrap();
+        let img = Image::auto(BufReader::new(f)).unwrap();
+
+        let render = |opts: &RenderOptions| {
+            let mut out: Vec<u8> = Vec::new();
+            write_with(&img, &font, opts, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let dims = |text: &str| {
+            let lines: Vec<&str> = text.lines().collect();
+            (lines[0].chars().count(), lines.len())
+        };
+
+        let mut plain: Vec<u8> = Vec::new();
+        write(&img, &font, &mut plain).unwrap();
+        assert_eq!(render(&RenderOptions::new()).as_bytes(), plain.as_slice());
+
+        let mut inverted: Vec<u8> = Vec::new();
+        write_inverted(&img, &font, &mut inverted).unwrap();
+        let opts = RenderOptions {
+            invert: true,
+            ..RenderOptions::default()
+        };
+        assert_eq!(render(&opts).as_bytes(), inverted.as_slice());
+
+        let (img_w, img_h) = img.geometry();
+        let (w, h) = dims(&render(&RenderOptions::terminal()));
+        assert_eq!(
+            (w, h),
+            ((img_w / fw) as usize, (img_h / (2.0 * fw)) as usize)
+        );
+
+        let opts = RenderOptions {
+            cell_width: Some(4.0),
+            cell_height: Some(8.0),
+            line_height: Some(3.0),
+            ..RenderOptions::default()
+        };
+        assert_eq!(opts.cell_size(&font), (4.0, 8.0));
+        let (w, h) = dims(&render(&opts));
+        assert_eq!((w, h), ((img_w / 4.0) as usize, (img_h / 8.0) as usize));
+
+        let opts = RenderOptions {
+            line_height: Some(1.5),
+            letter_spacing: 2.0,
+            ..RenderOptions::default()
+        };
+        assert_eq!(opts.cell_size(&font), (fw + 2.0, 18.0));
+
+        // Nonsense gets ignored.
+        let opts = RenderOptions {
+            cell_width: Some(0.0),
+            cell_aspect: Some(-1.0),
+            letter_spacing: -100.0,
+            ..RenderOptions::default()
+        };
+        assert_eq!(opts.cell_size(&font), (fw, fh));
+
+        // Fitting to a number of columns or rows keeps the cells' shape.
+        let terminal = RenderOptions::terminal();
+        let fit = terminal.fit(&font, img.dimensions(), Some(40), None);
+        let (cw, ch) = fit.cell_size(&font);
+        assert!((ch / cw - 2.0).abs() < 0.001);
+        assert_eq!(dims(&render(&fit)).0, 40);
+        let fit = terminal.fit(&font, img.dimensions(), None, Some(10));
+        assert_eq!(dims(&render(&fit)).1, 10);
+        for (cols, rows) in [(40, 10), (200, 30), (30, 200)] {
+            let fit = terminal.fit(&font, img.dimensions(), Some(cols), Some(rows));
+            let (w, h) = dims(&render(&fit));
+            let (cols, rows) = (cols as usize, rows as usize);
+            assert!(w <= cols && h <= rows);
+            assert!(w == cols || h == rows);
+        }
+    }
+
+    /*
+    Bundle the given fonts into a font collection (.ttc) file.
+    */
+    fn make_collection(fonts: &[&[u8]]) -> Vec<u8> {
+        let be16 = |b: &[u8], n: usize| u16::from_be_bytes([b[n], b[n + 1]]) as usize;
+        let be32 = |b: &[u8], n: usize| u32::from_be_bytes([b[n], b[n + 1], b[n + 2], b[n + 3]]);
+
+        let dir_len = |f: &[u8]| 12 + 16 * be16(f, 4);
+        let header_len = 12 + 4 * fonts.len();
+        let dirs_len: usize = fonts.iter().map(|f| dir_len(f)).sum();
+
+        let mut out: Vec<u8> = Vec::new();
+        out.extend_from_slice(b"ttcf");
+        out.extend_from_slice(&[0, 1, 0, 0]);
+        out.extend_from_slice(&(fonts.len() as u32).to_be_bytes());
+
+        // Each font's table directory, with its table offsets adjusted to
+        // point to where its whole original file goes after all of them.
+        let mut dirs: Vec<u8> = Vec::new();
+        let mut data_start = header_len + dirs_len;
+        for f in fonts.iter() {
+            out.extend_from_slice(&((header_len + dirs.len()) as u32).to_be_bytes());
+            let mut dir = f[..dir_len(f)].to_vec();
+            for t in 0..be16(f, 4) {
+                let n = 12 + 16 * t + 8;
+                let offset = be32(f, n) + data_start as u32;
+                dir[n..n + 4].copy_from_slice(&offset.to_be_bytes());
+            }
+            dirs.extend_from_slice(&dir);
+            data_start += f.len();
+        }
+        out.extend_from_slice(&dirs);
+        for f in fonts.iter() {
+            out.extend_from_slice(f);
+        }
+        out
+    }
+
+    #[test]
+    fn font_options() {
+        let chars = printable_ascii();
+        let liberation = std::fs::read(FONT_PATH).unwrap();
+        let iosevka = std::fs::read("test/Iosevka.ttf").unwrap();
+
+        assert_eq!(FontOptions::face_count(&liberation), 1);
+        assert!(FontOptions::axes(&liberation, 0).unwrap().is_empty());
+        assert!(FontOptions::named_instances(&liberation, 0)
+            .unwrap()
+            .is_empty());
+
+        let ttc = make_collection(&[&liberation, &iosevka]);
+        assert_eq!(FontOptions::face_count(&ttc), 2);
+        for (face_index, original) in [(0, &liberation), (1, &iosevka)] {
+            let opts = FontOptions {
+                face_index,
+                ..FontOptions::default()
+            };
+            let from_ttc = FontData::from_font_bytes_with(&ttc, 12.0, &chars, &opts)
+                .unwrap()
+                .unwrap();
+            let alone = FontData::from_font_bytes(original, 12.0, &chars)
+                .unwrap()
+                .unwrap();
+            assert_eq!(from_ttc.family(), alone.family());
+            assert_eq!(from_ttc.values, alone.values);
+        }
+
+        let bad = |opts: FontOptions| {
+            matches!(
+                FontData::from_font_bytes_with(&ttc, 12.0, &chars, &opts),
+                Err(Error::InvalidFontOption(_))
+            )
+        };
+        assert!(bad(FontOptions {
+            face_index: 2,
+            ..FontOptions::default()
+        }));
+        assert!(bad(FontOptions {
+            instance: Some("Bold".to_owned()),
+            ..FontOptions::default()
+        }));
+        assert!(bad(FontOptions {
+            variations: vec![(*b"wght", 800.0)],
+            ..FontOptions::default()
+        }));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let font = test_font(12.0);
+
+        let mut binary: Vec<u8> = Vec::new();
+        font.save(&mut binary, Encoding::Binary).unwrap();
+        let loaded = FontData::load(binary.as_slice()).unwrap();
+        assert_font_data_are_eq(&font, &loaded).unwrap();
+
+        let mut json: Vec<u8> = Vec::new();
+        font.save(&mut json, Encoding::Json).unwrap();
+        let loaded = FontData::load(json.as_slice()).unwrap();
+        assert_font_data_are_eq(&font, &loaded).unwrap();
+        assert!(binary.len() < json.len());
+
+        let mut legacy: Vec<u8> = Vec::new();
+        font.serialize(&mut legacy).unwrap();
+        let loaded = FontData::load(legacy.as_slice()).unwrap();
+        assert_font_data_are_eq(&font, &loaded).unwrap();
+
+        // Fields from the future get skipped.
+        let mut payload = font.to_binary();
+        payload.extend_from_slice(&[99, 3, 0, 0, 0, 1, 2, 3]);
+        let mut extended: Vec<u8> = Vec::new();
+        format::write_container(&mut extended, FONT_DATA_MAGIC, Encoding::Binary, &payload)
+            .unwrap();
+        let loaded = FontData::load(extended.as_slice()).unwrap();
+        assert_font_data_are_eq(&font, &loaded).unwrap();
+
+        binary[4] = 0xff;
+        assert!(matches!(
+            FontData::load(binary.as_slice()),
+            Err(Error::UnsupportedVersion(0xff))
+        ));
+    }
+
     #[test]
     fn load_image() {
         {
@@ -800,6 +2144,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn contrast() {
+        let buff = ImageBuffer::from_raw(4, 1, vec![0.0, 0.25, 0.5, 0.9]).unwrap();
+        let img = Image { buff };
+
+        let more = img.with_contrast(2.0);
+        assert_eq!(more.buff.as_raw(), &vec![0.0, 0.0, 0.5, 1.0]);
+        let less = img.with_contrast(0.5);
+        assert_eq!(less.buff.as_raw(), &vec![0.25, 0.375, 0.5, 0.7]);
+        let same = img.with_contrast(1.0);
+        assert_eq!(same.buff.as_raw(), img.buff.as_raw());
+    }
+
+    #[test]
+    fn resampling() {
+        use image::imageops::{resize, FilterType};
+
+        let f = std::fs::File::open(IMAGE_PATH).unwrap();
+        let decoded = image::io::Reader::new(BufReader::new(f))
+            .with_guessed_format()
+            .unwrap()
+            .decode()
+            .unwrap();
+
+        let buff = to_intensities(&decoded);
+        assert_eq!(buff, decoded.to_luma32f());
+
+        let (w, h) = buff.dimensions();
+        for (nw, nh) in [(w / 7, h / 13), (w / 3, h), (w * 2, h / 2), (1, 1)] {
+            let ours = resize_nearest(&buff, nw, nh);
+            let theirs = resize(&buff, nw, nh, FilterType::Nearest);
+            assert_eq!(ours, theirs, "resizing to {} x {}", nw, nh);
+        }
+    }
+
     #[test]
     fn to_writer() {
         let mut v: Vec<u8> = Vec::new();
@@ -0,0 +1,438 @@
+/*!
+Collections of `FontData` for several fonts at several sizes.
+
+This is what gets generated on a machine with a bunch of fonts installed
+(by `librarify`, for example) and shipped to somewhere that doesn't have
+them (like `aa_cgi`).
+*/
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::format::{self, Encoding, FieldWriter, Fields, Payload, Reader};
+use crate::{Error, FontData, FontDataRepr};
+
+const LIBRARY_MAGIC: &[u8; 4] = b"AAFL";
+
+// Field tags for the binary encoding. The payload is a series of
+// `TAG_ENTRY` fields, each of which contains the other fields.
+const TAG_ENTRY: u8 = 1;
+const TAG_FAMILY: u8 = 1;
+const TAG_SIZE: u8 = 2;
+const TAG_FONT: u8 = 3;
+const TAG_SOURCE: u8 = 4;
+const TAG_CHARSET: u8 = 5;
+const TAG_CREATED: u8 = 6;
+const TAG_REJECTED: u8 = 7;
+
+/**
+Information about where an entry in a `FontLibrary` came from.
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EntryInfo {
+    /// The path of the font file the entry was generated from, if known.
+    pub source: Option<String>,
+    /// The characters the entry was generated from.
+    pub charset: String,
+    /// The characters in `charset` the font had no glyphs for (and so
+    /// aren't in the entry's `FontData`).
+    #[serde(default)]
+    pub rejected: String,
+    /// When the entry was generated, in seconds since the Unix epoch,
+    /// if known.
+    pub created: Option<u64>,
+}
+
+impl EntryInfo {
+    /**
+    Info for an entry generated just now from the given characters of the
+    font file at `source`, of which the font had no glyphs for `rejected`.
+    */
+    pub fn new(source: Option<&str>, chars: &[char], rejected: &[char]) -> EntryInfo {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+
+        EntryInfo {
+            source: source.map(String::from),
+            charset: chars.iter().collect(),
+            rejected: rejected.iter().collect(),
+            created,
+        }
+    }
+}
+
+/**
+A `FontData` in a `FontLibrary`, along with information about where it
+came from.
+*/
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LibraryEntry {
+    font: FontData,
+    info: EntryInfo,
+}
+
+impl LibraryEntry {
+    /// The `FontData` itself.
+    pub fn font(&self) -> &FontData {
+        &self.font
+    }
+
+    /// Where the `FontData` came from.
+    pub fn info(&self) -> &EntryInfo {
+        &self.info
+    }
+}
+
+/**
+A collection of `FontData` keyed by font family name and pixel size.
+*/
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FontLibrary {
+    families: BTreeMap<String, BTreeMap<u16, LibraryEntry>>,
+}
+
+impl FontLibrary {
+    /// Return a new, empty library.
+    pub fn new() -> FontLibrary {
+        FontLibrary::default()
+    }
+
+    /**
+    Add `font` to the library as `family` at pixel size `size`, returning
+    whatever entry it replaces (if any).
+    */
+    pub fn insert(
+        &mut self,
+        family: &str,
+        size: u16,
+        font: FontData,
+        info: EntryInfo,
+    ) -> Option<LibraryEntry> {
+        self.families
+            .entry(family.to_owned())
+            .or_default()
+            .insert(size, LibraryEntry { font, info })
+    }
+
+    /// Remove and return the entry for `family` at `size` (if any).
+    pub fn remove(&mut self, family: &str, size: u16) -> Option<LibraryEntry> {
+        let sizes = self.families.get_mut(family)?;
+        let entry = sizes.remove(&size);
+        if sizes.is_empty() {
+            self.families.remove(family);
+        }
+        entry
+    }
+
+    /// Return the entry for `family` at exactly `size` (if any).
+    pub fn entry(&self, family: &str, size: u16) -> Option<&LibraryEntry> {
+        self.families.get(family)?.get(&size)
+    }
+
+    /// Return the `FontData` for `family` at exactly `size` (if any).
+    pub fn get(&self, family: &str, size: u16) -> Option<&FontData> {
+        self.entry(family, size).map(|e| &e.font)
+    }
+
+    /**
+    Return the `FontData` for `family` at whichever available size is
+    closest to `size`, along with that size. Ties go to the smaller size.
+    */
+    pub fn nearest(&self, family: &str, size: u16) -> Option<(u16, &FontData)> {
+        self.families
+            .get(family)?
+            .iter()
+            .min_by_key(|(s, _)| s.abs_diff(size))
+            .map(|(s, e)| (*s, &e.font))
+    }
+
+    /// Iterate over the names of the families in the library, in order.
+    pub fn families(&self) -> impl Iterator<Item = &str> {
+        self.families.keys().map(|k| k.as_str())
+    }
+
+    /// Return the sizes available for `family`, in order.
+    pub fn sizes(&self, family: &str) -> Vec<u16> {
+        match self.families.get(family) {
+            Some(sizes) => sizes.keys().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Iterate over `(family, size, entry)` for every entry, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16, &LibraryEntry)> {
+        self.families.iter().flat_map(|(family, sizes)| {
+            sizes
+                .iter()
+                .map(move |(size, entry)| (family.as_str(), *size, entry))
+        })
+    }
+
+    /// The total number of entries (of all families) in the library.
+    pub fn len(&self) -> usize {
+        self.families.values().map(|sizes| sizes.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.families.is_empty()
+    }
+
+    /**
+    Add all the entries from `other` to the receiver. Where both have an
+    entry for the same family and size, `other`'s wins.
+    */
+    pub fn merge(&mut self, other: FontLibrary) {
+        for (family, sizes) in other.families.into_iter() {
+            self.families.entry(family).or_default().extend(sizes);
+        }
+    }
+
+    /**
+    Save the receiver in this crate's versioned file format (see
+    `FontData::save()`).
+    */
+    pub fn save<W: Write>(&self, writer: W, encoding: Encoding) -> Result<(), Error> {
+        let payload = match encoding {
+            Encoding::Binary => self.to_binary(),
+            Encoding::Json => {
+                serde_json::to_vec(self).map_err(|e| Error::IOError(format!("{}", &e)))?
+            }
+        };
+        format::write_container(writer, LIBRARY_MAGIC, encoding, &payload)
+    }
+
+    /**
+    Save the receiver as a plain JSON document, without the container's
+    header and checksum, for tools that expect JSON (like `jq`). `.load()`
+    reads this too.
+    */
+    pub fn save_json<W: Write>(&self, writer: W) -> Result<(), Error> {
+        serde_json::to_writer(writer, self).map_err(|e| Error::IOError(format!("{}", &e)))
+    }
+
+    /**
+    Load a library previously saved with `.save()` (in either encoding)
+    or `.save_json()`.
+
+    This also reads the bare JSON
+    `HashMap<String, HashMap<u16, FontData>>` that older versions of
+    `librarify` wrote; entries loaded that way have empty `EntryInfo`.
+    */
+    pub fn load<R: Read>(reader: R) -> Result<FontLibrary, Error> {
+        match format::read_container(reader, LIBRARY_MAGIC)? {
+            Payload::Encoded(Encoding::Binary, data) => FontLibrary::from_binary(&data),
+            Payload::Encoded(Encoding::Json, data) => {
+                serde_json::from_slice(&data).map_err(|e| format::corrupt(format!("{}", &e)))
+            }
+            Payload::Legacy(data) => {
+                if let Ok(lib) = serde_json::from_slice::<FontLibrary>(&data) {
+                    return Ok(lib);
+                }
+                let map: HashMap<String, HashMap<u16, FontData>> = serde_json::from_slice(&data)
+                    .map_err(|e| format::corrupt(format!("{}", &e)))?;
+                let mut lib = FontLibrary::new();
+                for (family, sizes) in map.into_iter() {
+                    for (size, font) in sizes.into_iter() {
+                        lib.insert(&family, size, font, EntryInfo::default());
+                    }
+                }
+                Ok(lib)
+            }
+        }
+    }
+
+    fn to_binary(&self) -> Vec<u8> {
+        let mut w = FieldWriter::new();
+        for (family, size, entry) in self.iter() {
+            let mut e = FieldWriter::new();
+            e.str(TAG_FAMILY, family)
+                .u16(TAG_SIZE, size)
+                .bytes(TAG_FONT, &entry.font.to_binary())
+                .str(TAG_CHARSET, &entry.info.charset);
+            if !entry.info.rejected.is_empty() {
+                e.str(TAG_REJECTED, &entry.info.rejected);
+            }
+            if let Some(source) = &entry.info.source {
+                e.str(TAG_SOURCE, source);
+            }
+            if let Some(created) = entry.info.created {
+                e.u64(TAG_CREATED, created);
+            }
+            w.bytes(TAG_ENTRY, &e.finish());
+        }
+        w.finish()
+    }
+
+    fn from_binary(data: &[u8]) -> Result<FontLibrary, Error> {
+        let mut lib = FontLibrary::new();
+
+        for field in Fields::new(data) {
+            let (tag, data) = field?;
+            if tag != TAG_ENTRY {
+                continue;
+            }
+
+            let mut family: Option<&str> = None;
+            let mut size: Option<u16> = None;
+            let mut font: Option<FontData> = None;
+            let mut info = EntryInfo::default();
+
+            for field in Fields::new(data) {
+                let (tag, data) = field?;
+                let mut r = Reader::new(data);
+                match tag {
+                    TAG_FAMILY => family = Some(r.str()?),
+                    TAG_SIZE => size = Some(r.u16()?),
+                    TAG_FONT => font = Some(FontData::try_from(FontDataRepr::from_binary(data)?)?),
+                    TAG_SOURCE => info.source = Some(r.str()?.to_owned()),
+                    TAG_CHARSET => info.charset = r.str()?.to_owned(),
+                    TAG_CREATED => info.created = Some(r.u64()?),
+                    TAG_REJECTED => info.rejected = r.str()?.to_owned(),
+                    // Something added by a later version; skip it.
+                    _ => {}
+                }
+            }
+
+            match (family, size, font) {
+                (Some(family), Some(size), Some(font)) => {
+                    lib.insert(family, size, font, info);
+                }
+                _ => {
+                    return Err(format::corrupt("library entry is missing a field"));
+                }
+            }
+        }
+
+        Ok(lib)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printable_ascii;
+
+    const FONT_PATH: &str = "test/LiberationMono-Regular.ttf";
+
+    fn test_library() -> FontLibrary {
+        let font_bytes = std::fs::read(FONT_PATH).unwrap();
+        let mut chars = printable_ascii();
+        chars.push('\u{2603}');
+        let mut lib = FontLibrary::new();
+        for size in [8, 12, 16] {
+            let (fd, rejected) = FontData::from_font_bytes(&font_bytes, size as f32, &chars)
+                .unwrap()
+                .unwrap_err();
+            assert_eq!(rejected, vec!['\u{2603}']);
+            lib.insert(
+                "Liberation Mono",
+                size,
+                fd,
+                EntryInfo::new(Some(FONT_PATH), &chars, &rejected),
+            );
+        }
+        lib
+    }
+
+    fn assert_libraries_are_eq(lhs: &FontLibrary, rhs: &FontLibrary) {
+        assert_eq!(lhs.len(), rhs.len());
+        for ((lf, ls, le), (rf, rs, re)) in lhs.iter().zip(rhs.iter()) {
+            assert_eq!((lf, ls, le.info()), (rf, rs, re.info()));
+            assert_eq!(le.font().geometry(), re.font().geometry());
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        let mut lib = test_library();
+        assert_eq!(lib.len(), 3);
+        assert_eq!(lib.families().collect::<Vec<_>>(), vec!["Liberation Mono"]);
+        assert_eq!(lib.sizes("Liberation Mono"), vec![8, 12, 16]);
+        assert!(lib.sizes("Comic Sans").is_empty());
+
+        assert!(lib.get("Liberation Mono", 12).is_some());
+        assert_eq!(
+            lib.entry("Liberation Mono", 12).unwrap().info().rejected,
+            "\u{2603}"
+        );
+        assert!(lib.get("Liberation Mono", 11).is_none());
+        assert_eq!(lib.nearest("Liberation Mono", 11).unwrap().0, 12);
+        assert_eq!(lib.nearest("Liberation Mono", 10).unwrap().0, 8);
+        assert_eq!(lib.nearest("Liberation Mono", 100).unwrap().0, 16);
+        assert!(lib.nearest("Comic Sans", 12).is_none());
+
+        let mut other = FontLibrary::new();
+        let fd = lib.get("Liberation Mono", 8).unwrap().clone();
+        other.insert("Other Mono", 8, fd.clone(), EntryInfo::default());
+        other.insert("Liberation Mono", 12, fd, EntryInfo::default());
+        lib.merge(other);
+        assert_eq!(lib.len(), 4);
+        assert_eq!(
+            lib.entry("Liberation Mono", 12).unwrap().info().source,
+            None
+        );
+
+        assert!(lib.remove("Other Mono", 8).is_some());
+        assert_eq!(lib.families().count(), 1);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let lib = test_library();
+
+        for encoding in [Encoding::Binary, Encoding::Json] {
+            let mut data: Vec<u8> = Vec::new();
+            lib.save(&mut data, encoding).unwrap();
+            let loaded = FontLibrary::load(data.as_slice()).unwrap();
+            assert_libraries_are_eq(&lib, &loaded);
+        }
+
+        let mut data: Vec<u8> = Vec::new();
+        lib.save_json(&mut data).unwrap();
+        assert!(serde_json::from_slice::<serde_json::Value>(&data).is_ok());
+        let loaded = FontLibrary::load(data.as_slice()).unwrap();
+        assert_libraries_are_eq(&lib, &loaded);
+
+        // Entries from before `rejected` was recorded.
+        let mut value = serde_json::to_value(&lib).unwrap();
+        for sizes in value["families"].as_object_mut().unwrap().values_mut() {
+            for entry in sizes.as_object_mut().unwrap().values_mut() {
+                entry["info"].as_object_mut().unwrap().remove("rejected");
+            }
+        }
+        let data = serde_json::to_vec(&value).unwrap();
+        let loaded = FontLibrary::load(data.as_slice()).unwrap();
+        assert_eq!(loaded.len(), lib.len());
+        assert_eq!(
+            loaded.entry("Liberation Mono", 8).unwrap().info().rejected,
+            ""
+        );
+
+        // The format older versions of `librarify` wrote.
+        let mut legacy: HashMap<String, HashMap<u16, FontData>> = HashMap::new();
+        for (family, size, entry) in lib.iter() {
+            legacy
+                .entry(family.to_owned())
+                .or_default()
+                .insert(size, entry.font().clone());
+        }
+        let data = serde_json::to_vec(&legacy).unwrap();
+        let loaded = FontLibrary::load(data.as_slice()).unwrap();
+        assert_eq!(loaded.len(), lib.len());
+        assert_eq!(loaded.sizes("Liberation Mono"), vec![8, 12, 16]);
+
+        // A `FontData` isn't a library.
+        let mut data: Vec<u8> = Vec::new();
+        lib.get("Liberation Mono", 8)
+            .unwrap()
+            .save(&mut data, Encoding::Binary)
+            .unwrap();
+        assert!(FontLibrary::load(data.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+/*!
+Options for how text gets laid out over an image.
+*/
+
+use crate::FontData;
+
+/**
+Options controlling how an image is rendered as text by `write_with()`
+and `write_streaming_with()`.
+
+Each character of output stands in for a rectangular "cell" of the
+image. By default, cells are the size of the font's glyphs: as wide as
+its widest advance and as tall as its height plus line gap. But the
+program that eventually displays the text will use its own spacing
+(terminals have fixed cell sizes, browsers apply `line-height` and
+`letter-spacing`, &c), and the output will only have the proportions of
+the original image if the cells here match the cells there.
+
+In order of precedence, the height of a cell is
+
+  * `cell_height`, if set
+  * `cell_aspect` times the width of a cell, if set
+  * `line_height` times the font's pixel size (like CSS's unitless
+    `line-height`), if set
+  * the height of the font
+
+and the width of a cell is `cell_width`, if set, otherwise the width of
+the font plus `letter_spacing`.
+
+```
+# let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+# let font = ascii_art::FontData::from_font_bytes(
+#     &bytes, 12.0, &ascii_art::printable_ascii()
+# ).unwrap().unwrap();
+use ascii_art::RenderOptions;
+
+// Text for a web page styled with `line-height: 1.2; letter-spacing: 1px`.
+let opts = RenderOptions {
+    line_height: Some(1.2),
+    letter_spacing: 1.0,
+    ..RenderOptions::default()
+};
+let (w, h) = opts.cell_size(&font);
+assert_eq!(w, font.geometry().0 + 1.0);
+assert_eq!(h, 12.0 * 1.2);
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderOptions {
+    /// Render dark text on a light background (as `write_inverted()`).
+    pub invert: bool,
+    /// The width (in image pixels) of each cell.
+    pub cell_width: Option<f32>,
+    /// The height (in image pixels) of each cell.
+    pub cell_height: Option<f32>,
+    /// The ratio of each cell's height to its width.
+    pub cell_aspect: Option<f32>,
+    /// The height of each line, as a multiple of the font's pixel size.
+    pub line_height: Option<f32>,
+    /// Extra space (in pixels) between characters.
+    pub letter_spacing: f32,
+    /// Match the left, middle, and right thirds of each cell separately
+    /// (see `FontData::subpixel()`). This only makes a difference with
+    /// `FontData` made with `FontOptions::subpixel` set.
+    pub subpixel: bool,
+    /// Look for regions of the image that look like text (captions,
+    /// labels, screenshots of documents) and render them in stark black
+    /// and white, matching glyphs to each third of a cell where the font
+    /// allows it, so the text stays readable. The rest of the image is
+    /// shaded as usual. (`write_streaming_with()` doesn't support this.)
+    pub preserve_text: bool,
+}
+
+impl RenderOptions {
+    /// The aspect ratio (height / width) of a typical terminal cell.
+    pub const TERMINAL_ASPECT: f32 = 2.0;
+
+    /// Return the default options, which render exactly like `write()`.
+    pub fn new() -> RenderOptions {
+        RenderOptions::default()
+    }
+
+    /**
+    Options for text that will be viewed in a terminal: cells are as wide
+    as the font, and `RenderOptions::TERMINAL_ASPECT` times as tall as
+    they are wide, regardless of the font's own line height.
+    */
+    pub fn terminal() -> RenderOptions {
+        RenderOptions {
+            cell_aspect: Some(RenderOptions::TERMINAL_ASPECT),
+            ..RenderOptions::default()
+        }
+    }
+
+    /**
+    Return the width and height (in image pixels) of the area each
+    character of output will cover when rendering with `font`.
+
+    Settings that would make for cells with no (or negative) size are
+    ignored.
+    */
+    pub fn cell_size(&self, font: &FontData) -> (f32, f32) {
+        let (font_w, font_h) = font.geometry();
+        let positive = |x: &f32| *x > 0.0;
+
+        let width = self
+            .cell_width
+            .filter(positive)
+            .or_else(|| Some(font_w + self.letter_spacing).filter(positive))
+            .unwrap_or(font_w);
+
+        let height = self
+            .cell_height
+            .filter(positive)
+            .or_else(|| self.cell_aspect.map(|a| a * width).filter(positive))
+            .or_else(|| {
+                let em = font.pixel_size().unwrap_or(font_h);
+                self.line_height.map(|m| m * em).filter(positive)
+            })
+            .unwrap_or(font_h);
+
+        (width, height)
+    }
+
+    /**
+    Return options that render an image of the given `size` (in pixels)
+    at most `cols` characters wide and `rows` lines tall (either or both
+    of which may be given), as large as will fit, with cells the same
+    shape as the receiver's.
+    */
+    pub fn fit(
+        &self,
+        font: &FontData,
+        size: (u32, u32),
+        cols: Option<u32>,
+        rows: Option<u32>,
+    ) -> RenderOptions {
+        let (cell_w, cell_h) = self.cell_size(font);
+        let aspect = cell_h / cell_w;
+        let (img_w, img_h) = (size.0 as f32, size.1 as f32);
+
+        let by_cols = cols.filter(|c| *c > 0).map(|c| img_w / c as f32);
+        let by_rows = rows.filter(|r| *r > 0).map(|r| img_h / (r as f32 * aspect));
+        let width = match (by_cols, by_rows) {
+            (Some(a), Some(b)) => a.max(b),
+            (Some(a), None) | (None, Some(a)) => a,
+            (None, None) => return self.clone(),
+        };
+        // A hair smaller, so rounding never costs a column or row.
+        let width = width * (1.0 - 1e-6);
+
+        RenderOptions {
+            cell_width: Some(width),
+            cell_height: Some(width * aspect),
+            ..self.clone()
+        }
+    }
+}
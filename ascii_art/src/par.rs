@@ -0,0 +1,80 @@
+/*!
+Row-at-a-time helpers that run in parallel when the `rayon` feature is
+enabled and serially otherwise.
+
+Everything that touches every pixel (or every output character) goes
+through these, so that both builds run exactly the same per-row code
+and produce exactly the same output; the only difference is how many
+rows are in flight at once.
+*/
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/*
+Call `f(y, row)` for each `width`-long row of `buff`, where `y` is the
+index of the row.
+*/
+pub(crate) fn fill_rows<T, F>(buff: &mut [T], width: usize, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Send + Sync,
+{
+    if width == 0 {
+        return;
+    }
+
+    #[cfg(feature = "rayon")]
+    buff.par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(y, row)| f(y, row));
+
+    #[cfg(not(feature = "rayon"))]
+    buff.chunks_mut(width)
+        .enumerate()
+        .for_each(|(y, row)| f(y, row));
+}
+
+/*
+Return the results of calling `f(y, row)` for each `width`-long row of
+`buff`, in order.
+*/
+pub(crate) fn map_rows<T, U, F>(buff: &[T], width: usize, f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(usize, &[T]) -> U + Send + Sync,
+{
+    if width == 0 {
+        return Vec::new();
+    }
+
+    #[cfg(feature = "rayon")]
+    return buff
+        .par_chunks(width)
+        .enumerate()
+        .map(|(y, row)| f(y, row))
+        .collect();
+
+    #[cfg(not(feature = "rayon"))]
+    return buff
+        .chunks(width)
+        .enumerate()
+        .map(|(y, row)| f(y, row))
+        .collect();
+}
+
+/*
+Return the results of calling `f(n)` for each `n` in `0..count`, in order.
+*/
+pub(crate) fn map_range<U, F>(count: usize, f: F) -> Vec<U>
+where
+    U: Send,
+    F: Fn(usize) -> U + Send + Sync,
+{
+    #[cfg(feature = "rayon")]
+    return (0..count).into_par_iter().map(f).collect();
+
+    #[cfg(not(feature = "rayon"))]
+    return (0..count).map(f).collect();
+}
@@ -0,0 +1,953 @@
+/*!
+Rendering images without holding the whole thing in memory at once.
+
+`write()` and friends decode the entire image, convert the entire thing
+to floating-point intensities, and then resize it into another buffer.
+That's fine for photos, but for a 20000 by 20000 pixel scan it amounts to
+gigabytes. The `TextRows` iterator instead reads the image a source row
+at a time, averaging each band of rows that falls under a row of
+characters down to one intensity per character as it goes, and yields
+each row of text as soon as its band is complete.
+
+How much of the image actually needs to be in memory depends on the
+format:
+
+  * PNG and binary PNM (.pgm and .ppm) images are decoded a row at a time;
+    only one row of source pixels is ever held.
+  * JPEG images are decoded at a reduced scale (1/2, 1/4, or 1/8 size,
+    whichever is the smallest that still has at least one pixel for each
+    output character), which is about as good as JPEG allows.
+  * Everything else is decoded all at once, but in its native 8- or
+    16-bit format, without the additional four-bytes-per-pixel
+    floating-point copy.
+
+Because each character is the average of all the pixels under it,
+rather than just the pixel nearest its center, the results will differ
+somewhat from (and will generally be smoother than) those of `write()`.
+
+Finding text takes the image's pixels, not just their averages, so
+`RenderOptions::preserve_text` isn't supported here; asking for it is an
+error (`Error::UnsupportedOption`) rather than being quietly ignored.
+*/
+#[cfg(any(feature = "jpeg", feature = "png"))]
+use std::io::Read;
+use std::io::{BufRead, BufWriter, Seek, SeekFrom, Write};
+
+use image::{DynamicImage, ImageFormat};
+
+use crate::{Error, FontData, RenderOptions};
+
+fn io_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::IOError(format!("{}", &e))
+}
+
+/*
+Storage format of each channel of a pixel of raw decoded data.
+*/
+#[derive(Clone, Copy, Debug)]
+enum Depth {
+    U8,
+    // Only the formats we decode ourselves use big-endian samples.
+    #[cfg_attr(
+        not(any(feature = "jpeg", feature = "png", feature = "pnm")),
+        allow(dead_code)
+    )]
+    U16Be,
+    U16Ne,
+    F32Ne,
+}
+
+/*
+Describes how a row of raw decoded pixel data is laid out, so it can be
+converted to intensities.
+*/
+#[derive(Clone, Copy, Debug)]
+struct Layout {
+    /// 1 (gray), 2 (gray + alpha), 3 (RGB), or 4 (RGBA)
+    channels: usize,
+    depth: Depth,
+    /// sample value that represents full intensity
+    max: f32,
+}
+
+impl Layout {
+    fn new(channels: usize, depth: Depth) -> Layout {
+        let max = match depth {
+            Depth::U8 => u8::MAX as f32,
+            Depth::U16Be | Depth::U16Ne => u16::MAX as f32,
+            Depth::F32Ne => 1.0,
+        };
+        Layout {
+            channels,
+            depth,
+            max,
+        }
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        let bytes_per_channel = match self.depth {
+            Depth::U8 => 1,
+            Depth::U16Be | Depth::U16Ne => 2,
+            Depth::F32Ne => 4,
+        };
+        self.channels * bytes_per_channel
+    }
+
+    fn sample(&self, raw: &[u8], n: usize) -> f32 {
+        let v = match self.depth {
+            Depth::U8 => raw[n] as f32,
+            Depth::U16Be => u16::from_be_bytes([raw[2 * n], raw[2 * n + 1]]) as f32,
+            Depth::U16Ne => u16::from_ne_bytes([raw[2 * n], raw[2 * n + 1]]) as f32,
+            Depth::F32Ne => {
+                let b = &raw[4 * n..4 * n + 4];
+                f32::from_ne_bytes([b[0], b[1], b[2], b[3]])
+            }
+        };
+        v / self.max
+    }
+
+    /*
+    Convert a row of raw pixel data to intensities in `out`, weighting
+    the color channels the same way `image` does when it converts to
+    grayscale, and ignoring alpha.
+    */
+    fn convert_row(&self, raw: &[u8], out: &mut [f32]) {
+        for (n, p) in out.iter_mut().enumerate() {
+            let base = n * self.channels;
+            let v = if self.channels < 3 {
+                self.sample(raw, base)
+            } else {
+                0.2126 * self.sample(raw, base)
+                    + 0.7152 * self.sample(raw, base + 1)
+                    + 0.0722 * self.sample(raw, base + 2)
+            };
+            *p = v.clamp(0.0, 1.0);
+        }
+    }
+}
+
+/*
+Something that produces an image's intensities one row at a time, from
+the top down.
+*/
+trait RowSource {
+    /// Width and height (in pixels) of the rows this will produce.
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Width and height (in pixels) of the original image, if the source
+    /// is producing it at a reduced size.
+    fn full_dimensions(&self) -> (u32, u32) {
+        self.dimensions()
+    }
+
+    /// Fill `buff` (which is `.dimensions().0` long) with the intensities
+    /// of the next row.
+    fn next_row(&mut self, buff: &mut [f32]) -> Result<(), Error>;
+}
+
+/*
+Rows from an image that has already been decoded in its entirety.
+*/
+struct DecodedSource {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    full: (u32, u32),
+    layout: Layout,
+    y: usize,
+}
+
+impl DecodedSource {
+    fn from_image(img: DynamicImage) -> Result<DecodedSource, Error> {
+        use image::ColorType;
+
+        let (channels, depth) = match img.color() {
+            ColorType::L8 => (1, Depth::U8),
+            ColorType::La8 => (2, Depth::U8),
+            ColorType::Rgb8 => (3, Depth::U8),
+            ColorType::Rgba8 => (4, Depth::U8),
+            ColorType::L16 => (1, Depth::U16Ne),
+            ColorType::La16 => (2, Depth::U16Ne),
+            ColorType::Rgb16 => (3, Depth::U16Ne),
+            ColorType::Rgba16 => (4, Depth::U16Ne),
+            ColorType::Rgb32F => (3, Depth::F32Ne),
+            ColorType::Rgba32F => (4, Depth::F32Ne),
+            c => {
+                return Err(Error::IOError(format!("unsupported color type {:?}", c)));
+            }
+        };
+
+        Ok(DecodedSource {
+            width: img.width(),
+            height: img.height(),
+            full: (img.width(), img.height()),
+            data: img.into_bytes(),
+            layout: Layout::new(channels, depth),
+            y: 0,
+        })
+    }
+}
+
+impl RowSource for DecodedSource {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn full_dimensions(&self) -> (u32, u32) {
+        self.full
+    }
+
+    fn next_row(&mut self, buff: &mut [f32]) -> Result<(), Error> {
+        let stride = (self.width as usize) * self.layout.bytes_per_pixel();
+        let start = self.y * stride;
+        let row = match self.data.get(start..start + stride) {
+            Some(row) => row,
+            None => {
+                return Err(Error::IOError("read past end of image".to_owned()));
+            }
+        };
+        self.layout.convert_row(row, buff);
+        self.y += 1;
+        Ok(())
+    }
+}
+
+/*
+Rows from a PNG image, decoded one at a time.
+*/
+#[cfg(feature = "png")]
+struct PngSource<R: Read> {
+    reader: png::Reader<R>,
+    layout: Layout,
+}
+
+#[cfg(feature = "png")]
+impl<R: Read> PngSource<R> {
+    /*
+    Returns `Ok(None)` for interlaced images, which can't be decoded from
+    top to bottom.
+    */
+    fn new(r: R) -> Result<Option<PngSource<R>>, Error> {
+        use png::{BitDepth, ColorType, Transformations};
+
+        let mut decoder = png::Decoder::new(r);
+        // Palettes to RGB, and low bit depths to 8 bits.
+        decoder.set_transformations(Transformations::EXPAND);
+        let reader = decoder.read_info().map_err(io_error)?;
+        if reader.info().interlaced {
+            return Ok(None);
+        }
+
+        let (color, bits) = reader.output_color_type();
+        let channels = match color {
+            ColorType::Grayscale => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+            ColorType::Indexed => {
+                return Err(Error::IOError("unexpanded PNG palette".to_owned()));
+            }
+        };
+        let depth = match bits {
+            BitDepth::Sixteen => Depth::U16Be,
+            _ => Depth::U8,
+        };
+
+        Ok(Some(PngSource {
+            reader,
+            layout: Layout::new(channels, depth),
+        }))
+    }
+}
+
+#[cfg(feature = "png")]
+impl<R: Read> RowSource for PngSource<R> {
+    fn dimensions(&self) -> (u32, u32) {
+        self.reader.info().size()
+    }
+
+    fn next_row(&mut self, buff: &mut [f32]) -> Result<(), Error> {
+        match self.reader.next_row().map_err(io_error)? {
+            Some(row) => {
+                self.layout.convert_row(row.data(), buff);
+                Ok(())
+            }
+            None => Err(Error::IOError("read past end of image".to_owned())),
+        }
+    }
+}
+
+/*
+Rows from a binary PGM (P5) or PPM (P6) image, read one at a time.
+*/
+#[cfg(feature = "pnm")]
+struct PnmSource<R: BufRead> {
+    reader: R,
+    width: u32,
+    height: u32,
+    layout: Layout,
+    raw: Vec<u8>,
+}
+
+#[cfg(feature = "pnm")]
+impl<R: BufRead> PnmSource<R> {
+    /*
+    Read the next whitespace-delimited token of a PNM header, skipping
+    `#` comments.
+    */
+    fn header_token(r: &mut R) -> Result<String, Error> {
+        let mut tok = String::new();
+        let mut byte = [0u8; 1];
+        let mut in_comment = false;
+        loop {
+            if r.read(&mut byte).map_err(io_error)? == 0 {
+                return Err(Error::IOError("truncated PNM header".to_owned()));
+            }
+            let c = byte[0] as char;
+            if in_comment {
+                in_comment = c != '\n';
+            } else if c == '#' {
+                in_comment = true;
+            } else if c.is_ascii_whitespace() {
+                // This consumes exactly one whitespace character after
+                // the last token, which is what the format calls for
+                // between the header and the raster.
+                if !tok.is_empty() {
+                    return Ok(tok);
+                }
+            } else {
+                tok.push(c);
+            }
+        }
+    }
+
+    /*
+    Returns `Ok(None)` for any PNM variant other than binary graymap or
+    pixmap.
+    */
+    fn new(mut r: R) -> Result<Option<PnmSource<R>>, Error> {
+        let channels = match Self::header_token(&mut r)?.as_str() {
+            "P5" => 1,
+            "P6" => 3,
+            _ => {
+                return Ok(None);
+            }
+        };
+
+        let mut nums = [0u32; 3];
+        for n in nums.iter_mut() {
+            *n = Self::header_token(&mut r)?
+                .parse()
+                .map_err(|_| Error::IOError("bad PNM header".to_owned()))?;
+        }
+        let [width, height, maxval] = nums;
+        if maxval == 0 || maxval > u16::MAX as u32 {
+            return Err(Error::IOError(format!("bad PNM maxval: {}", maxval)));
+        }
+
+        let depth = if maxval > u8::MAX as u32 {
+            Depth::U16Be
+        } else {
+            Depth::U8
+        };
+        let mut layout = Layout::new(channels, depth);
+        layout.max = maxval as f32;
+
+        Ok(Some(PnmSource {
+            reader: r,
+            width,
+            height,
+            raw: vec![0u8; (width as usize) * layout.bytes_per_pixel()],
+            layout,
+        }))
+    }
+}
+
+#[cfg(feature = "pnm")]
+impl<R: BufRead> RowSource for PnmSource<R> {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn next_row(&mut self, buff: &mut [f32]) -> Result<(), Error> {
+        self.reader.read_exact(&mut self.raw).map_err(io_error)?;
+        self.layout.convert_row(&self.raw, buff);
+        Ok(())
+    }
+}
+
+/*
+Decode a JPEG image at the smallest scale that will still supply at least
+one pixel per `cell`-sized character.
+*/
+#[cfg(feature = "jpeg")]
+fn decode_jpeg_scaled<R: Read>(r: R, cell: (f32, f32)) -> Result<DecodedSource, Error> {
+    use jpeg_decoder::PixelFormat;
+
+    let mut decoder = jpeg_decoder::Decoder::new(r);
+    decoder.read_info().map_err(io_error)?;
+    let info = decoder.info().unwrap();
+    let (cols, rows) = crate::grid_size(info.width as f32, info.height as f32, cell);
+    let (width, height) = decoder
+        .scale(cols.max(1) as u16, rows.max(1) as u16)
+        .map_err(io_error)?;
+
+    let mut data = decoder.decode().map_err(io_error)?;
+    let layout = match decoder.info().unwrap().pixel_format {
+        PixelFormat::L8 => Layout::new(1, Depth::U8),
+        PixelFormat::L16 => Layout::new(1, Depth::U16Be),
+        PixelFormat::RGB24 => Layout::new(3, Depth::U8),
+        PixelFormat::CMYK32 => {
+            // Same conversion `image` does.
+            data = data
+                .chunks_exact(4)
+                .flat_map(|p| {
+                    let k = 255 - p[3] as u16;
+                    let f = |c: u8| ((255 - c as u16) * k / 255) as u8;
+                    [f(p[0]), f(p[1]), f(p[2])]
+                })
+                .collect();
+            Layout::new(3, Depth::U8)
+        }
+    };
+
+    Ok(DecodedSource {
+        data,
+        width: width as u32,
+        height: height as u32,
+        full: (info.width as u32, info.height as u32),
+        layout,
+        y: 0,
+    })
+}
+
+/*
+Choose the most economical `RowSource` available for `format`.
+*/
+#[cfg_attr(not(feature = "jpeg"), allow(unused_variables))]
+fn row_source<'a, R: BufRead + Seek + 'a>(
+    mut r: R,
+    format: ImageFormat,
+    cell: (f32, f32),
+) -> Result<Box<dyn RowSource + 'a>, Error> {
+    let start = r.stream_position().map_err(io_error)?;
+
+    match format {
+        // `PngSource` and `PnmSource` need to own their readers, but we
+        // need to hang on to the reader in case they can't handle this
+        // particular image and we have to start over.
+        #[cfg(feature = "png")]
+        ImageFormat::Png if PngSource::new(&mut r)?.is_some() => {
+            r.seek(SeekFrom::Start(start)).map_err(io_error)?;
+            return Ok(Box::new(PngSource::new(r)?.unwrap()));
+        }
+        #[cfg(feature = "pnm")]
+        ImageFormat::Pnm if PnmSource::new(&mut r)?.is_some() => {
+            r.seek(SeekFrom::Start(start)).map_err(io_error)?;
+            return Ok(Box::new(PnmSource::new(r)?.unwrap()));
+        }
+        #[cfg(feature = "jpeg")]
+        ImageFormat::Jpeg => {
+            return Ok(Box::new(decode_jpeg_scaled(r, cell)?));
+        }
+        _ => {}
+    }
+
+    // Either a format we can't do any better with, or a variant of one
+    // of the above we can't stream. Back to the beginning and decode the
+    // whole thing.
+    r.seek(SeekFrom::Start(start)).map_err(io_error)?;
+    let img = image::io::Reader::with_format(r, format)
+        .decode()
+        .map_err(io_error)?;
+    Ok(Box::new(DecodedSource::from_image(img)?))
+}
+
+/*
+Return the range of `len` source pixels that correspond to the `n`th of
+`new_len` output pixels. Every output pixel gets at least one source
+pixel, even when enlarging.
+*/
+fn span(n: u32, new_len: u32, len: u32) -> (usize, usize) {
+    let (n, new_len, len) = (n as u64, new_len as u64, len as u64);
+    let start = (n * len / new_len).min(len - 1);
+    let end = ((n + 1) * len / new_len).max(start + 1).min(len);
+    (start as usize, end as usize)
+}
+
+/*
+How a `TextRows` chooses characters: from the average intensity of each
+cell, or from the average intensities of each third of each cell (see
+`FontData::subpixel()`).
+*/
+#[derive(Clone, Copy)]
+enum Glyph {
+    Whole(fn(&FontData, f32) -> char),
+    Thirds(fn(&FontData, [f32; 3]) -> char),
+}
+
+impl Glyph {
+    fn new(opts: &RenderOptions, font: &FontData) -> Glyph {
+        match (opts.subpixel && font.has_subpixel(), opts.invert) {
+            (false, false) => Glyph::Whole(FontData::pixel),
+            (false, true) => Glyph::Whole(FontData::pixel_inv),
+            (true, false) => Glyph::Thirds(FontData::subpixel),
+            (true, true) => Glyph::Thirds(FontData::subpixel_inv),
+        }
+    }
+
+    // How many bins of source pixels go into each character.
+    fn bins(&self) -> u32 {
+        match self {
+            Glyph::Whole(_) => 1,
+            Glyph::Thirds(_) => 3,
+        }
+    }
+}
+
+/**
+An iterator over the rows of text of an image rendered in a given font,
+which reads (and, where possible, decodes) the image incrementally as
+the rows are produced.
+
+Each item is either a row of text (without a trailing newline), or an
+`Error` encountered reading the image data, after which the iterator
+will produce nothing further.
+
+```
+# use std::io::BufReader;
+# use ascii_art::{printable_ascii, FontData, TextRows};
+# let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+# let font = FontData::from_font_bytes(&bytes, 12.0, &printable_ascii())
+#     .unwrap().unwrap();
+let f = std::fs::File::open("test/griffin_sm.jpg").unwrap();
+let rows = TextRows::auto(BufReader::new(f), &font).unwrap();
+println!("{} by {} characters", rows.dimensions().0, rows.dimensions().1);
+
+for row in rows {
+    println!("{}", row.unwrap());
+}
+```
+*/
+pub struct TextRows<'a> {
+    source: Box<dyn RowSource + 'a>,
+    font: &'a FontData,
+    glyph: Glyph,
+    cols: u32,
+    rows: u32,
+    /// ranges of source columns averaged into each character (or third
+    /// of a character)
+    bins: Vec<(usize, usize)>,
+    /// the most recently read source row
+    row_buff: Vec<f32>,
+    rows_read: usize,
+    sums: Vec<f32>,
+    next_row: u32,
+}
+
+impl<'a> TextRows<'a> {
+    /**
+    Prepare to render the image in `r` with `font`, attempting to guess
+    the image format from its contents.
+
+    This is for writing light text on a dark background; see
+    `.inverted()`.
+    */
+    pub fn auto<R: BufRead + Seek + 'a>(r: R, font: &'a FontData) -> Result<TextRows<'a>, Error> {
+        TextRows::auto_with(r, font, &RenderOptions::default())
+    }
+
+    /**
+    Prepare to render the image in `r` with `font` as specified by `opts`
+    (see `RenderOptions`), attempting to guess the image format from its
+    contents.
+
+    Fails with `Error::UnsupportedOption` if `opts.preserve_text` is set.
+    */
+    pub fn auto_with<R: BufRead + Seek + 'a>(
+        r: R,
+        font: &'a FontData,
+        opts: &RenderOptions,
+    ) -> Result<TextRows<'a>, Error> {
+        let rdr = image::io::Reader::new(r)
+            .with_guessed_format()
+            .map_err(io_error)?;
+        match rdr.format() {
+            Some(format) => TextRows::build(rdr.into_inner(), format, font, opts),
+            None => Err(Error::IOError("unrecognized image format".to_owned())),
+        }
+    }
+
+    /**
+    Prepare to render the image in `r` with `font`, decoding it as the
+    provided `format`.
+
+    This is for writing light text on a dark background; see
+    `.inverted()`.
+    */
+    pub fn with_format<R: BufRead + Seek + 'a>(
+        r: R,
+        format: ImageFormat,
+        font: &'a FontData,
+    ) -> Result<TextRows<'a>, Error> {
+        TextRows::build(r, format, font, &RenderOptions::default())
+    }
+
+    fn build<R: BufRead + Seek + 'a>(
+        r: R,
+        format: ImageFormat,
+        font: &'a FontData,
+        opts: &RenderOptions,
+    ) -> Result<TextRows<'a>, Error> {
+        if opts.preserve_text {
+            return Err(Error::UnsupportedOption(
+                "preserve_text can't be used when streaming".to_owned(),
+            ));
+        }
+        let cell = opts.cell_size(font);
+        let source = row_source(r, format, cell)?;
+        let (src_w, src_h) = source.dimensions();
+
+        // The output dimensions are always based on the full-sized image,
+        // even if the source is (say) a JPEG decoded at reduced size.
+        let (full_w, full_h) = source.full_dimensions();
+        let (cols, rows) = crate::grid_size(full_w as f32, full_h as f32, cell);
+
+        let glyph = Glyph::new(opts, font);
+        let n_bins = cols * glyph.bins();
+        let bins: Vec<(usize, usize)> = if src_w == 0 || src_h == 0 {
+            Vec::new()
+        } else {
+            (0..n_bins).map(|c| span(c, n_bins, src_w)).collect()
+        };
+
+        Ok(TextRows {
+            source,
+            font,
+            glyph,
+            cols,
+            rows: if bins.is_empty() { 0 } else { rows },
+            sums: vec![0.0; bins.len()],
+            bins,
+            row_buff: vec![0.0; src_w as usize],
+            rows_read: 0,
+            next_row: 0,
+        })
+    }
+
+    /**
+    Render dark text on a light background instead.
+    */
+    pub fn inverted(mut self) -> TextRows<'a> {
+        self.glyph = match self.glyph {
+            Glyph::Whole(_) => Glyph::Whole(FontData::pixel_inv),
+            Glyph::Thirds(_) => Glyph::Thirds(FontData::subpixel_inv),
+        };
+        self
+    }
+
+    /**
+    Return the width and height (in characters) of the text this will
+    produce.
+    */
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.cols, self.rows)
+    }
+
+    fn render_row(&mut self) -> Result<String, Error> {
+        let (_, src_h) = self.source.dimensions();
+        let (y0, y1) = span(self.next_row, self.rows, src_h);
+        self.sums.iter_mut().for_each(|s| *s = 0.0);
+
+        for y in y0..y1 {
+            // When enlarging, consecutive rows of text can share a source
+            // row; it will be the one already in the buffer.
+            while self.rows_read <= y {
+                self.source.next_row(&mut self.row_buff)?;
+                self.rows_read += 1;
+            }
+            for (sum, (x0, x1)) in self.sums.iter_mut().zip(self.bins.iter()) {
+                *sum += self.row_buff[*x0..*x1].iter().sum::<f32>();
+            }
+        }
+
+        let band = (y1 - y0) as f32;
+        for (sum, (x0, x1)) in self.sums.iter_mut().zip(self.bins.iter()) {
+            *sum /= band * (x1 - x0) as f32;
+        }
+
+        let row = match self.glyph {
+            Glyph::Whole(f) => self.sums.iter().map(|v| f(self.font, *v)).collect(),
+            Glyph::Thirds(f) => self
+                .sums
+                .chunks_exact(3)
+                .map(|v| f(self.font, [v[0], v[1], v[2]]))
+                .collect(),
+        };
+        Ok(row)
+    }
+}
+
+impl Iterator for TextRows<'_> {
+    type Item = Result<String, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_row >= self.rows {
+            return None;
+        }
+
+        let res = self.render_row();
+        if res.is_err() {
+            // Don't try to keep reading a broken image.
+            self.next_row = self.rows;
+        } else {
+            self.next_row += 1;
+        }
+        Some(res)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = (self.rows - self.next_row) as usize;
+        (n, Some(n))
+    }
+}
+
+fn write_rows<W: Write>(rows: TextRows, writer: W) -> Result<(), Error> {
+    let mut writer = BufWriter::new(writer);
+    for row in rows {
+        writeln!(&mut writer, "{}", row?).map_err(io_error)?;
+    }
+    writer.flush().map_err(io_error)
+}
+
+/**
+Given some `FontData`, read an image from `r` and write it as text to the
+`writer`, a row at a time, without ever decoding the whole image into
+memory (at least for formats that allow that; see `TextRows`).
+
+Like `write()`, this is for writing light text on a dark background.
+*/
+pub fn write_streaming<R, W>(r: R, font: &FontData, writer: W) -> Result<(), Error>
+where
+    R: BufRead + Seek,
+    W: Write,
+{
+    write_rows(TextRows::auto(r, font)?, writer)
+}
+
+/**
+Given some `FontData`, read an image from `r` and write it as text to the
+`writer`, a row at a time, without ever decoding the whole image into
+memory (at least for formats that allow that; see `TextRows`).
+
+Like `write_inverted()`, this is for writing dark text on a light
+background.
+*/
+pub fn write_streaming_inverted<R, W>(r: R, font: &FontData, writer: W) -> Result<(), Error>
+where
+    R: BufRead + Seek,
+    W: Write,
+{
+    write_rows(TextRows::auto(r, font)?.inverted(), writer)
+}
+
+/**
+Given some `FontData`, read an image from `r` and write it as text to the
+`writer` as specified by `opts`, a row at a time, without ever decoding
+the whole image into memory (at least for formats that allow that; see
+`TextRows`).
+
+This is to `write_streaming()` as `write_with()` is to `write()`, except
+that it fails with `Error::UnsupportedOption` if `opts.preserve_text` is
+set.
+*/
+pub fn write_streaming_with<R, W>(
+    r: R,
+    font: &FontData,
+    opts: &RenderOptions,
+    writer: W,
+) -> Result<(), Error>
+where
+    R: BufRead + Seek,
+    W: Write,
+{
+    write_rows(TextRows::auto_with(r, font, opts)?, writer)
+}
+
+// The tests round-trip images through these encoders.
+#[cfg(all(test, feature = "bmp", feature = "png", feature = "pnm"))]
+mod tests {
+    use super::*;
+    use crate::printable_ascii;
+    use image::{ImageOutputFormat, Rgb, RgbImage};
+    use std::io::{BufReader, Cursor};
+
+    const FONT_PATH: &str = "test/LiberationMono-Regular.ttf";
+    const IMAGE_PATH: &str = "test/griffin_sm.jpg";
+
+    fn test_font(size: f32) -> FontData {
+        let font_bytes = std::fs::read(FONT_PATH).unwrap();
+        FontData::from_font_bytes(&font_bytes, size, &printable_ascii())
+            .unwrap()
+            .unwrap()
+    }
+
+    fn render(data: &[u8], font: &FontData) -> Vec<String> {
+        TextRows::auto(Cursor::new(data), font)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn formats_agree() {
+        let font = test_font(8.0);
+        let img = image::open(IMAGE_PATH).unwrap();
+
+        let mut outputs = Vec::new();
+        for format in [
+            ImageOutputFormat::Png,
+            ImageOutputFormat::Pnm(image::codecs::pnm::PnmSubtype::Pixmap(
+                image::codecs::pnm::SampleEncoding::Binary,
+            )),
+            ImageOutputFormat::Bmp,
+        ] {
+            let mut data = Vec::new();
+            img.write_to(&mut Cursor::new(&mut data), format.clone())
+                .unwrap();
+            outputs.push((format, render(&data, &font)));
+        }
+
+        let (_, first) = &outputs[0];
+        for (format, rows) in outputs.iter() {
+            assert_eq!(first, rows, "{:?} differs from PNG", format);
+        }
+    }
+
+    #[test]
+    fn same_size_as_write() {
+        let font_bytes = std::fs::read(FONT_PATH).unwrap();
+        let font_opts = crate::FontOptions {
+            subpixel: true,
+            ..crate::FontOptions::default()
+        };
+        let font = FontData::from_font_bytes_with(&font_bytes, 8.0, &printable_ascii(), &font_opts)
+            .unwrap()
+            .unwrap();
+        let f = std::fs::File::open(IMAGE_PATH).unwrap();
+        let img = crate::Image::auto(BufReader::new(f)).unwrap();
+
+        let subpixel = RenderOptions {
+            subpixel: true,
+            ..RenderOptions::default()
+        };
+        for opts in [
+            RenderOptions::default(),
+            RenderOptions::terminal(),
+            subpixel,
+        ] {
+            let mut v: Vec<u8> = Vec::new();
+            crate::write_with(&img, &font, &opts, &mut v).unwrap();
+            let written = String::from_utf8(v).unwrap();
+
+            let f = std::fs::File::open(IMAGE_PATH).unwrap();
+            let rows = TextRows::auto_with(BufReader::new(f), &font, &opts).unwrap();
+            let (cols, n_rows) = rows.dimensions();
+            let streamed: Vec<String> = rows.map(|r| r.unwrap()).collect();
+
+            assert_eq!(streamed.len(), n_rows as usize);
+            assert_eq!(streamed.len(), written.lines().count());
+            for (s, w) in streamed.iter().zip(written.lines()) {
+                assert_eq!(s.chars().count(), cols as usize);
+                assert_eq!(s.chars().count(), w.chars().count());
+            }
+        }
+    }
+
+    #[test]
+    fn averaging() {
+        // Three characters wide and two high, left half black, right half
+        // white; the middle column of characters straddles the edge.
+        let font = test_font(12.0);
+        let (fw, fh) = font.geometry();
+        let (w, h) = ((fw * 3.0).ceil() as u32, (fh * 2.0).ceil() as u32);
+        let img = RgbImage::from_fn(w, h, |x, _| {
+            if x < w / 2 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(img)
+            .write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+            .unwrap();
+
+        let rows = render(&data, &font);
+        assert_eq!(rows.len(), 2);
+        for row in rows.iter() {
+            let chars: Vec<char> = row.chars().collect();
+            assert_eq!(chars.len(), 3);
+            assert_eq!(chars[0], font.pixel(0.0));
+            assert_eq!(chars[2], font.pixel(1.0));
+            assert_ne!(chars[1], chars[0]);
+            assert_ne!(chars[1], chars[2]);
+        }
+
+        let inverted: Vec<String> = TextRows::auto(Cursor::new(&data), &font)
+            .unwrap()
+            .inverted()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(inverted[0].chars().next(), Some(font.pixel_inv(0.0)));
+    }
+
+    #[test]
+    fn preserve_text() {
+        // A gradient with a band of thin, closely-spaced white strokes on
+        // black (which looks like text) across the middle.
+        let font = test_font(12.0);
+        let img = RgbImage::from_fn(300, 120, |x, y| {
+            if (42..84).contains(&y) && (10..290).contains(&x) {
+                let stroke = x.is_multiple_of(4) || (y - 42).is_multiple_of(6);
+                Rgb([if stroke { 255 } else { 0 }; 3])
+            } else {
+                Rgb([(x * 255 / 300) as u8; 3])
+            }
+        });
+        let img = DynamicImage::ImageRgb8(img);
+        let mut data = Vec::new();
+        img.write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+            .unwrap();
+
+        // Writing it all at once, the text comes out differently...
+        let opts = RenderOptions {
+            preserve_text: true,
+            ..RenderOptions::default()
+        };
+        let img = crate::Image::auto(Cursor::new(&data)).unwrap();
+        let render = |opts: &RenderOptions| {
+            let mut v: Vec<u8> = Vec::new();
+            crate::write_with(&img, &font, opts, &mut v).unwrap();
+            String::from_utf8(v).unwrap()
+        };
+        assert_ne!(render(&opts), render(&RenderOptions::default()));
+
+        // ...which streaming can't do, so it says so, rather than giving
+        // the same output as without it.
+        assert!(matches!(
+            TextRows::auto_with(Cursor::new(&data), &font, &opts),
+            Err(Error::UnsupportedOption(_))
+        ));
+        assert!(matches!(
+            write_streaming_with(Cursor::new(&data), &font, &opts, std::io::sink()),
+            Err(Error::UnsupportedOption(_))
+        ));
+        let mut v: Vec<u8> = Vec::new();
+        write_streaming_with(Cursor::new(&data), &font, &RenderOptions::default(), &mut v).unwrap();
+        assert_eq!(
+            String::from_utf8(v).unwrap().lines().count(),
+            render(&opts).lines().count()
+        );
+    }
+}
@@ -0,0 +1,282 @@
+/*!
+Finding text in an image, and rendering it so it stays readable.
+
+Shading a cell by its overall intensity turns the strokes of small text
+into gray mush. Cells that contain text tend to have both strong contrast
+(ink against paper) and lots of edges (the strokes), and they come in
+runs that are wider than they are tall (lines of text). Cells like that
+are rendered by how much of each cell (or each third of each cell) is on
+the ink side of the text's threshold, rather than by their average
+intensity, which gives crisp, high-contrast glyphs.
+*/
+
+use std::collections::VecDeque;
+
+use crate::{par, FontData, Image, RenderOptions};
+
+// The minimum difference between the lightest and darkest pixels of a
+// cell for it to possibly contain text.
+const MIN_CONTRAST: f32 = 0.5;
+// Adjacent pixels at least this different make an edge.
+const EDGE_STEP: f32 = 0.25;
+// The minimum fraction of a cell's pixels that have to be on an edge for
+// it to possibly contain text.
+const MIN_EDGES: f32 = 0.1;
+// The minimum width (in cells) of a run of text.
+const MIN_TEXT_WIDTH: u32 = 3;
+
+/*
+The range of intensities in a cell, and how edgy it is.
+*/
+#[derive(Clone, Copy, Debug)]
+struct CellStats {
+    min: f32,
+    max: f32,
+    edges: f32,
+}
+
+impl CellStats {
+    fn maybe_text(&self) -> bool {
+        self.max - self.min >= MIN_CONTRAST && self.edges >= MIN_EDGES
+    }
+}
+
+/*
+The pixels of `img` covered by a `cell`-sized cell, as `(x0, x1, y0, y1)`.
+*/
+fn cell_rect(img: &Image, cell: (f32, f32), x: u32, y: u32) -> (u32, u32, u32, u32) {
+    let (img_w, img_h) = img.buff.dimensions();
+    let (x0, x1) = crate::cell_span(x, cell.0, img_w);
+    let (y0, y1) = crate::cell_span(y, cell.1, img_h);
+    (x0, x1, y0, y1)
+}
+
+fn cell_stats(img: &Image, rect: (u32, u32, u32, u32)) -> CellStats {
+    let (x0, x1, y0, y1) = rect;
+    let (img_w, _) = img.buff.dimensions();
+    let px = img.buff.as_raw();
+    let at = |x: u32, y: u32| px[(y as usize) * (img_w as usize) + (x as usize)];
+
+    let mut stats = CellStats {
+        min: f32::INFINITY,
+        max: f32::NEG_INFINITY,
+        edges: 0.0,
+    };
+    let mut edges = 0u32;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let p = at(x, y);
+            stats.min = stats.min.min(p);
+            stats.max = stats.max.max(p);
+            let right = x + 1 < x1 && (at(x + 1, y) - p).abs() >= EDGE_STEP;
+            let below = y + 1 < y1 && (at(x, y + 1) - p).abs() >= EDGE_STEP;
+            if right || below {
+                edges += 1;
+            }
+        }
+    }
+    stats.edges = edges as f32 / ((x1 - x0) * (y1 - y0)) as f32;
+    stats
+}
+
+/*
+Return, for each cell of the `w` by `h` grid of `cell`-sized cells over
+`img`, the intensity that separates ink from background if the cell is
+part of a region of text, or `None` if it isn't.
+*/
+fn find_text(img: &Image, cell: (f32, f32), w: u32, h: u32) -> Vec<Option<f32>> {
+    let stats: Vec<CellStats> = par::map_range(h as usize, |y| {
+        (0..w)
+            .map(|x| cell_stats(img, cell_rect(img, cell, x, y as u32)))
+            .collect::<Vec<_>>()
+    })
+    .concat();
+
+    let (w, h) = (w as usize, h as usize);
+    let mut text: Vec<Option<f32>> = vec![None; w * h];
+    let mut seen = vec![false; w * h];
+
+    // Gather each connected group of cells that might contain text, and
+    // keep the ones shaped like lines (or paragraphs) of it.
+    for start in 0..(w * h) {
+        if seen[start] || !stats[start].maybe_text() {
+            continue;
+        }
+        seen[start] = true;
+        let mut group: Vec<usize> = Vec::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(n) = queue.pop_front() {
+            group.push(n);
+            let (x, y) = ((n % w) as i64, (n / w) as i64);
+            for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= w as i64 || ny >= h as i64 {
+                    continue;
+                }
+                let m = (ny as usize) * w + (nx as usize);
+                if !seen[m] && stats[m].maybe_text() {
+                    seen[m] = true;
+                    queue.push_back(m);
+                }
+            }
+        }
+
+        let xs = group.iter().map(|n| n % w);
+        let ys = group.iter().map(|n| n / w);
+        let group_w = xs.clone().max().unwrap() - xs.min().unwrap() + 1;
+        let group_h = ys.clone().max().unwrap() - ys.min().unwrap() + 1;
+        if group_w < MIN_TEXT_WIDTH as usize || group_w < group_h {
+            continue;
+        }
+
+        let threshold = group
+            .iter()
+            .map(|n| (stats[*n].min + stats[*n].max) / 2.0)
+            .sum::<f32>()
+            / group.len() as f32;
+        for n in group.iter() {
+            text[*n] = Some(threshold);
+        }
+    }
+
+    text
+}
+
+/*
+Return the fraction of each of the `k` vertical slices of the cell's
+pixels that are brighter than `threshold`.
+*/
+fn ink(img: &Image, rect: (u32, u32, u32, u32), threshold: f32, k: usize) -> [f32; 3] {
+    let (x0, x1, y0, y1) = rect;
+    let (img_w, _) = img.buff.dimensions();
+    let px = img.buff.as_raw();
+    let width = (x1 - x0) as f32;
+
+    let mut bright = [0u32; 3];
+    let mut total = [0u32; 3];
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let slice = ((k as f32 * ((x - x0) as f32 + 0.5) / width) as usize).min(k - 1);
+            total[slice] += 1;
+            if px[(y as usize) * (img_w as usize) + (x as usize)] > threshold {
+                bright[slice] += 1;
+            }
+        }
+    }
+
+    [0, 1, 2].map(|n| bright[n] as f32 / total[n].max(1) as f32)
+}
+
+/*
+Replace the characters of the already-rendered `rows` that fall in
+regions of text with ones chosen to keep the text legible.
+*/
+pub(crate) fn sharpen_text(
+    img: &Image,
+    font: &FontData,
+    opts: &RenderOptions,
+    rows: Vec<String>,
+) -> Vec<String> {
+    let h = rows.len() as u32;
+    let w = rows.first().map(|r| r.chars().count()).unwrap_or(0) as u32;
+    if w == 0 || h == 0 {
+        return rows;
+    }
+
+    let cell = opts.cell_size(font);
+    let text = find_text(img, cell, w, h);
+    if text.iter().all(|t| t.is_none()) {
+        return rows;
+    }
+
+    // Text gets the sharpest matching the font has to offer.
+    let k = if font.has_subpixel() { 3 } else { 1 };
+    let glyph = |vals: [f32; 3]| match (k, opts.invert) {
+        (3, false) => font.subpixel(vals),
+        (3, true) => font.subpixel_inv(vals),
+        (_, false) => font.pixel(vals[0]),
+        (_, true) => font.pixel_inv(vals[0]),
+    };
+
+    rows.into_iter()
+        .enumerate()
+        .map(|(y, row)| {
+            row.chars()
+                .enumerate()
+                .map(|(x, chr)| match text[y * (w as usize) + x] {
+                    Some(threshold) => {
+                        let rect = cell_rect(img, cell, x as u32, y as u32);
+                        glyph(ink(img, rect, threshold, k))
+                    }
+                    None => chr,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageBuffer;
+
+    /*
+    A `width` by `height` image with a smooth gradient, and a band of
+    "text" (thin, closely-spaced white strokes on black) from `top` to
+    `bottom`.
+    */
+    fn captioned(width: u32, height: u32, top: u32, bottom: u32) -> Image {
+        let buff = ImageBuffer::from_fn(width, height, |x, y| {
+            if y >= top && y < bottom && x >= 10 && x < width - 10 {
+                let stroke = x.is_multiple_of(4) || (y - top).is_multiple_of(6);
+                image::Luma([if stroke { 1.0f32 } else { 0.0 }])
+            } else {
+                image::Luma([x as f32 / width as f32])
+            }
+        });
+        Image { buff }
+    }
+
+    #[test]
+    fn text_regions() {
+        let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+        let font = FontData::from_font_bytes(&bytes, 12.0, &crate::printable_ascii())
+            .unwrap()
+            .unwrap();
+        let plain = RenderOptions::default();
+        let opts = RenderOptions {
+            preserve_text: true,
+            ..RenderOptions::default()
+        };
+        let (cell_w, cell_h) = font.geometry();
+
+        let img = captioned(300, 120, 42, 84);
+        let (w, h) = crate::grid_size(300.0, 120.0, (cell_w, cell_h));
+        let text = find_text(&img, (cell_w, cell_h), w, h);
+        // The middle of the caption is found, and the gradient isn't.
+        let mid_y = (63.0 / cell_h) as usize;
+        assert!(text[mid_y * (w as usize) + (w as usize) / 2].is_some());
+        assert!(text[..(w as usize)].iter().all(|t| t.is_none()));
+
+        let before = crate::render_rows_with(&img, &font, &plain);
+        let after = crate::render_rows_with(&img, &font, &opts);
+        assert_eq!(before.len(), after.len());
+        for (y, (b, a)) in before.iter().zip(after.iter()).enumerate() {
+            assert_eq!(b.chars().count(), a.chars().count());
+            if text[y * (w as usize)..(y + 1) * (w as usize)]
+                .iter()
+                .all(|t| t.is_none())
+            {
+                assert_eq!(b, a);
+            }
+        }
+        assert_ne!(before, after);
+
+        // An image with no text in it comes out the same either way.
+        let img = captioned(300, 120, 0, 0);
+        assert_eq!(
+            crate::render_rows_with(&img, &font, &plain),
+            crate::render_rows_with(&img, &font, &opts)
+        );
+    }
+}
@@ -0,0 +1,345 @@
+/*!
+Rendering each character cell with both a foreground and a background
+color.
+
+A cell drawn with glyph `g` in foreground color `fg` on background `bg`
+looks (from a distance) like a mix of the two, in proportion to the
+glyph's coverage; with subpixel coverage information, each third of the
+cell gets its own mix. Here, each cell's colors are split into a light
+and a dark group, and the glyph (and which of the two colors goes in
+front) is chosen to minimize the difference between what the cell would
+look like and the thirds of the image it covers.
+*/
+
+use std::io::{BufWriter, Write};
+
+use image::RgbImage;
+
+use crate::color::{io_error, to_unit};
+use crate::{par, ColorImage, ColorOptions, Error, FontData, Palette, RenderOptions};
+
+// A glyph, and its foreground and background colors (each a palette index
+// and the color itself).
+type Cell = (char, (usize, [u8; 3]), (usize, [u8; 3]));
+
+fn luma(c: [f32; 3]) -> f32 {
+    0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]
+}
+
+fn mean(sum: [f32; 3], n: u32) -> [f32; 3] {
+    sum.map(|v| v / n as f32)
+}
+
+fn add(a: &mut [f32; 3], b: [f32; 3]) {
+    for (a, b) in a.iter_mut().zip(b.iter()) {
+        *a += b;
+    }
+}
+
+/*
+The colors of the part of the image covered by one character cell.
+*/
+struct CellColors {
+    // The mean color of each of the left, middle, and right thirds.
+    thirds: [[f32; 3]; 3],
+    // The mean colors of the lighter and darker halves of the pixels.
+    light: [f32; 3],
+    dark: [f32; 3],
+}
+
+impl CellColors {
+    /*
+    Gather the colors of the pixels from `x0` to `x1` and `y0` to `y1`
+    (exclusive) of `rgb`.
+    */
+    fn measure(rgb: &RgbImage, x0: u32, x1: u32, y0: u32, y1: u32) -> CellColors {
+        let width = (x1 - x0) as f32;
+        let mut pixels: Vec<(usize, [f32; 3])> = Vec::new();
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let third = (3.0 * ((x - x0) as f32 + 0.5) / width) as usize;
+                pixels.push((third.min(2), to_unit(rgb.get_pixel(x, y).0)));
+            }
+        }
+
+        let mut total = [0.0f32; 3];
+        let mut sums = [[0.0f32; 3]; 3];
+        let mut counts = [0u32; 3];
+        for (third, c) in pixels.iter() {
+            add(&mut total, *c);
+            add(&mut sums[*third], *c);
+            counts[*third] += 1;
+        }
+        let overall = mean(total, (pixels.len() as u32).max(1));
+        let thirds = [0, 1, 2].map(|n| {
+            if counts[n] == 0 {
+                overall
+            } else {
+                mean(sums[n], counts[n])
+            }
+        });
+
+        let threshold = luma(overall);
+        let (mut light, mut dark) = ([0.0f32; 3], [0.0f32; 3]);
+        let (mut n_light, mut n_dark) = (0u32, 0u32);
+        for (_, c) in pixels.iter() {
+            if luma(*c) > threshold {
+                add(&mut light, *c);
+                n_light += 1;
+            } else {
+                add(&mut dark, *c);
+                n_dark += 1;
+            }
+        }
+        let light = if n_light == 0 {
+            overall
+        } else {
+            mean(light, n_light)
+        };
+        let dark = if n_dark == 0 {
+            overall
+        } else {
+            mean(dark, n_dark)
+        };
+
+        CellColors {
+            thirds,
+            light,
+            dark,
+        }
+    }
+}
+
+/*
+Return how far off drawing a glyph with the given `coverage` of each
+third in `fg` on `bg` would be from `thirds`.
+*/
+fn error(coverage: &[f32; 3], fg: [f32; 3], bg: [f32; 3], thirds: &[[f32; 3]; 3]) -> f32 {
+    let mut err = 0.0f32;
+    for (a, t) in coverage.iter().zip(thirds.iter()) {
+        for c in 0..3 {
+            let d = a * fg[c] + (1.0 - a) * bg[c] - t[c];
+            err += d * d;
+        }
+    }
+    err
+}
+
+/*
+Choose the glyph and colors for a cell, from the glyphs (and their
+coverage of each third) in `glyphs`, with colors reduced to `palette` if
+there is one.
+*/
+fn choose(cell: &CellColors, glyphs: &[(char, [f32; 3])], palette: Option<&Palette>) -> Cell {
+    let pick = |c: [f32; 3]| match palette {
+        Some(p) => {
+            let n = p.nearest(c);
+            (n, p.colors()[n])
+        }
+        None => (0, c.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)),
+    };
+    let light = pick(cell.light);
+    let dark = pick(cell.dark);
+
+    let mut best = (glyphs[0].0, light, dark);
+    let mut best_err = f32::INFINITY;
+    for (fg, bg) in [(light, dark), (dark, light)] {
+        let (fg_c, bg_c) = (to_unit(fg.1), to_unit(bg.1));
+        for (chr, coverage) in glyphs.iter() {
+            let err = error(coverage, fg_c, bg_c, &cell.thirds);
+            if err < best_err {
+                best = (*chr, fg, bg);
+                best_err = err;
+            }
+        }
+    }
+    best
+}
+
+/**
+Given some `FontData`, write the `ColorImage` as text to the `writer`,
+choosing a glyph, a foreground color, and a background color for each
+character together.
+
+This gets much closer to the original image than `write_color()`, which
+only colors the glyphs themselves, but only works with targets that can
+also set background colors (see `ColorTarget::has_background()`).
+
+Cells are sized according to `opts` as with `write_with()`, and if
+`opts.subpixel` is set and the font has subpixel coverage information,
+glyphs are matched to the left, middle, and right thirds of each cell
+separately. `opts.invert` and `color.dither` have no effect, as colors
+are chosen explicitly for each cell.
+
+```
+use ascii_art::{ColorImage, ColorOptions, ColorTarget, RenderOptions};
+# let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+# let font = ascii_art::FontData::from_font_bytes(
+#     &bytes, 12.0, &ascii_art::printable_ascii()
+# ).unwrap().unwrap();
+
+let f = std::fs::File::open("test/griffin_sm.jpg").unwrap();
+let img = ColorImage::auto(std::io::BufReader::new(f)).unwrap();
+
+let color = ColorOptions::new(ColorTarget::AnsiTrueColor);
+let mut out: Vec<u8> = Vec::new();
+ascii_art::write_two_color(&img, &font, &RenderOptions::terminal(), &color, &mut out).unwrap();
+```
+*/
+pub fn write_two_color<W: Write>(
+    img: &ColorImage,
+    font: &FontData,
+    opts: &RenderOptions,
+    color: &ColorOptions,
+    writer: W,
+) -> Result<(), Error> {
+    let target = color.target;
+    if !target.has_background() {
+        return Err(Error::InvalidPalette(format!(
+            "{:?} can't set background colors",
+            target
+        )));
+    }
+    let palette = color.resolve_palette()?;
+    let glyphs = font.coverage_thirds(opts.subpixel);
+
+    let rgb = img.rgb();
+    let (img_w, img_h) = rgb.dimensions();
+    let (cell_w, cell_h) = opts.cell_size(font);
+    let (w, h) = crate::grid_size(img_w as f32, img_h as f32, (cell_w, cell_h));
+
+    let rows: Vec<Vec<Cell>> = par::map_range(h as usize, |y| {
+        let (y0, y1) = crate::cell_span(y as u32, cell_h, img_h);
+        (0..w)
+            .map(|x| {
+                let (x0, x1) = crate::cell_span(x, cell_w, img_w);
+                let cell = CellColors::measure(rgb, x0, x1, y0, y1);
+                choose(&cell, &glyphs, palette.as_ref())
+            })
+            .collect()
+    });
+
+    let mut writer = BufWriter::new(writer);
+    for row in rows.iter() {
+        write_two_color_row(&mut writer, color, row).map_err(io_error)?;
+    }
+    writer.flush().map_err(io_error)
+}
+
+fn write_two_color_row<W: Write>(
+    w: &mut W,
+    color: &ColorOptions,
+    row: &[Cell],
+) -> std::io::Result<()> {
+    let mut current: Option<([u8; 3], [u8; 3])> = None;
+
+    for (chr, fg, bg) in row.iter() {
+        // A space's foreground color doesn't matter, as long as the
+        // background is right.
+        let unchanged = match current {
+            Some((cur_fg, cur_bg)) => cur_bg == bg.1 && (*chr == ' ' || cur_fg == fg.1),
+            None => false,
+        };
+        if !unchanged {
+            color.target.start_pair(w, *fg, *bg)?;
+            current = Some((fg.1, bg.1));
+        }
+        write!(w, "{}", chr)?;
+    }
+
+    if current.is_some() {
+        color.target.end(w, true)?;
+    }
+    writeln!(w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ColorTarget;
+
+    #[test]
+    fn choose_cells() {
+        let glyphs = [(' ', [0.0; 3]), ('|', [0.0, 0.8, 0.0]), ('#', [0.7; 3])];
+        let red = [1.0, 0.0, 0.0];
+        let blue = [0.0, 0.0, 1.0];
+        let cell = |thirds: [[f32; 3]; 3], light, dark| CellColors {
+            thirds,
+            light,
+            dark,
+        };
+
+        // A flat color is a space on that color.
+        let (chr, _, bg) = choose(&cell([red; 3], red, red), &glyphs, None);
+        assert_eq!((chr, bg.1), (' ', [255, 0, 0]));
+
+        // A red stripe down the middle of blue is a red bar on blue.
+        let (chr, fg, bg) = choose(&cell([blue, red, blue], red, blue), &glyphs, None);
+        assert_eq!((chr, fg.1, bg.1), ('|', [255, 0, 0], [0, 0, 255]));
+
+        // A blue stripe down the middle of red is a blue bar on red, even
+        // though blue is the darker color.
+        let (chr, fg, bg) = choose(&cell([red, blue, red], red, blue), &glyphs, None);
+        assert_eq!((chr, fg.1, bg.1), ('|', [0, 0, 255], [255, 0, 0]));
+
+        // With a palette, colors come from the palette.
+        let palette = ColorTarget::Ansi8.palette().unwrap();
+        let (_, fg, bg) = choose(&cell([blue, red, blue], red, blue), &glyphs, Some(&palette));
+        assert_eq!((fg.0, bg.0), (1, 4));
+    }
+
+    #[test]
+    fn write() {
+        let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+        let font = FontData::from_font_bytes(&bytes, 12.0, &crate::printable_ascii())
+            .unwrap()
+            .unwrap();
+        let f = std::fs::File::open("test/griffin_sm.jpg").unwrap();
+        let img = ColorImage::auto(std::io::BufReader::new(f)).unwrap();
+        let opts = RenderOptions::default();
+
+        let mut plain: Vec<u8> = Vec::new();
+        crate::write(img.image(), &font, &mut plain).unwrap();
+        let plain = String::from_utf8(plain).unwrap();
+
+        for target in [
+            ColorTarget::Ansi16,
+            ColorTarget::AnsiTrueColor,
+            ColorTarget::Mirc,
+        ] {
+            let mut out: Vec<u8> = Vec::new();
+            write_two_color(&img, &font, &opts, &ColorOptions::new(target), &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert_eq!(out.lines().count(), plain.lines().count());
+        }
+
+        let row = [
+            ('a', (1, [205, 0, 0]), (0, [0, 0, 0])),
+            (' ', (2, [0, 205, 0]), (0, [0, 0, 0])),
+            ('b', (9, [255, 0, 0]), (12, [92, 92, 255])),
+        ];
+        let mut out: Vec<u8> = Vec::new();
+        write_two_color_row(&mut out, &ColorOptions::new(ColorTarget::Ansi16), &row).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[31;40ma \x1b[91;104mb\x1b[0m\n"
+        );
+        let mut out: Vec<u8> = Vec::new();
+        write_two_color_row(&mut out, &ColorOptions::new(ColorTarget::Mirc), &row).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x0301,00a \x0309,12b\x0f\n"
+        );
+
+        assert!(matches!(
+            write_two_color(
+                &img,
+                &font,
+                &opts,
+                &ColorOptions::new(ColorTarget::BBCode),
+                std::io::sink()
+            ),
+            Err(Error::InvalidPalette(_))
+        ));
+    }
+}
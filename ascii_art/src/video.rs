@@ -0,0 +1,876 @@
+/*!
+Reading a sequence of frames (from a YUV4MPEG2 stream, an animated GIF,
+PNG, or WebP, or just raw pixel data), and rendering each of them as
+text.
+
+Since `ffmpeg` can write either format to a pipe, this gets any video
+`ffmpeg` can decode into the crate without needing a video decoder:
+
+```text
+ffmpeg -i clip.mp4 -f yuv4mpegpipe - | your_program
+```
+*/
+
+use std::io::{BufRead, BufWriter, Read, Write};
+use std::time::{Duration, Instant};
+
+use image::ImageBuffer;
+
+use crate::{AnimationOptions, Animator, AsciicastWriter, Error, FontData, Image, RenderOptions};
+
+const Y4M_MAGIC: &[u8] = b"YUV4MPEG2";
+const Y4M_FRAME: &[u8] = b"FRAME";
+// Header lines longer than this are assumed to be garbage.
+const MAX_HEADER_LEN: usize = 4096;
+// Animation frame delays shorter than this get `DEFAULT_DELAY` instead,
+// as they do in web browsers. (So do still images.)
+#[cfg(any(feature = "gif", feature = "png", feature = "webp"))]
+const MIN_DELAY: Duration = Duration::from_millis(20);
+#[cfg(any(feature = "gif", feature = "png", feature = "webp"))]
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+fn io_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::IOError(format!("{}", &e))
+}
+
+/*
+How the pixel data of each frame is laid out.
+*/
+enum Layout {
+    // YUV4MPEG2: a luma plane of `bytes`-byte samples (full scale at
+    // `max`), followed by `chroma` more samples that get skipped.
+    Y4m {
+        bytes: usize,
+        max: f32,
+        full_range: bool,
+        chroma: usize,
+    },
+    Gray,
+    Rgb,
+    // Frames that have already been decoded, with their delays.
+    #[cfg(any(feature = "gif", feature = "png", feature = "webp"))]
+    Decoded(std::collections::VecDeque<(Image, Duration)>),
+}
+
+/**
+Reads frames, one at a time, from a stream of video data.
+
+Each frame is returned as an `Image`, ready to be rendered with any of
+the crate's writing functions (or all of them at once with
+`write_frames()`).
+
+```
+use ascii_art::FrameReader;
+
+// A 2x2 monochrome YUV4MPEG2 stream at 25 frames per second, with two
+// frames.
+let mut data: Vec<u8> = b"YUV4MPEG2 W2 H2 F25:1 Cmono\n".to_vec();
+data.extend_from_slice(b"FRAME\n\x00\x00\xff\xff");
+data.extend_from_slice(b"FRAME\n\xff\xff\x00\x00");
+
+let frames = FrameReader::y4m(&data[..]).unwrap();
+assert_eq!(frames.dimensions(), (2, 2));
+assert_eq!(frames.frame_rate(), Some(25.0));
+assert_eq!(frames.count(), 2);
+```
+*/
+pub struct FrameReader<R> {
+    r: R,
+    width: u32,
+    height: u32,
+    frame_rate: Option<f64>,
+    layout: Layout,
+    done: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    /**
+    Read frames from a YUV4MPEG2 (`.y4m`) stream, as written by
+    `ffmpeg -f yuv4mpegpipe`. The stream header is read immediately.
+
+    Only the luma plane of each frame is used. Samples may be 8 bits or
+    (with colorspaces like `C420p10`) more, and are taken to be
+    limited-range ("TV" levels, where black is 16 and white is 235)
+    unless the header says `XCOLORRANGE=FULL`, except for monochrome
+    (`Cmono`) streams, which are taken to be full-range unless the
+    header says `XCOLORRANGE=LIMITED`.
+    */
+    pub fn y4m(mut r: R) -> Result<FrameReader<R>, Error> {
+        let header = read_line(&mut r)?
+            .ok_or_else(|| Error::IOError("empty YUV4MPEG2 stream".to_owned()))?;
+        let mut params = header.split(|b| *b == b' ');
+        if params.next() != Some(Y4M_MAGIC) {
+            return Err(Error::IOError("not a YUV4MPEG2 stream".to_owned()));
+        }
+
+        let mut width: Option<u32> = None;
+        let mut height: Option<u32> = None;
+        let mut frame_rate: Option<f64> = None;
+        let mut colorspace = "420".to_owned();
+        let mut range: Option<bool> = None;
+        for param in params.filter(|p| !p.is_empty()) {
+            let value = String::from_utf8_lossy(&param[1..]);
+            let bad = || Error::IOError(format!("bad YUV4MPEG2 parameter: {}", value));
+            match param[0] {
+                b'W' => width = Some(value.parse().map_err(|_| bad())?),
+                b'H' => height = Some(value.parse().map_err(|_| bad())?),
+                b'F' => {
+                    let (n, d) = value.split_once(':').ok_or_else(bad)?;
+                    let (n, d): (f64, f64) =
+                        (n.parse().map_err(|_| bad())?, d.parse().map_err(|_| bad())?);
+                    if n > 0.0 && d > 0.0 {
+                        frame_rate = Some(n / d);
+                    }
+                }
+                b'C' => colorspace = value.into_owned(),
+                b'X' => match value.as_ref() {
+                    "COLORRANGE=FULL" => range = Some(true),
+                    "COLORRANGE=LIMITED" => range = Some(false),
+                    _ => {}
+                },
+                // Interlacing, pixel aspect ratio, and anything else don't
+                // matter here.
+                _ => {}
+            }
+        }
+
+        let (width, height) = match (width, height) {
+            (Some(w), Some(h)) => (w, h),
+            _ => {
+                return Err(Error::IOError(
+                    "YUV4MPEG2 header is missing the frame size".to_owned(),
+                ))
+            }
+        };
+        let (w, h) = (width as usize, height as usize);
+
+        // Split "420p10" (or "mono10") into the subsampling and the bit
+        // depth.
+        let depth_at = |n: usize| {
+            let digits = &colorspace[n..];
+            if digits.is_empty() {
+                Some(8)
+            } else {
+                digits.parse::<u32>().ok()
+            }
+        };
+        let (sampling, depth) = match colorspace.find('p') {
+            _ if colorspace.starts_with("mono") => ("mono", depth_at(4)),
+            Some(n) if depth_at(n + 1).is_some() => (&colorspace[..n], depth_at(n + 1)),
+            _ => (colorspace.as_str(), Some(8)),
+        };
+        let depth = depth.unwrap_or(0);
+        let chroma = match sampling {
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => 2 * w.div_ceil(2) * h.div_ceil(2),
+            "411" => 2 * w.div_ceil(4) * h,
+            "422" => 2 * w.div_ceil(2) * h,
+            "444" => 2 * w * h,
+            "444alpha" => 3 * w * h,
+            "mono" => 0,
+            _ => {
+                return Err(Error::IOError(format!(
+                    "unsupported YUV4MPEG2 colorspace: {}",
+                    colorspace
+                )))
+            }
+        };
+        if !(8..=16).contains(&depth) {
+            return Err(Error::IOError(format!(
+                "unsupported YUV4MPEG2 colorspace: {}",
+                colorspace
+            )));
+        }
+        let bytes = if depth > 8 { 2 } else { 1 };
+
+        Ok(FrameReader {
+            r,
+            width,
+            height,
+            frame_rate,
+            layout: Layout::Y4m {
+                bytes,
+                max: ((1u32 << depth) - 1) as f32,
+                full_range: range.unwrap_or(sampling == "mono"),
+                chroma: chroma * bytes,
+            },
+            done: false,
+        })
+    }
+
+    /**
+    Read frames of raw 8-bit grayscale pixels (`ffmpeg -f rawvideo
+    -pix_fmt gray`), each `width` by `height` pixels, with no headers or
+    padding.
+
+    Raw streams don't say how fast they go; see `.with_frame_rate()`.
+    */
+    pub fn raw_gray(r: R, width: u32, height: u32) -> FrameReader<R> {
+        FrameReader::raw(r, width, height, Layout::Gray)
+    }
+
+    /**
+    Read frames of raw 8-bit RGB pixels (`ffmpeg -f rawvideo -pix_fmt
+    rgb24`), each `width` by `height` pixels, with no headers or padding.
+
+    Raw streams don't say how fast they go; see `.with_frame_rate()`.
+    */
+    pub fn raw_rgb(r: R, width: u32, height: u32) -> FrameReader<R> {
+        FrameReader::raw(r, width, height, Layout::Rgb)
+    }
+
+    /**
+    Read the frames of an animated GIF, each of which is shown for its
+    own delay (rather than at a fixed frame rate). A GIF that isn't
+    animated has a single frame.
+
+    The whole GIF is decoded immediately.
+    */
+    #[cfg(feature = "gif")]
+    pub fn gif(mut r: R) -> Result<FrameReader<R>, Error> {
+        let decoder = image::codecs::gif::GifDecoder::new(&mut r).map_err(io_error)?;
+        let frames = decode_frames(decoder)?;
+        Ok(FrameReader::decoded(r, frames))
+    }
+
+    /**
+    Read the frames of an animated PNG (APNG), each of which is shown for
+    its own delay. A PNG that isn't animated has a single frame.
+
+    The whole PNG is decoded immediately.
+    */
+    #[cfg(feature = "png")]
+    pub fn png(mut r: R) -> Result<FrameReader<R>, Error> {
+        let decoder = image::codecs::png::PngDecoder::new(&mut r).map_err(io_error)?;
+        let frames = if decoder.is_apng() {
+            decode_frames(decoder.apng())?
+        } else {
+            still_frame(decoder)?
+        };
+        Ok(FrameReader::decoded(r, frames))
+    }
+
+    /**
+    Read the frames of an animated WebP, each of which is shown for its
+    own delay. A WebP that isn't animated has a single frame.
+
+    The whole WebP is decoded immediately.
+    */
+    #[cfg(feature = "webp")]
+    pub fn webp(mut r: R) -> Result<FrameReader<R>, Error> {
+        let decoder = image::codecs::webp::WebPDecoder::new(&mut r).map_err(io_error)?;
+        let frames = if decoder.has_animation() {
+            decode_frames(decoder)?
+        } else {
+            still_frame(decoder)?
+        };
+        Ok(FrameReader::decoded(r, frames))
+    }
+
+    #[cfg(any(feature = "gif", feature = "png", feature = "webp"))]
+    fn decoded(r: R, frames: std::collections::VecDeque<(Image, Duration)>) -> FrameReader<R> {
+        let (width, height) = frames
+            .front()
+            .map(|(img, _)| img.buff.dimensions())
+            .unwrap_or((0, 0));
+        FrameReader {
+            r,
+            width,
+            height,
+            frame_rate: None,
+            layout: Layout::Decoded(frames),
+            done: false,
+        }
+    }
+
+    fn raw(r: R, width: u32, height: u32, layout: Layout) -> FrameReader<R> {
+        FrameReader {
+            r,
+            width,
+            height,
+            frame_rate: None,
+            layout,
+            done: false,
+        }
+    }
+
+    /// Set (or override) the number of frames per second.
+    pub fn with_frame_rate(mut self, fps: f64) -> FrameReader<R> {
+        self.frame_rate = Some(fps).filter(|f| *f > 0.0);
+        self
+    }
+
+    /// The width and height (in pixels) of each frame.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The number of frames per second, if known (and constant).
+    pub fn frame_rate(&self) -> Option<f64> {
+        self.frame_rate
+    }
+
+    /**
+    Read the next frame, returning `None` at the end of the stream. A
+    stream that ends partway through a frame is an error.
+    */
+    pub fn next_frame(&mut self) -> Result<Option<Image>, Error> {
+        Ok(self.next_timed_frame()?.map(|(img, _)| img))
+    }
+
+    /**
+    Like `.next_frame()`, but also return how long the frame should be
+    shown: its own delay for GIFs, otherwise one over the frame rate (or
+    `FrameOutput::DEFAULT_FRAME_RATE`, if it isn't known).
+    */
+    pub fn next_timed_frame(&mut self) -> Result<Option<(Image, Duration)>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.read_frame() {
+            Ok(Some(frame)) => Ok(Some(frame)),
+            other => {
+                self.done = true;
+                other
+            }
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Option<(Image, Duration)>, Error> {
+        let n = (self.width as usize) * (self.height as usize);
+        let fps = self.frame_rate.unwrap_or(FrameOutput::DEFAULT_FRAME_RATE);
+        let delay = Duration::from_secs_f64(1.0 / fps);
+
+        let values: Vec<f32> = match &mut self.layout {
+            #[cfg(any(feature = "gif", feature = "png", feature = "webp"))]
+            Layout::Decoded(frames) => return Ok(frames.pop_front()),
+            Layout::Y4m {
+                bytes,
+                max,
+                full_range,
+                chroma,
+            } => {
+                let (bytes, max, full_range, chroma) = (*bytes, *max, *full_range, *chroma);
+                let header = match read_line(&mut self.r)? {
+                    Some(h) => h,
+                    None => return Ok(None),
+                };
+                if !header.starts_with(Y4M_FRAME) {
+                    return Err(Error::IOError("bad YUV4MPEG2 frame header".to_owned()));
+                }
+
+                let mut luma = vec![0u8; n * bytes];
+                self.r.read_exact(&mut luma).map_err(io_error)?;
+                let mut chroma = (&mut self.r).take(chroma as u64);
+                std::io::copy(&mut chroma, &mut std::io::sink()).map_err(io_error)?;
+                if chroma.limit() > 0 {
+                    return Err(Error::IOError("truncated YUV4MPEG2 frame".to_owned()));
+                }
+
+                // Limited range puts black at 16 and white at 235, scaled
+                // up for deeper samples.
+                let (black, white) = if full_range {
+                    (0.0, max)
+                } else {
+                    let scale = (max + 1.0) / 256.0;
+                    (16.0 * scale, 235.0 * scale)
+                };
+                let sample = |s: &[u8]| match s {
+                    [a] => *a as f32,
+                    [a, b] => u16::from_le_bytes([*a, *b]) as f32,
+                    _ => unreachable!(),
+                };
+                luma.chunks_exact(bytes)
+                    .map(|s| ((sample(s) - black) / (white - black)).clamp(0.0, 1.0))
+                    .collect()
+            }
+            layout => {
+                let channels = if matches!(layout, Layout::Gray) { 1 } else { 3 };
+                let mut buff = vec![0u8; n * channels];
+                if !read_all_or_nothing(&mut self.r, &mut buff)? {
+                    return Ok(None);
+                }
+                buff.chunks_exact(channels)
+                    .map(|p| match p {
+                        [v] => *v as f32 / 255.0,
+                        // The same weights `image` uses to convert to luma.
+                        [r, g, b] => {
+                            (0.2126 * *r as f32 + 0.7152 * *g as f32 + 0.0722 * *b as f32) / 255.0
+                        }
+                        _ => unreachable!(),
+                    })
+                    .collect()
+            }
+        };
+
+        let img = Image {
+            buff: ImageBuffer::from_raw(self.width, self.height, values).unwrap(),
+        };
+        Ok(Some((img, delay)))
+    }
+}
+
+impl<R: BufRead> FrameReader<R> {
+    /**
+    Read frames from a YUV4MPEG2 stream, or from an animated GIF, PNG, or
+    WebP (as enabled by the crate's features), depending on what the data
+    starts with.
+
+    ```
+    use ascii_art::FrameReader;
+
+    let data = b"YUV4MPEG2 W2 H1 F10:1 Cmono\nFRAME\n\x00\xff".to_vec();
+    let frames = FrameReader::auto(&data[..]).unwrap();
+    assert_eq!(frames.frame_rate(), Some(10.0));
+    assert!(FrameReader::auto(&b"not a video"[..]).is_err());
+    ```
+    */
+    pub fn auto(mut r: R) -> Result<FrameReader<R>, Error> {
+        let start = r.fill_buf().map_err(io_error)?;
+        if start.starts_with(Y4M_MAGIC) {
+            return FrameReader::y4m(r);
+        }
+        match image::guess_format(start) {
+            #[cfg(feature = "gif")]
+            Ok(image::ImageFormat::Gif) => FrameReader::gif(r),
+            #[cfg(feature = "png")]
+            Ok(image::ImageFormat::Png) => FrameReader::png(r),
+            #[cfg(feature = "webp")]
+            Ok(image::ImageFormat::WebP) => FrameReader::webp(r),
+            _ => Err(Error::IOError(
+                "not a YUV4MPEG2 stream or a (supported) animated image".to_owned(),
+            )),
+        }
+    }
+}
+
+/*
+Decode all the frames of an animation, with how long each is shown for.
+*/
+#[cfg(any(feature = "gif", feature = "png", feature = "webp"))]
+fn decode_frames<'a, D: image::AnimationDecoder<'a>>(
+    decoder: D,
+) -> Result<std::collections::VecDeque<(Image, Duration)>, Error> {
+    let mut frames = std::collections::VecDeque::new();
+    for frame in decoder.into_frames() {
+        let frame = frame.map_err(io_error)?;
+        let (num, denom) = frame.delay().numer_denom_ms();
+        let delay = Duration::from_secs_f64(num as f64 / (1000.0 * denom.max(1) as f64));
+        let delay = if delay < MIN_DELAY {
+            DEFAULT_DELAY
+        } else {
+            delay
+        };
+        let img = image::DynamicImage::ImageRgba8(frame.into_buffer());
+        frames.push_back((Image::from_decoded(&img), delay));
+    }
+    Ok(frames)
+}
+
+/*
+Decode a still image as a single frame.
+*/
+#[cfg(any(feature = "png", feature = "webp"))]
+fn still_frame<'a, D: image::ImageDecoder<'a>>(
+    decoder: D,
+) -> Result<std::collections::VecDeque<(Image, Duration)>, Error> {
+    let img = image::DynamicImage::from_decoder(decoder).map_err(io_error)?;
+    Ok(std::collections::VecDeque::from([(
+        Image::from_decoded(&img),
+        DEFAULT_DELAY,
+    )]))
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = Result<Image, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+/*
+Read a `\n`-terminated line (without the `\n`), or `None` if the reader
+is already at its end.
+*/
+fn read_line<R: Read>(r: &mut R) -> Result<Option<Vec<u8>>, Error> {
+    let mut line: Vec<u8> = Vec::new();
+    let mut byte = [0u8];
+    loop {
+        match r.read(&mut byte) {
+            Ok(0) if line.is_empty() => return Ok(None),
+            Ok(0) => return Err(Error::IOError("truncated YUV4MPEG2 header".to_owned())),
+            Ok(_) if byte[0] == b'\n' => return Ok(Some(line)),
+            Ok(_) => line.push(byte[0]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(io_error(e)),
+        }
+        if line.len() > MAX_HEADER_LEN {
+            return Err(Error::IOError("YUV4MPEG2 header too long".to_owned()));
+        }
+    }
+}
+
+/*
+Fill `buff`, returning `false` if the reader was already at its end, and
+an error if it ends partway through.
+*/
+fn read_all_or_nothing<R: Read>(r: &mut R, buff: &mut [u8]) -> Result<bool, Error> {
+    let mut filled = 0;
+    while filled < buff.len() {
+        match r.read(&mut buff[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(Error::IOError("truncated frame".to_owned())),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(io_error(e)),
+        }
+    }
+    Ok(true)
+}
+
+/**
+How `write_frames()` separates one frame of text from the next.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameOutput {
+    /// Write each frame's text followed by a line holding just a form
+    /// feed (`\x0c`), as fast as the frames can be rendered.
+    Separated,
+    /// Write escape sequences that clear the terminal and then redraw
+    /// each frame in place, pausing between frames to play back at the
+    /// speed of the original (see `FrameReader::next_timed_frame()`).
+    Playback,
+    /// Write an asciicast v2 recording (see `AsciicastWriter`) that plays
+    /// back at the speed of the original.
+    Asciicast,
+}
+
+impl FrameOutput {
+    /// The frame rate used for playing back streams that don't have one.
+    pub const DEFAULT_FRAME_RATE: f64 = 24.0;
+}
+
+/**
+Render each frame from `frames` as text with `font` (as `write_with()`
+would), writing them one after another to `writer` as specified by
+`output`.
+
+```
+use ascii_art::{FrameOutput, FrameReader, RenderOptions};
+# let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+# let font = ascii_art::FontData::from_font_bytes(
+#     &bytes, 12.0, &ascii_art::printable_ascii()
+# ).unwrap().unwrap();
+
+// Three blank 64x64 frames.
+let data = vec![0u8; 3 * 64 * 64];
+let mut frames = FrameReader::raw_gray(&data[..], 64, 64);
+
+let mut out: Vec<u8> = Vec::new();
+ascii_art::write_frames(
+    &mut frames, &font, &RenderOptions::default(), FrameOutput::Separated, &mut out
+).unwrap();
+assert_eq!(out.iter().filter(|b| **b == b'\x0c').count(), 3);
+```
+*/
+pub fn write_frames<R: Read, W: Write>(
+    frames: &mut FrameReader<R>,
+    font: &FontData,
+    opts: &RenderOptions,
+    output: FrameOutput,
+    writer: W,
+) -> Result<(), Error> {
+    write_frames_with(
+        frames,
+        font,
+        opts,
+        &AnimationOptions::default(),
+        output,
+        writer,
+    )
+}
+
+/**
+Like `write_frames()`, but carrying each frame over into the next as
+specified by `anim` (see `Animator`).
+
+`AnimationOptions::diff` only makes a difference with
+`FrameOutput::Playback` and `FrameOutput::Asciicast`, as separated frames
+are always written in full.
+*/
+pub fn write_frames_with<R: Read, W: Write>(
+    frames: &mut FrameReader<R>,
+    font: &FontData,
+    opts: &RenderOptions,
+    anim: &AnimationOptions,
+    output: FrameOutput,
+    writer: W,
+) -> Result<(), Error> {
+    let mut writer = BufWriter::new(writer);
+    let mut animator = Animator::new(font, opts, anim);
+
+    if output == FrameOutput::Asciicast {
+        let mut cast = AsciicastWriter::new(writer);
+        while let Some((img, delay)) = frames.next_timed_frame()? {
+            let data = animator.update(&img);
+            let (w, h) = animator.size();
+            cast.frame_data((w as usize, h as usize), &data, delay)?;
+        }
+        cast.finish()?;
+        return Ok(());
+    }
+
+    if output == FrameOutput::Playback {
+        // Clear the screen and hide the cursor.
+        write!(writer, "\x1b[2J\x1b[?25l").map_err(io_error)?;
+    }
+
+    let start = Instant::now();
+    // How long after `start` the next frame is due.
+    let mut due = Duration::ZERO;
+    let result = loop {
+        let (img, delay) = match frames.next_timed_frame() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        };
+
+        let written = if output == FrameOutput::Playback {
+            let data = animator.update(&img);
+            if let Some(wait) = (start + due).checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+            due += delay;
+            write!(writer, "{}", data).and_then(|_| writer.flush())
+        } else {
+            animator
+                .render(&img)
+                .iter()
+                .try_for_each(|row| writeln!(writer, "{}", row))
+                .and_then(|_| writeln!(writer, "\x0c"))
+        };
+        if let Err(e) = written {
+            break Err(io_error(e));
+        }
+    };
+
+    if output == FrameOutput::Playback {
+        // Leave the cursor below the last frame, and show it again (even
+        // if something went wrong).
+        write!(writer, "\r\n\x1b[?25h").map_err(io_error)?;
+    }
+    writer.flush().map_err(io_error)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn y4m(header: &str, frames: &[&[u8]]) -> Vec<u8> {
+        let mut data = header.as_bytes().to_vec();
+        for f in frames.iter() {
+            data.extend_from_slice(b"FRAME\n");
+            data.extend_from_slice(f);
+        }
+        data
+    }
+
+    #[test]
+    fn y4m_frames() {
+        // 4x2 4:2:0, so each frame has 8 bytes of luma and 2 * 2 of chroma.
+        let data = y4m(
+            "YUV4MPEG2 W4 H2 F30000:1001 It A1:1 C420jpeg XYSCSS=420JPEG\n",
+            &[
+                &[16, 16, 235, 235, 16, 126, 235, 255, 1, 2, 3, 4],
+                &[235; 12],
+            ],
+        );
+        let mut frames = FrameReader::y4m(&data[..]).unwrap();
+        assert_eq!(frames.dimensions(), (4, 2));
+        assert!((frames.frame_rate().unwrap() - 29.97).abs() < 0.01);
+
+        let img = frames.next_frame().unwrap().unwrap();
+        let px: Vec<f32> = img.buff.as_raw().clone();
+        assert_eq!(&px[..4], &[0.0, 0.0, 1.0, 1.0]);
+        assert!((px[5] - 0.5).abs() < 0.01);
+        assert_eq!(px[7], 1.0);
+        let img = frames.next_frame().unwrap().unwrap();
+        assert!(img.buff.as_raw().iter().all(|p| *p == 1.0));
+        assert!(frames.next_frame().unwrap().is_none());
+
+        // Full range, 10-bit mono.
+        let data = y4m(
+            "YUV4MPEG2 W2 H1 F25:1 Cmono10 XCOLORRANGE=FULL\n",
+            &[&[0, 0, 0xff, 0x03]],
+        );
+        let img = FrameReader::y4m(&data[..])
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(img.buff.as_raw(), &vec![0.0, 1.0]);
+
+        // Limited range, 10-bit 4:4:4; the chroma planes get skipped.
+        let mut frame = vec![0x40, 0x00, 0xac, 0x03];
+        frame.extend_from_slice(&[0x55; 8]);
+        let data = y4m("YUV4MPEG2 W2 H1 C444p10\n", &[&frame, &frame]);
+        let frames: Vec<Image> = FrameReader::y4m(&data[..])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].buff.as_raw(), &vec![0.0, 1.0]);
+
+        // A frame cut short is an error, and the end of the frames.
+        let data = y4m("YUV4MPEG2 W2 H2 Cmono\n", &[&[0, 0, 0, 0], &[0, 0]]);
+        let results: Vec<_> = FrameReader::y4m(&data[..]).unwrap().collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok() && results[1].is_err());
+
+        assert!(FrameReader::y4m(&b"YUV4MPEG2 W2 C420\n"[..]).is_err());
+        assert!(FrameReader::y4m(&b"P5 2 2 255\n"[..]).is_err());
+    }
+
+    #[test]
+    fn raw_frames() {
+        let data = [0u8, 255, 255, 0, 0, 0];
+        let frames: Vec<Image> = FrameReader::raw_gray(&data[..], 3, 1)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].buff.as_raw(), &vec![0.0, 1.0, 1.0]);
+
+        let data = [255u8, 255, 255, 255, 0, 0];
+        let mut frames = FrameReader::raw_rgb(&data[..], 2, 1).with_frame_rate(10.0);
+        assert_eq!(frames.frame_rate(), Some(10.0));
+        let img = frames.next_frame().unwrap().unwrap();
+        assert_eq!(img.buff.as_raw()[0], 1.0);
+        assert!((img.buff.as_raw()[1] - 0.2126).abs() < 0.001);
+        assert!(frames.next_frame().unwrap().is_none());
+
+        assert!(FrameReader::raw_gray(&data[..4], 3, 1)
+            .nth(1)
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn write() {
+        let bytes = std::fs::read("test/LiberationMono-Regular.ttf").unwrap();
+        let font = FontData::from_font_bytes(&bytes, 12.0, &crate::printable_ascii())
+            .unwrap()
+            .unwrap();
+        let opts = RenderOptions::default();
+        let data = vec![0u8; 2 * 70 * 30];
+
+        let mut frame: Vec<u8> = Vec::new();
+        let img = FrameReader::raw_gray(&data[..], 70, 30)
+            .next()
+            .unwrap()
+            .unwrap();
+        crate::write_with(&img, &font, &opts, &mut frame).unwrap();
+        let frame = String::from_utf8(frame).unwrap();
+
+        let mut frames = FrameReader::raw_gray(&data[..], 70, 30);
+        let mut out: Vec<u8> = Vec::new();
+        write_frames(&mut frames, &font, &opts, FrameOutput::Separated, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, format!("{}\x0c\n", frame).repeat(2));
+
+        let mut frames = FrameReader::raw_gray(&data[..], 70, 30).with_frame_rate(1000.0);
+        let mut out: Vec<u8> = Vec::new();
+        write_frames(&mut frames, &font, &opts, FrameOutput::Playback, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("\x1b[2J\x1b[?25l\x1b[H"));
+        assert!(out.ends_with("\x1b[?25h"));
+        assert_eq!(out.matches("\x1b[H").count(), 2);
+
+        let mut frames = FrameReader::raw_gray(&data[..], 70, 30).with_frame_rate(4.0);
+        let mut out: Vec<u8> = Vec::new();
+        write_frames(&mut frames, &font, &opts, FrameOutput::Asciicast, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        let (w, h) = (frame.lines().next().unwrap().len(), frame.lines().count());
+        assert_eq!(
+            lines[0],
+            format!(r#"{{"version":2,"width":{},"height":{}}}"#, w, h)
+        );
+        let event = format!(
+            "\\u001b[H{}",
+            frame.strip_suffix('\n').unwrap().replace('\n', "\\r\\n")
+        );
+        assert_eq!(lines[1], format!(r#"[0.0,"o","{}"]"#, event));
+        assert_eq!(lines[2], format!(r#"[0.25,"o","{}"]"#, event));
+        assert_eq!(lines[3], r#"[0.5,"o",""]"#);
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn gif_frames() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let mut data: Vec<u8> = Vec::new();
+        {
+            let mut enc = GifEncoder::new(&mut data);
+            for (shade, ms) in [(0u8, 50u32), (255, 0)] {
+                let buff = RgbaImage::from_pixel(3, 2, Rgba([shade, shade, shade, 255]));
+                let delay = Delay::from_numer_denom_ms(ms, 1);
+                enc.encode_frame(Frame::from_parts(buff, 0, 0, delay))
+                    .unwrap();
+            }
+        }
+
+        let mut frames = FrameReader::gif(&data[..]).unwrap();
+        assert_eq!(frames.dimensions(), (3, 2));
+        assert_eq!(frames.frame_rate(), None);
+        let (img, delay) = frames.next_timed_frame().unwrap().unwrap();
+        assert!(img.buff.as_raw().iter().all(|p| *p == 0.0));
+        assert_eq!(delay, Duration::from_millis(50));
+        // Too-short delays get the default.
+        let (img, delay) = frames.next_timed_frame().unwrap().unwrap();
+        assert!(img.buff.as_raw().iter().all(|p| *p == 1.0));
+        assert_eq!(delay, DEFAULT_DELAY);
+        assert!(frames.next_timed_frame().unwrap().is_none());
+
+        // It's recognized as a GIF, too.
+        let frames = FrameReader::auto(&data[..]).unwrap();
+        assert_eq!(frames.count(), 2);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_frames() {
+        let mut data: Vec<u8> = Vec::new();
+        {
+            let mut enc = png::Encoder::new(&mut data, 2, 2);
+            enc.set_color(png::ColorType::Grayscale);
+            enc.set_animated(3, 0).unwrap();
+            let mut w = enc.write_header().unwrap();
+            for (shade, ms) in [(0u8, 40u16), (128, 250), (255, 40)] {
+                w.set_frame_delay(ms, 1000).unwrap();
+                w.write_image_data(&[shade; 4]).unwrap();
+            }
+        }
+
+        let mut frames = FrameReader::auto(&data[..]).unwrap();
+        assert_eq!(frames.dimensions(), (2, 2));
+        let mut delays = Vec::new();
+        while let Some((img, delay)) = frames.next_timed_frame().unwrap() {
+            assert_eq!(img.dimensions(), (2, 2));
+            delays.push(delay.as_millis());
+        }
+        assert_eq!(delays, vec![40, 250, 40]);
+
+        // A PNG that isn't animated is a single frame.
+        let mut still: Vec<u8> = Vec::new();
+        {
+            let mut enc = png::Encoder::new(&mut still, 1, 1);
+            enc.set_color(png::ColorType::Grayscale);
+            let mut w = enc.write_header().unwrap();
+            w.write_image_data(&[77]).unwrap();
+        }
+        assert_eq!(FrameReader::png(&still[..]).unwrap().count(), 1);
+    }
+}
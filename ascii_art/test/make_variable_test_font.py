@@ -0,0 +1,238 @@
+#!/usr/bin/env python3
+"""
+Generate VariableTest.ttf, the tiny variable font the `font_options` tests
+use, with nothing but the standard library:
+
+    python3 make_variable_test_font.py [OUTPUT]
+
+(OUTPUT defaults to VariableTest.ttf next to this script.)
+
+The font has an em of 1000 units, every glyph is 1000 units wide, and
+there are three characters:
+
+    space   empty
+    I       a bar from x = 400 to 600 (1/4 as wide as the `#`)
+    #       a block from x = 100 to 900
+
+There's one axis, `wght`, from 100 to 900 (default 100), with named
+instances "Light" (wght 100) and "Bold" (wght 900). The only variation is
+that, at full weight, the `I` is 250 units wider on each side: from 150
+to 850, or 7/8 as wide as the `#`. In between, it widens linearly.
+"""
+import os
+import struct
+import sys
+
+UPEM = 1000
+
+# Glyph IDs.
+NOTDEF, SPACE, BAR, BLOCK = range(4)
+CMAP = {0x20: SPACE, 0x23: BLOCK, 0x49: BAR}
+
+# The bar's outline, and how far its points move at full weight (in the
+# same order as the points).
+BAR_RECT = (400, 0, 600, 1000)
+BLOCK_RECT = (100, 0, 900, 1000)
+BAR_DELTAS = (-250, -250, 250, 250)
+
+NAMES = {
+    1: "AA Test Var",
+    2: "Regular",
+    4: "AA Test Var Regular",
+    6: "AATestVar-Regular",
+    256: "Light",
+    257: "Bold",
+    258: "Weight",
+}
+LIGHT_NAME, BOLD_NAME, AXIS_NAME = 256, 257, 258
+
+
+def fixed(v):
+    """A 16.16 fixed-point number."""
+    return struct.pack(">i", int(round(v * 65536)))
+
+
+def pad4(data):
+    return data + b"\0" * (-len(data) % 4)
+
+
+def rect_glyph(x0, y0, x1, y1):
+    """
+    A `glyf` entry for a rectangle: one contour of four on-curve points,
+    going (x0, y0), (x0, y1), (x1, y1), (x1, y0).
+    """
+    points = [(x0, y0), (x0, y1), (x1, y1), (x1, y0)]
+    data = struct.pack(">hhhhh", 1, x0, y0, x1, y1)
+    data += struct.pack(">HH", len(points) - 1, 0)  # endPts, no instructions
+    data += bytes([1] * len(points))  # flags: on curve, word coordinates
+    xs, ys, px, py = b"", b"", 0, 0
+    for x, y in points:
+        xs += struct.pack(">h", x - px)
+        ys += struct.pack(">h", y - py)
+        px, py = x, y
+    return pad4(data + xs + ys)
+
+
+def glyf_and_loca():
+    glyphs = [b"", b"", rect_glyph(*BAR_RECT), rect_glyph(*BLOCK_RECT)]
+    loca, offset = b"", 0
+    for g in glyphs:
+        loca += struct.pack(">I", offset)
+        offset += len(g)
+    loca += struct.pack(">I", offset)
+    return b"".join(glyphs), loca
+
+
+def head():
+    # checkSumAdjustment (the fourth field) is filled in at the end.
+    return struct.pack(
+        ">IIIIHHqqhhhhHHhhh",
+        0x10000, 0x10000, 0, 0x5F0F3CF5,  # versions, checksum, magic
+        0, UPEM,  # flags, unitsPerEm
+        0, 0,  # created, modified
+        0, 0, 1000, 1000,  # bounding box
+        0, 8,  # macStyle, lowestRecPPEM
+        2, 1, 0,  # direction hint, long loca offsets, glyph data format
+    )
+
+
+def hhea():
+    return struct.pack(
+        ">IhhhHhhhhhhhhhhhH",
+        0x10000,
+        1000, -200, 0,  # ascender, descender, line gap
+        1000, 0, 0, 1000,  # max advance, min side bearings, max extent
+        1, 0, 0,  # caret slope and offset
+        0, 0, 0, 0, 0,  # reserved, metric data format
+        4,  # number of hMetrics
+    )
+
+
+def maxp():
+    return struct.pack(">IH", 0x10000, 4) + struct.pack(
+        ">13H", 4, 1, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0
+    )
+
+
+def hmtx():
+    lsbs = (0, 0, BAR_RECT[0], BLOCK_RECT[0])
+    return b"".join(struct.pack(">Hh", 1000, lsb) for lsb in lsbs)
+
+
+def cmap():
+    """A Windows Unicode BMP `cmap` with a format 4 subtable."""
+    chars = sorted(CMAP)
+    n = len(chars) + 1  # and the final 0xFFFF segment
+    ends = chars + [0xFFFF]
+    deltas = [(CMAP[c] - c) % 65536 for c in chars] + [1]
+    search_range = 2 ** (n.bit_length() - 1) * 2
+    sub = struct.pack(
+        ">HHHHHHH",
+        4, 0, 0,  # format, length (filled in below), language
+        n * 2, search_range, (search_range // 2).bit_length() - 1, n * 2 - search_range,
+    )
+    sub += struct.pack(">%dH" % n, *ends) + b"\0\0"
+    sub += struct.pack(">%dH" % n, *ends)  # one character per segment
+    sub += struct.pack(">%dH" % n, *deltas)
+    sub += struct.pack(">%dH" % n, *[0] * n)
+    sub = sub[:2] + struct.pack(">H", len(sub)) + sub[4:]
+    return struct.pack(">HHHHI", 0, 1, 3, 1, 12) + sub
+
+
+def name():
+    records, strings = b"", b""
+    for name_id, s in sorted(NAMES.items()):
+        e = s.encode("utf-16-be")
+        records += struct.pack(">HHHHHH", 3, 1, 0x409, name_id, len(e), len(strings))
+        strings += e
+    return struct.pack(">HHH", 0, len(NAMES), 6 + len(records)) + records + strings
+
+
+def post():
+    return struct.pack(">IIhhIIIII", 0x30000, 0, -100, 50, 1, 0, 0, 0, 0)
+
+
+def fvar():
+    data = struct.pack(">HHHHHHHH", 1, 0, 16, 2, 1, 20, 2, 8)
+    data += b"wght" + fixed(100) + fixed(100) + fixed(900) + struct.pack(">HH", 0, AXIS_NAME)
+    data += struct.pack(">HH", LIGHT_NAME, 0) + fixed(100)
+    data += struct.pack(">HH", BOLD_NAME, 0) + fixed(900)
+    return data
+
+
+def gvar():
+    """
+    One shared tuple (wght at its maximum), and variation data only for
+    the bar: its four points move by `BAR_DELTAS` in x and not at all in
+    y (nor do the four phantom points).
+    """
+    # Serialized data: shared point numbers ("all points"), then the
+    # x deltas (four words, then four zeros for the phantom points) and
+    # the y deltas (eight zeros).
+    points = bytes([0])
+    deltas = bytes([0x40 | 3]) + struct.pack(">4h", *BAR_DELTAS) + bytes([0x80 | 3])
+    deltas += bytes([0x80 | 7])
+    # The tuple's data size doesn't count the shared point numbers.
+    glyph = struct.pack(">HH", 0x8000 | 1, 8) + struct.pack(">HH", len(deltas), 0)
+    glyph += points + deltas
+    glyph += b"\0" * (len(glyph) % 2)
+
+    shared_tuples = struct.pack(">h", 16384)  # wght = 1.0 (F2DOT14)
+    header_len, offsets_len = 20, 2 * 5  # short offsets, one per glyph + 1
+    shared_offset = header_len + offsets_len
+    array_offset = shared_offset + len(shared_tuples)
+    offsets = [0, 0, 0, len(glyph), len(glyph)]
+    return (
+        struct.pack(">HHHHIHHI", 1, 0, 1, 1, shared_offset, 4, 0, array_offset)
+        + struct.pack(">5H", *[o // 2 for o in offsets])
+        + shared_tuples
+        + glyph
+    )
+
+
+def checksum(data):
+    data = pad4(data)
+    return sum(struct.unpack(">%dI" % (len(data) // 4), data)) & 0xFFFFFFFF
+
+
+def font():
+    glyf, loca = glyf_and_loca()
+    tables = {
+        "cmap": cmap(),
+        "fvar": fvar(),
+        "glyf": glyf,
+        "gvar": gvar(),
+        "head": head(),
+        "hhea": hhea(),
+        "hmtx": hmtx(),
+        "loca": loca,
+        "maxp": maxp(),
+        "name": name(),
+        "post": post(),
+    }
+
+    n = len(tables)
+    search_range = 2 ** (n.bit_length() - 1)
+    directory = struct.pack(
+        ">IHHHH",
+        0x10000, n, search_range * 16, search_range.bit_length() - 1, (n - search_range) * 16,
+    )
+    offset, body = 12 + 16 * n, b""
+    for tag in sorted(tables):
+        data = tables[tag]
+        directory += tag.encode() + struct.pack(">III", checksum(data), offset + len(body), len(data))
+        body += pad4(data)
+
+    data = bytearray(directory + body)
+    adjustment = (0xB1B0AFBA - checksum(bytes(data))) & 0xFFFFFFFF
+    head_entry = data.find(b"head", 12)
+    head_offset = struct.unpack(">I", data[head_entry + 8 : head_entry + 12])[0]
+    data[head_offset + 8 : head_offset + 12] = struct.pack(">I", adjustment)
+    return bytes(data)
+
+
+if __name__ == "__main__":
+    here = os.path.dirname(os.path.abspath(__file__))
+    path = sys.argv[1] if len(sys.argv) > 1 else os.path.join(here, "VariableTest.ttf")
+    with open(path, "wb") as f:
+        f.write(font())
@@ -0,0 +1,378 @@
+/*!
+Converting many image files at once.
+
+The inputs are files and directories; directories are searched (and,
+optionally, their subdirectories, too) for image files, and each image
+found is written to a file in the output directory, at the same relative
+location as it was in the input directory.
+*/
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::{ErrorShim, Render};
+
+/// Extensions of files in input directories that get converted (when
+/// no `--glob` patterns are given).
+const IMAGE_EXTENSIONS: &[&str] = &[
+    "bmp", "gif", "ico", "jpeg", "jpg", "pbm", "pgm", "png", "pnm", "ppm", "tga", "tif", "tiff",
+    "webp",
+];
+
+/**
+Everything needed to convert a batch of files.
+*/
+pub struct Batch {
+    /// files and directories to convert
+    pub inputs: Vec<PathBuf>,
+    /// search subdirectories of input directories
+    pub recursive: bool,
+    /// file name patterns to convert from input directories
+    pub globs: Vec<String>,
+    /// directory to write output files to
+    pub out_dir: PathBuf,
+    /// output file name template
+    pub name: String,
+    /// output file extension
+    pub ext: String,
+    /// number of files to convert at once
+    pub threads: usize,
+}
+
+/// Items (by their index) that couldn't be converted, and why.
+type Failures = Vec<(usize, ErrorShim)>;
+
+/**
+An image file to convert, and the directory (relative to the output
+directory) its output goes in.
+*/
+struct Item {
+    path: PathBuf,
+    subdir: PathBuf,
+}
+
+/**
+Return whether `name` matches the shell-style wildcard `pattern`, where
+`*` matches any run of characters and `?` matches any one character.
+*/
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to go back to if the most recent `*` needs to match more.
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    star = Some((sp, sn + 1));
+                    p = sp + 1;
+                    n = sn + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl Batch {
+    /*
+    Return whether a file found in an input directory should be converted.
+    */
+    fn wants(&self, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy(),
+            None => return false,
+        };
+        if self.globs.is_empty() {
+            match path.extension() {
+                Some(ext) => {
+                    let ext = ext.to_string_lossy().to_lowercase();
+                    IMAGE_EXTENSIONS.contains(&ext.as_str())
+                }
+                None => false,
+            }
+        } else {
+            self.globs.iter().any(|g| glob_match(g, &name))
+        }
+    }
+
+    /*
+    Add the wanted files in `dir` (and its subdirectories, if searching
+    recursively) to `items`, in order.
+    */
+    fn search(&self, dir: &Path, subdir: &Path, items: &mut Vec<Item>) -> Result<(), ErrorShim> {
+        let mut entries: Vec<PathBuf> = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            entries.push(entry?.path());
+        }
+        entries.sort();
+
+        for path in entries.into_iter() {
+            if path.is_dir() {
+                if self.recursive {
+                    let subdir = subdir.join(path.file_name().unwrap_or_default());
+                    self.search(&path, &subdir, items)?;
+                }
+            } else if self.wants(&path) {
+                items.push(Item {
+                    path,
+                    subdir: subdir.to_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /*
+    Gather all the files to convert. Files named explicitly are always
+    converted, whatever their names.
+    */
+    fn items(&self) -> Result<Vec<Item>, ErrorShim> {
+        let mut items: Vec<Item> = Vec::new();
+        for input in self.inputs.iter() {
+            if input.is_dir() {
+                self.search(input, Path::new(""), &mut items)?;
+            } else {
+                items.push(Item {
+                    path: input.clone(),
+                    subdir: PathBuf::new(),
+                });
+            }
+        }
+        Ok(items)
+    }
+
+    /*
+    Return the path of the output file for `item`.
+
+    The name template can contain `{stem}` (the input file's name without
+    its extension), `{name}` (its whole name), and `{ext}` (the output
+    extension). What it makes has to be a plain file name, so the output
+    stays where it belongs in the output directory.
+    */
+    fn output_path(&self, item: &Item) -> Result<PathBuf, ErrorShim> {
+        let stem = item.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = item.path.file_name().unwrap_or_default().to_string_lossy();
+        let file_name = self
+            .name
+            .replace("{stem}", &stem)
+            .replace("{name}", &name)
+            .replace("{ext}", &self.ext);
+
+        let mut components = Path::new(&file_name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) if !file_name.ends_with('/') => {
+                Ok(self.out_dir.join(&item.subdir).join(file_name))
+            }
+            _ => Err(ErrorShim(format!(
+                "output file name \"{}\" (from --name \"{}\") isn't a plain file name",
+                file_name, &self.name
+            ))),
+        }
+    }
+
+    /*
+    Work out where each of `items` gets written. Returns the items to
+    convert (by index) with their output paths, and the ones that can't
+    be: those with bad output file names, and those whose output file
+    is already the output file of an earlier item.
+    */
+    fn plan(&self, items: &[Item]) -> (Vec<(usize, PathBuf)>, Failures) {
+        let mut jobs: Vec<(usize, PathBuf)> = Vec::new();
+        let mut failures: Failures = Vec::new();
+        let mut claimed: HashMap<PathBuf, usize> = HashMap::new();
+
+        for (n, item) in items.iter().enumerate() {
+            let dest = match self.output_path(item) {
+                Ok(dest) => dest,
+                Err(e) => {
+                    failures.push((n, e));
+                    continue;
+                }
+            };
+            match claimed.get(&dest) {
+                Some(first) => {
+                    let estr = format!(
+                        "output file {} would overwrite the output of {}",
+                        dest.display(),
+                        items[*first].path.display()
+                    );
+                    failures.push((n, ErrorShim(estr)));
+                }
+                None => {
+                    claimed.insert(dest.clone(), n);
+                    jobs.push((n, dest));
+                }
+            }
+        }
+        (jobs, failures)
+    }
+
+    fn convert(&self, render: &Render, item: &Item, dest: &Path) -> Result<(), ErrorShim> {
+        // Decode the image first, so there's no output file if it fails.
+        let picture = render.decode(BufReader::new(File::open(&item.path)?))?;
+
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        render.write(&picture, File::create(dest)?)
+    }
+
+    /**
+    Convert every file in the batch, `threads` at a time, then print a
+    summary (and any errors) to stderr. Returns whether every file was
+    converted successfully.
+
+    Files whose output would overwrite that of another file in the batch
+    count as failures, and aren't converted.
+    */
+    pub fn run(&self, render: &Render) -> Result<bool, ErrorShim> {
+        let items = self.items()?;
+        let (jobs, failures) = self.plan(&items);
+        let next = AtomicUsize::new(0);
+        let failures: Mutex<Failures> = Mutex::new(failures);
+
+        std::thread::scope(|s| {
+            for _ in 0..self.threads.clamp(1, jobs.len().max(1)) {
+                s.spawn(|| {
+                    while let Some((n, dest)) = jobs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        if let Err(e) = self.convert(render, &items[*n], dest) {
+                            failures.lock().unwrap().push((*n, e));
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut failures = failures.into_inner().unwrap();
+        failures.sort_by_key(|(n, _)| *n);
+        for (n, e) in failures.iter() {
+            eprintln!("{}: {:?}", items[*n].path.display(), e);
+        }
+        eprintln!(
+            "{} of {} files converted, {} failed.",
+            items.len() - failures.len(),
+            items.len(),
+            failures.len()
+        );
+        Ok(failures.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(name: &str) -> Batch {
+        Batch {
+            inputs: Vec::new(),
+            recursive: true,
+            globs: Vec::new(),
+            out_dir: PathBuf::from("out"),
+            name: name.to_owned(),
+            ext: "txt".to_owned(),
+            threads: 1,
+        }
+    }
+
+    fn item(path: &str, subdir: &str) -> Item {
+        Item {
+            path: PathBuf::from(path),
+            subdir: PathBuf::from(subdir),
+        }
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("*.jpg", "griffin.jpg"));
+        assert!(!glob_match("*.jpg", "griffin.jpeg"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("g?iffin.*", "griffin.jpg"));
+        assert!(!glob_match("g?iffin.*", "gffin.jpg"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(!glob_match("*a*b", "xxbxxa"));
+        assert!(glob_match("**.png", "a.png"));
+        assert!(glob_match("*.tar.*", "x.tar.tar.gz"));
+        assert!(!glob_match("?", ""));
+        assert!(glob_match("ñ?", "ñé"));
+    }
+
+    #[test]
+    fn output_paths() {
+        let path = |name: &str, item: &Item| batch(name).output_path(item).map_err(|e| e.0);
+        let griffin = item("photos/griffin.jpg", "");
+        let nested = item("photos/birds/owl.png", "birds");
+
+        assert_eq!(
+            path("{stem}.{ext}", &griffin),
+            Ok(PathBuf::from("out/griffin.txt"))
+        );
+        assert_eq!(
+            path("{stem}.{ext}", &nested),
+            Ok(PathBuf::from("out/birds/owl.txt"))
+        );
+        assert_eq!(
+            path("{name}-{stem}.{ext}.bak", &griffin),
+            Ok(PathBuf::from("out/griffin.jpg-griffin.txt.bak"))
+        );
+        assert_eq!(path("art", &griffin), Ok(PathBuf::from("out/art")));
+        assert_eq!(
+            path("..{stem}", &griffin),
+            Ok(PathBuf::from("out/..griffin"))
+        );
+
+        for bad in [
+            "{stem}/{ext}",
+            "../{stem}",
+            "..",
+            ".",
+            "",
+            "/tmp/{stem}",
+            "{stem}/",
+        ] {
+            assert!(path(bad, &griffin).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn collisions() {
+        let items = [
+            item("a/griffin.jpg", ""),
+            item("b/griffin.jpg", ""),
+            item("b/griffin.png", "sub"),
+            item("c/owl.jpg", ""),
+        ];
+
+        let (jobs, failures) = batch("{stem}.{ext}").plan(&items);
+        let converted: Vec<usize> = jobs.iter().map(|(n, _)| *n).collect();
+        assert_eq!(converted, vec![0, 2, 3]);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, 1);
+        assert!(failures[0].1 .0.contains("a/griffin.jpg"));
+
+        // Without `{stem}` or `{name}`, everything in a directory collides.
+        let (jobs, failures) = batch("art.{ext}").plan(&items);
+        let converted: Vec<usize> = jobs.iter().map(|(n, _)| *n).collect();
+        assert_eq!(converted, vec![0, 2]);
+        let failed: Vec<usize> = failures.iter().map(|(n, _)| *n).collect();
+        assert_eq!(failed, vec![1, 3]);
+
+        let (jobs, failures) = batch("../{stem}").plan(&items);
+        assert!(jobs.is_empty());
+        assert_eq!(failures.len(), items.len());
+    }
+}
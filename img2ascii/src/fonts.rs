@@ -0,0 +1,306 @@
+/*!
+Finding, loading, and describing fonts.
+*/
+use std::ffi::CString;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ascii_art::{FontData, FontLibrary};
+
+use crate::{Args, ErrorShim};
+
+/// Fontconfig's `spacing` value for monospaced fonts. (Character-cell
+/// fonts, which are also monospaced, are 110.)
+const FC_MONO: i32 = 100;
+
+/**
+Where to get the font from: the family (looked up with Fontconfig, or
+in the library, if there is one) or the file, the size, and the
+characters to analyze it for.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct FontSpec {
+    pub name: String,
+    pub file: Option<String>,
+    pub lib: Option<String>,
+    pub pixels: f32,
+    /// the characters to render with, if not printable ASCII
+    pub charset: Option<String>,
+}
+
+impl From<&Args> for FontSpec {
+    fn from(args: &Args) -> Self {
+        FontSpec {
+            name: args.font.clone(),
+            file: args.font_file.clone(),
+            lib: args.font_lib.clone(),
+            pixels: args.pixels,
+            charset: args.charset.clone(),
+        }
+    }
+}
+
+/**
+A font, found and analyzed.
+*/
+#[derive(Clone)]
+pub struct Resolved {
+    /// the analyzed font
+    pub font: FontData,
+    /// the font file (or font library) it came from
+    pub path: String,
+    /// characters the font has no glyphs for
+    pub rejected: Vec<char>,
+    /// the contents of the font file, for drawing its glyphs (fonts from
+    /// a library don't have any)
+    pub bytes: Option<Arc<[u8]>>,
+    /// how long finding, loading, and analyzing it took
+    pub time: Duration,
+}
+
+impl FontSpec {
+    /**
+    Find, load, and analyze the font, keeping track of where it came from
+    and which characters it couldn't provide (and how long it took).
+    */
+    pub fn resolve(&self) -> Result<Resolved, ErrorShim> {
+        let start = Instant::now();
+        let chars = self.chars();
+        let mut resolved = match (&self.file, &self.lib) {
+            (Some(path), _) => font_from_file(path, self.pixels, &chars),
+            (None, Some(_)) if self.charset.is_some() => {
+                let estr = "--charset needs a font file or an installed font, \
+                            not a font library (whose characters are fixed)."
+                    .to_owned();
+                return Err(ErrorShim(estr));
+            }
+            (None, Some(path)) => font_from_library(path, &self.name, self.pixels),
+            (None, None) => font_from_file(&fontconfig_path(&self.name)?, self.pixels, &chars),
+        }?;
+        resolved.time = start.elapsed();
+        Ok(resolved)
+    }
+
+    /**
+    Return the characters to analyze the font for: those of the charset
+    (each only once), or printable ASCII.
+    */
+    pub fn chars(&self) -> Vec<char> {
+        match &self.charset {
+            Some(charset) => {
+                let mut chars: Vec<char> = Vec::new();
+                for c in charset.chars() {
+                    if !chars.contains(&c) {
+                        chars.push(c);
+                    }
+                }
+                chars
+            }
+            None => ascii_art::printable_ascii(),
+        }
+    }
+}
+
+/**
+Use Fontconfig to find the path of the font file that best matches the
+font name `font`.
+*/
+fn fontconfig_path(font: &str) -> Result<String, ErrorShim> {
+    use fontconfig::{Fontconfig, Pattern};
+
+    let fc = match Fontconfig::new() {
+        Some(fc) => fc,
+        None => {
+            let estr = "Unable to initialize fontconfig.".to_owned();
+            return Err(ErrorShim(estr));
+        }
+    };
+    let mut pattern = Pattern::new(&fc);
+    let family = CString::new("family")?;
+    let family_name = CString::new(font.as_bytes())?;
+    pattern.add_string(&family, &family_name);
+    let pattern = pattern.font_match();
+
+    match pattern.filename() {
+        Some(p) => Ok(p.to_owned()),
+        None => {
+            let estr = format!("Unable to find matching font file for font \"{}\".", font);
+            Err(ErrorShim(estr))
+        }
+    }
+}
+
+/**
+Read the font file at `path` and analyze it at `pixels` pixels for the
+characters `chars`.
+*/
+fn font_from_file(path: &str, pixels: f32, chars: &[char]) -> Result<Resolved, ErrorShim> {
+    let font_bytes = std::fs::read(path)?;
+
+    let (font, rejected) = match FontData::from_font_bytes(&font_bytes, pixels, chars) {
+        Err(e) => {
+            let estr = format!("Error reading font file: {:?}", &e);
+            return Err(ErrorShim(estr));
+        }
+        Ok(Ok(fd)) => (fd, Vec::new()),
+        Ok(Err((fd, rejected))) => (fd, rejected),
+    };
+
+    Ok(Resolved {
+        font,
+        path: path.to_owned(),
+        rejected,
+        bytes: Some(Arc::from(font_bytes)),
+        time: Duration::ZERO,
+    })
+}
+
+/**
+Load the font library at `path` and return its data for `family` at the
+size closest to `pixels`.
+*/
+fn font_from_library(path: &str, family: &str, pixels: f32) -> Result<Resolved, ErrorShim> {
+    let f = std::fs::File::open(path)?;
+    let lib = match FontLibrary::load(BufReader::new(f)) {
+        Ok(lib) => lib,
+        Err(e) => {
+            let estr = format!("Error loading font library: {}", &e);
+            return Err(ErrorShim(estr));
+        }
+    };
+
+    let size = pixels.round().clamp(0.0, u16::MAX as f32) as u16;
+    let entry = match lib.nearest(family, size) {
+        Some((size, _)) => lib.entry(family, size).unwrap(),
+        None => {
+            let families: Vec<&str> = lib.families().collect();
+            let estr = format!(
+                "Font library has no family \"{}\" (it has: {}).",
+                family,
+                families.join(", ")
+            );
+            return Err(ErrorShim(estr));
+        }
+    };
+
+    let font = entry.font().clone();
+    let info = entry.info();
+    let rejected = if info.charset.is_empty() {
+        // Libraries from older versions of `librarify` don't record what
+        // was left out, but it's whatever printable ASCII isn't there.
+        let ramp: Vec<char> = font.ramp().into_iter().map(|(c, _)| c).collect();
+        ascii_art::printable_ascii()
+            .into_iter()
+            .filter(|c| !ramp.contains(c))
+            .collect()
+    } else {
+        info.rejected.chars().collect()
+    };
+
+    Ok(Resolved {
+        font,
+        path: path.to_owned(),
+        rejected,
+        bytes: None,
+        time: Duration::ZERO,
+    })
+}
+
+/**
+Print the monospaced fonts Fontconfig knows about (their families,
+styles, and files), and whether each has glyphs for all the characters
+`img2ascii` uses (those of `--charset`, or printable ASCII).
+*/
+pub fn list_fonts(args: &Args) -> Result<(), ErrorShim> {
+    use fontconfig::{Fontconfig, ObjectSet, Pattern};
+
+    let fc = match Fontconfig::new() {
+        Some(fc) => fc,
+        None => {
+            let estr = "Unable to initialize fontconfig.".to_owned();
+            return Err(ErrorShim(estr));
+        }
+    };
+    let family = CString::new("family")?;
+    let style = CString::new("style")?;
+    let file = CString::new("file")?;
+    let spacing = CString::new("spacing")?;
+    let mut objects = ObjectSet::new(&fc);
+    for name in [&family, &style, &file, &spacing] {
+        objects.add(name);
+    }
+
+    let pattern = Pattern::new(&fc);
+    let mut fonts: Vec<(String, String, String)> = fontconfig::list_fonts(&pattern, Some(&objects))
+        .iter()
+        .filter(|p| p.get_int(&spacing).unwrap_or(0) >= FC_MONO)
+        .filter_map(|p| {
+            Some((
+                p.get_string(&family)?.to_owned(),
+                p.get_string(&style).unwrap_or("").to_owned(),
+                p.get_string(&file)?.to_owned(),
+            ))
+        })
+        .collect();
+    fonts.sort();
+    fonts.dedup();
+
+    let name_width = fonts
+        .iter()
+        .map(|(f, s, _)| f.chars().count() + s.chars().count() + 3)
+        .max()
+        .unwrap_or(0);
+    let chars = FontSpec::from(args).chars();
+    for (family, style, file) in fonts.iter() {
+        let coverage = match font_from_file(file, args.pixels, &chars) {
+            Ok(r) if r.rejected.is_empty() => "complete".to_owned(),
+            Ok(r) => format!("missing {}", r.rejected.len()),
+            Err(_) => "unreadable".to_owned(),
+        };
+        let name = format!("{} ({})", family, style);
+        println!("{:<w$}  {:<10}  {}", name, coverage, file, w = name_width);
+    }
+
+    Ok(())
+}
+
+/**
+Print what `img2ascii` would make of the font `name` (as modified by the
+other font options in `args`, less any font file): the file it comes from, the size of its cells,
+its characters from dark to light, and any characters it's missing.
+*/
+pub fn show_font(args: &Args, name: &str) -> Result<(), ErrorShim> {
+    let spec = FontSpec {
+        name: name.to_owned(),
+        file: None,
+        ..FontSpec::from(args)
+    };
+    let resolved = spec.resolve()?;
+    let font = &resolved.font;
+
+    let family = match (font.family(), font.style()) {
+        (Some(family), Some(style)) => format!("{} ({})", family, style),
+        (Some(family), None) => family.to_owned(),
+        _ => "(unknown)".to_owned(),
+    };
+    let (cell_w, cell_h) = font.geometry();
+    let ramp: String = font.ramp().into_iter().map(|(c, _)| c).collect();
+    let rejected: String = resolved.rejected.iter().collect();
+
+    println!("font:      {}", family);
+    println!("file:      {}", resolved.path);
+    println!(
+        "size:      {} pixels",
+        font.pixel_size().unwrap_or(spec.pixels)
+    );
+    println!("cell:      {:.2} x {:.2} pixels", cell_w, cell_h);
+    println!("ramp:      {:?}", ramp);
+    if rejected.is_empty() {
+        println!("rejected:  (none)");
+    } else {
+        println!("rejected:  {:?}", rejected);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+/*!
+Output formats, and guessing them from file names.
+*/
+use std::path::Path;
+
+use clap::ArgEnum;
+
+/**
+What the rendered text gets written as.
+*/
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// plain text
+    Text,
+    /// text colored with 24-bit ANSI escape codes
+    Ansi,
+    /// an HTML page
+    Html,
+    /// an SVG drawing
+    Svg,
+    /// a PNG image of the text, drawn with the font
+    Png,
+    /// a JSON grid of rows
+    Json,
+    /// an asciinema recording
+    Asciicast,
+}
+
+impl Format {
+    /**
+    Return the format that files with extension `ext` are in, if it's one
+    of these.
+    */
+    pub fn from_extension(ext: &str) -> Option<Format> {
+        match ext.to_lowercase().as_str() {
+            "txt" | "text" => Some(Format::Text),
+            "ans" | "ansi" => Some(Format::Ansi),
+            "html" | "htm" => Some(Format::Html),
+            "svg" => Some(Format::Svg),
+            "png" => Some(Format::Png),
+            "json" => Some(Format::Json),
+            "cast" => Some(Format::Asciicast),
+            _ => None,
+        }
+    }
+
+    /**
+    Return the format the file at `path` should be in, going by its
+    extension.
+    */
+    pub fn from_path(path: &Path) -> Option<Format> {
+        Format::from_extension(&path.extension()?.to_string_lossy())
+    }
+
+    /**
+    The usual extension of files in this format.
+    */
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Text => "txt",
+            Format::Ansi => "ans",
+            Format::Html => "html",
+            Format::Svg => "svg",
+            Format::Png => "png",
+            Format::Json => "json",
+            Format::Asciicast => "cast",
+        }
+    }
+
+    /**
+    Whether the image's colors (and not just its brightness) are needed.
+    */
+    pub fn needs_color(&self) -> bool {
+        *self == Format::Ansi
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extensions() {
+        for format in Format::value_variants() {
+            assert_eq!(Format::from_extension(format.extension()), Some(*format));
+        }
+        assert_eq!(Format::from_extension("HTM"), Some(Format::Html));
+        assert_eq!(Format::from_extension("Text"), Some(Format::Text));
+        assert_eq!(Format::from_extension("jpg"), None);
+        assert_eq!(Format::from_extension(""), None);
+
+        assert_eq!(
+            Format::from_path(Path::new("out/art.SVG")),
+            Some(Format::Svg)
+        );
+        assert_eq!(Format::from_path(Path::new("art.json.bak")), None);
+        assert_eq!(Format::from_path(Path::new("art")), None);
+        assert_eq!(Format::from_path(Path::new(".cast")), None);
+    }
+}
@@ -8,16 +8,50 @@ Dan <dx2718@gmail.com>
 Command-line utility to turn image files into ASCII art.
 
 USAGE:
-    img2ascii [OPTIONS]
+    img2ascii [OPTIONS] [INPUT]... [SUBCOMMAND]
+
+ARGS:
+    <INPUT>...    image files or directories to convert in batch mode
 
 OPTIONS:
-    -d, --dest <DEST>        output path [default: write to stdout]
-    -f, --font <FONT>        font to use [default: mono]
-    -h, --help               Print help information
-    -i, --invert             target inverted (dark on light) text
-    -p, --pixels <PIXELS>    font size in pixels [default: 12.0]
-    -s, --source <SOURCE>    image path [default: read from stdin]
-    -V, --version            Print version information
+        --charset <CHARS>      characters to render with [default: printable ASCII]
+        --config <PATH>        configuration file [default: $XDG_CONFIG_HOME/img2ascii/config.toml]
+        --contrast <FACTOR>    multiply the image's contrast by FACTOR before rendering [default:
+                               1.0]
+    -d, --dest <DEST>          output path [default: write to stdout]
+        --ext <EXT>            batch output file extension [default: that of the --format, or txt]
+    -f, --font <FONT>          font to use [default: mono]
+        --fit <WxH>            fit output within WxH characters
+        --fit-terminal         fit output within the terminal
+        --font-file <PATH>     font file to use (instead of --font)
+        --font-lib <PATH>      font library to take --font from
+        --format <FORMAT>      output format [default: from the --dest (or --ext) extension, or
+                               text] [possible values: text, ansi, html, svg, png, json, asciicast]
+        --glob <PATTERN>       only convert files in INPUT directories matching PATTERN
+    -h, --help                 Print help information
+        --height <ROWS>        output height in lines
+    -i, --invert               target inverted (dark on light) text
+    -j, --threads <THREADS>    number of files to convert at once [default: number of CPUs]
+        --json                 write a JSON report of the rows, font, settings, and timings instead
+        --list-fonts           list installed monospace fonts, and whether they have every glyph
+        --name <TEMPLATE>      batch output file name template [default: {stem}.{ext}]
+        --no-fit-terminal      don't fit output within the terminal, whatever the configuration says
+        --no-invert            target light-on-dark text, whatever the configuration says
+    -o, --out-dir <DIR>        directory to write batch output to
+    -p, --pixels <PIXELS>      font size in pixels [default: 12.0]
+    -P, --preset <NAME>        preset (from the configuration file) to use
+    -r, --recursive            also search subdirectories of INPUT directories
+    -s, --source <SOURCE>      image path [default: read from stdin]
+        --settings <PATH>      TOML file of options that replace those given here
+        --show-font <NAME>     show which file, glyphs, and geometry font NAME resolves to
+    -V, --version              Print version information
+    -w, --watch                re-render whenever the source (or settings) file changes
+        --width <COLS>         output width in characters
+
+SUBCOMMANDS:
+    help    Print this message or the help of the given subcommand(s)
+    play    Play an animated GIF, PNG, or WebP, or a YUV4MPEG2 stream, in the terminal
+    view    View images full-screen in the terminal, with keys to zoom, pan, and adjust them
 ```
 
 By default this will read image data from stdin and write the rendered
@@ -76,12 +110,189 @@ use based on the font name you provide:
 ```text
 $ img2ascii -s rust-social-sm.jpg -d rust-social-sm.txt -f "Anonymous Pro" -p 16
 ````
+
+Fontconfig isn't needed (or even loaded) if you tell `img2ascii` where to
+find the font yourself, either with `--font-file`, which takes the path
+of a font file, or with `--font-lib`, which takes the path of a font
+library made by `librarify`; `--font` then names the family to use from
+the library, and the size closest to `--pixels` is used:
+
+```text
+$ img2ascii -s rust-social-sm.jpg --font-file /path/to/AnonymousPro.ttf -p 16
+$ img2ascii -s rust-social-sm.jpg --font-lib fonts.json -f "Anonymous Pro" -p 16
+```
+
+Left to itself, `img2ascii` renders one character for every
+character-sized cell of the source image, so the size of the output
+depends on the resolution of the image. The `--width`, `--height`, and
+`--fit` options instead scale the image to the given number of characters
+and/or lines (keeping its aspect ratio, as measured in the target font),
+and `--fit-terminal` makes it as large as will fit in the terminal:
+
+```text
+$ img2ascii -s rust-social-sm.jpg --width 40
+$ img2ascii -s rust-social-sm.jpg --fit 80x24
+$ img2ascii -s rust-social-sm.jpg --fit-terminal
+```
+
+`--charset` renders with the given characters instead of printable ASCII
+(the font still has to have them, and a font library's fonts only know
+about printable ASCII, so it needs an installed font or a font file),
+and `--contrast` multiplies the image's contrast (around middle gray)
+before matching it to characters:
+
+```text
+$ img2ascii -s rust-social-sm.jpg --charset " .:-=+*#%@" --contrast 1.5
+```
+
+The output is plain text unless `--format` says otherwise: `ansi` colors
+each character (with 24-bit ANSI escape codes) to match the image,
+`html` and `svg` make a web page or drawing of the text in the font it
+was rendered for, `png` draws the text with the font's glyphs (so it
+needs a font file, rather than a font library), `json` writes the rows
+of text and the size of the grid, and `asciicast` makes an asciinema
+recording of a single frame. Without `--format`, the format is guessed
+from the extension of `--dest` (`.txt`, `.ans`, `.html`, `.svg`, `.png`,
+`.json`, or `.cast`):
+
+```text
+$ img2ascii -s rust-social-sm.jpg -d rust-social-sm.svg -f "Anonymous Pro" -p 16
+$ img2ascii -s rust-social-sm.jpg --format ansi --fit-terminal
+```
+
+For scripts, `--json` writes a report instead: the rows and the size of
+the grid, the font family and style, the file the font was actually
+loaded from (wherever Fontconfig found it), its pixel size and cell size,
+the polarity (`light-on-dark`, or with `--invert`, `dark-on-light`), the
+contrast, the characters rendered with (darkest first) and any the font lacks, and how
+long loading the font, decoding the image, and rendering it took (in
+seconds), all on one line:
+
+```text
+$ img2ascii -s rust-social-sm.jpg --json --width 40 | jq .font.file
+"/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf"
+```
+
+Given any number of image files and directories instead of `--source`,
+`img2ascii` converts them all (several at once; see `--threads`), writing
+the results to the directory given by `--out-dir`. Directories are
+searched for image files (and with `--recursive`, so are their
+subdirectories; the output directory gets the same layout), or, with
+`--glob`, for files whose names match any of the given patterns. Each
+output file is named according to `--name`, in which `{stem}` is the
+input file's name without its extension, `{name}` is its whole name,
+and `{ext}` is the value of `--ext` (which defaults to the usual
+extension for the `--format`, and otherwise determines the format). The
+name has to be a plain file name (no `/`, and not `..`), and a file whose
+output would overwrite that of an earlier file isn't converted. Once
+it's done, it prints a summary (and any errors) and exits unsuccessfully
+if any file couldn't be converted:
+
+```text
+$ img2ascii -r --glob '*.png' -o ascii --name '{stem}-16px.{ext}' -p 16 photos/
+```
+
+To see which fonts are available, `--list-fonts` lists the monospaced
+fonts Fontconfig knows about, and whether each has all the characters
+`img2ascii` uses. `--show-font NAME` shows which file the font `NAME`
+resolves to, the size of its character cells, its characters from
+darkest to lightest, and any it's missing:
+
+```text
+$ img2ascii --show-font "Liberation Mono"
+font:      Liberation Mono (Regular)
+file:      /usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf
+size:      12 pixels
+cell:      6.36 x 12.00 pixels
+ramp:      " `.-'_,:~;*!\"^\\/+=r><|l)7L?(tcixTvYzJs1jf}{][IunyoCF#V42ekXah3ZP5wAUbSdpq6K%9GEHO0mD&8RNgMW$BQ@"
+rejected:  (none)
+```
+
+Options for choosing the font and sizing the output can also be given in
+a TOML settings file (with the same names as the long options; those in
+the file take precedence):
+
+```toml
+font = "Anonymous Pro"
+pixels = 16
+invert = true
+fit = "80x24"
+```
+
+Settings can also be kept in a configuration file, which is read from
+`$XDG_CONFIG_HOME/img2ascii/config.toml` (or `~/.config/img2ascii/config.toml`)
+if it exists, or from wherever `--config` says. Its settings are used
+where none are given on the command line (`--no-invert` and
+`--no-fit-terminal` turn off those set there), and it can contain named
+sets of settings, to be picked with `--preset`:
+
+```toml
+font = "Iosevka"
+
+[presets.big]
+pixels = 24
+fit-terminal = true
+```
+
+```text
+$ img2ascii -P big -s rust-social-sm.jpg
+```
+
+With `--watch`, `img2ascii` renders the `--source` image, then renders it
+again every time it (or the settings file) changes, until interrupted.
+Without `--dest`, each rendering replaces the last one in the terminal:
+
+```text
+$ img2ascii -w -s drawing.png --settings tweaks.toml
+```
+
+`img2ascii view` shows images full-screen in the terminal (in its
+alternate screen, so whatever was there before comes back afterwards),
+fitting each one to the terminal and redrawing it when the terminal
+changes size. Keys zoom in and out (`+` and `-`), pan (the arrow keys, or
+`h`, `j`, `k`, and `l`), toggle inverted text (`i`), cycle through
+character sets (`c`; other than the font's own, these need a font file),
+adjust the contrast (`[` and `]`), reset the view (`0`), step through the
+files (`n` and `p`, or space and backspace), and quit (`q`):
+
+```text
+$ img2ascii view -f "Anonymous Pro" griffin.jpg rust-social-sm.jpg
+```
+
+`img2ascii play` plays an animated GIF, PNG, or WebP (or, without a file,
+a YUV4MPEG2 video stream from stdin) in the terminal, fitting it to the
+terminal unless given a size, and showing each frame for as long as the
+animation (or frame rate) says, times `--speed`. It plays through once,
+or (for a file) `--loop` times (`0` for forever), or until interrupted:
+
+```text
+$ ffmpeg -loglevel quiet -i clip.mp4 -f yuv4mpegpipe - | img2ascii play --width 100
+```
 */
+mod batch;
+mod fonts;
+mod format;
+mod play;
+mod report;
+mod settings;
+#[cfg(unix)]
+mod view;
+mod watch;
+
 use std::fmt::{Debug, Display, Formatter};
-use std::io::{BufReader, Cursor, Read, Seek, Write};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use ascii_art::{FontData, Image};
-use clap::Parser;
+use ascii_art::{
+    AsciicastWriter, ColorImage, ColorOptions, ColorTarget, FontData, FontOptions, Image,
+    RenderOptions,
+};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+
+use fonts::FontSpec;
+use format::Format;
 
 /**
 This is a hack to simplify error propagation and reporting.
@@ -112,7 +323,7 @@ impl Debug for ErrorShim {
 Struct used by clap to generate the CLI parsing code, and also
 to hold the arguments passed from the command line.
 */
-#[derive(Parser, Debug)]
+#[derive(Parser, Clone, Debug)]
 #[clap(
     name = "img2ascii",
     version,
@@ -128,17 +339,254 @@ struct Args {
     #[clap(short, long)]
     dest: Option<String>,
 
+    /// output format [default: from the --dest (or --ext) extension, or text]
+    #[clap(long, arg_enum, value_name = "FORMAT")]
+    format: Option<Format>,
+
+    /// write a JSON report of the rows, font, settings, and timings instead
+    #[clap(long, conflicts_with = "format")]
+    json: bool,
+
     /// font to use
-    #[clap(short, long, default_value = "mono")]
+    #[clap(short, long, global = true, default_value = "mono")]
     font: String,
 
+    /// font file to use (instead of --font)
+    #[clap(long, global = true, value_name = "PATH", conflicts_with_all = &["font", "font-lib"])]
+    font_file: Option<String>,
+
+    /// font library to take --font from
+    #[clap(long, global = true, value_name = "PATH")]
+    font_lib: Option<String>,
+
     /// font size in pixels
-    #[clap(short, long, default_value = "12.0")]
+    #[clap(short, long, global = true, default_value = "12.0")]
     pixels: f32,
 
+    /// characters to render with [default: printable ASCII]
+    #[clap(
+        long,
+        global = true,
+        value_name = "CHARS",
+        parse(try_from_str = parse_charset)
+    )]
+    charset: Option<String>,
+
+    /// multiply the image's contrast by FACTOR before rendering
+    #[clap(
+        long,
+        global = true,
+        value_name = "FACTOR",
+        default_value = "1.0",
+        parse(try_from_str = parse_contrast)
+    )]
+    contrast: f32,
+
     /// target inverted (dark on light) text
-    #[clap(short, long)]
+    #[clap(short, long, global = true, overrides_with = "no-invert")]
     invert: bool,
+
+    /// target light-on-dark text, whatever the configuration says
+    #[clap(long, global = true, overrides_with = "invert")]
+    no_invert: bool,
+
+    /// output width in characters
+    #[clap(long, global = true, value_name = "COLS")]
+    width: Option<u32>,
+
+    /// output height in lines
+    #[clap(long, global = true, value_name = "ROWS")]
+    height: Option<u32>,
+
+    /// fit output within WxH characters
+    #[clap(
+        long,
+        global = true,
+        value_name = "WxH",
+        parse(try_from_str = parse_fit),
+        conflicts_with_all = &["width", "height"]
+    )]
+    fit: Option<(u32, u32)>,
+
+    /// fit output within the terminal
+    #[clap(
+        long,
+        global = true,
+        conflicts_with_all = &["width", "height", "fit"],
+        overrides_with = "no-fit-terminal"
+    )]
+    fit_terminal: bool,
+
+    /// don't fit output within the terminal, whatever the configuration says
+    #[clap(long, global = true, overrides_with = "fit-terminal")]
+    no_fit_terminal: bool,
+
+    /// image files or directories to convert in batch mode
+    #[clap(
+        value_name = "INPUT",
+        conflicts_with_all = &["source", "dest"],
+        requires = "out-dir"
+    )]
+    inputs: Vec<String>,
+
+    /// also search subdirectories of INPUT directories
+    #[clap(short, long)]
+    recursive: bool,
+
+    /// only convert files in INPUT directories matching PATTERN
+    #[clap(long = "glob", value_name = "PATTERN")]
+    globs: Vec<String>,
+
+    /// directory to write batch output to
+    #[clap(short, long, value_name = "DIR", requires = "inputs")]
+    out_dir: Option<String>,
+
+    /// batch output file name template
+    #[clap(long, value_name = "TEMPLATE", default_value = "{stem}.{ext}")]
+    name: String,
+
+    /// batch output file extension [default: that of the --format, or txt]
+    #[clap(long)]
+    ext: Option<String>,
+
+    /// number of files to convert at once [default: number of CPUs]
+    #[clap(short = 'j', long)]
+    threads: Option<usize>,
+
+    /// re-render whenever the source (or settings) file changes
+    #[clap(short, long, requires = "source", conflicts_with = "inputs")]
+    watch: bool,
+
+    /// TOML file of options that replace those given here
+    #[clap(long, global = true, value_name = "PATH")]
+    settings: Option<String>,
+
+    /// configuration file [default: $XDG_CONFIG_HOME/img2ascii/config.toml]
+    #[clap(long, global = true, value_name = "PATH")]
+    config: Option<String>,
+
+    /// preset (from the configuration file) to use
+    #[clap(short = 'P', long, global = true, value_name = "NAME")]
+    preset: Option<String>,
+
+    /// list installed monospace fonts, and whether they have every glyph
+    #[clap(long)]
+    list_fonts: bool,
+
+    /// show which file, glyphs, and geometry font NAME resolves to
+    #[clap(long, value_name = "NAME", conflicts_with = "font-file")]
+    show_font: Option<String>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+/**
+Things to do besides converting images.
+*/
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// View images full-screen in the terminal, with keys to zoom, pan, and adjust them
+    View {
+        /// image files to view
+        #[clap(value_name = "FILE", required = true)]
+        files: Vec<String>,
+    },
+    /// Play an animated GIF, PNG, or WebP, or a YUV4MPEG2 stream, in the terminal
+    Play {
+        /// file to play [default: read from stdin]
+        #[clap(value_name = "FILE")]
+        file: Option<String>,
+        /// number of times to play a file through (0 for forever)
+        #[clap(short = 'l', long = "loop", value_name = "COUNT", default_value = "1")]
+        loops: u32,
+        /// playback speed multiplier
+        #[clap(long, default_value = "1.0", parse(try_from_str = parse_speed))]
+        speed: f64,
+    },
+}
+
+/**
+Parse the argument to `--fit`, a width and height like `80x24`.
+*/
+fn parse_fit(s: &str) -> Result<(u32, u32), String> {
+    let (w, h) = s
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("expected WxH (like 80x24), not \"{}\"", s))?;
+    let w: u32 = w.trim().parse().map_err(|e| format!("bad width: {}", &e))?;
+    let h: u32 = h
+        .trim()
+        .parse()
+        .map_err(|e| format!("bad height: {}", &e))?;
+    if w == 0 || h == 0 {
+        return Err("width and height must be greater than zero".to_owned());
+    }
+    Ok((w, h))
+}
+
+/**
+Parse the argument to `--charset`, which must have at least one character.
+*/
+fn parse_charset(s: &str) -> Result<String, String> {
+    if s.is_empty() {
+        return Err("the character set can't be empty".to_owned());
+    }
+    Ok(s.to_owned())
+}
+
+/**
+Parse the argument to `--contrast`, a positive multiplier like `1.5`.
+*/
+fn parse_contrast(s: &str) -> Result<f32, String> {
+    let contrast: f32 = s
+        .trim()
+        .parse()
+        .map_err(|e| format!("bad contrast: {}", &e))?;
+    if !contrast.is_finite() || contrast <= 0.0 {
+        return Err("contrast must be greater than zero".to_owned());
+    }
+    Ok(contrast)
+}
+
+/**
+Parse the argument to `play --speed`, a positive multiplier like `0.5`.
+*/
+fn parse_speed(s: &str) -> Result<f64, String> {
+    let speed: f64 = s.trim().parse().map_err(|e| format!("bad speed: {}", &e))?;
+    if !speed.is_finite() || speed <= 0.0 {
+        return Err("speed must be greater than zero".to_owned());
+    }
+    Ok(speed)
+}
+
+/**
+Return the size of the terminal (in columns and rows) attached to stdout,
+or, if there isn't one, as given by the `COLUMNS` and `LINES` environment
+variables.
+*/
+fn terminal_size() -> Option<(u32, u32)> {
+    #[cfg(unix)]
+    {
+        let mut ws = libc::winsize {
+            ws_row: 0,
+            ws_col: 0,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        // SAFETY: TIOCGWINSZ only writes a `winsize` through the pointer.
+        let ret = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) };
+        if ret == 0 && ws.ws_col > 0 && ws.ws_row > 0 {
+            return Some((ws.ws_col as u32, ws.ws_row as u32));
+        }
+    }
+
+    let var = |name: &str| -> Option<u32> {
+        std::env::var(name)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|n| *n > 0)
+    };
+    Some((var("COLUMNS")?, var("LINES")?))
 }
 
 /**
@@ -153,113 +601,461 @@ impl Reread for std::fs::File {}
 impl<T: AsRef<[u8]>> Reread for std::io::Cursor<T> {}
 
 /**
-Struct returned by the `configure()` function (below). Holds pointers
-to the image input stream, the text output stream, and the font information
-to turn the data in the former into data in the latter.
+What to convert: one image to one text output, or a whole batch of image
+files to text files.
+*/
+enum Job {
+    Single {
+        /// image input stream (file or contents of stdin)
+        source: Box<dyn Reread>,
+        /// text output stream (file or stdout)
+        dest: Box<dyn Write>,
+    },
+    Batch(batch::Batch),
+    /// re-render the source file whenever it (or the settings file)
+    /// changes, according to these (command-line) arguments
+    Watch(Box<Args>),
+    /// view these files interactively in the terminal
+    View(Vec<String>),
+    Play(play::Playback),
+}
+
+/**
+Struct returned by the `configure()` function (below). Holds what to
+convert, and how to convert it.
 */
 struct Cfg {
-    /// image input stream (file or contents of stdin)
-    source: Box<dyn Reread>,
-    /// text output stream (file or stdout)
-    dest: Box<dyn Write>,
+    job: Job,
+    render: Render,
+}
+
+/**
+Return the output format: as given, or else going by the extension of the
+output file (or, in batch mode, the output extension), or else plain text.
+*/
+fn output_format(args: &Args) -> Format {
+    let guess = if args.inputs.is_empty() {
+        args.dest
+            .as_ref()
+            .and_then(|path| Format::from_path(Path::new(path)))
+    } else {
+        args.ext.as_deref().and_then(Format::from_extension)
+    };
+    if args.json {
+        // The report is JSON, whatever the file name says.
+        return Format::Json;
+    }
+    args.format.or(guess).unwrap_or(Format::Text)
+}
+
+/**
+A decoded image's pixels: in color, if the output format needs it, or
+otherwise just their brightness.
+*/
+enum Pixels {
+    Gray(Image),
+    Color(ColorImage),
+}
+
+/**
+A decoded image, and how long decoding it took.
+*/
+struct Picture {
+    pixels: Pixels,
+    time: Duration,
+}
+
+impl Picture {
+    fn image(&self) -> &Image {
+        match &self.pixels {
+            Pixels::Gray(image) => image,
+            Pixels::Color(image) => image.image(),
+        }
+    }
+}
+
+/**
+The font information (and other settings) used to turn image data into
+text.
+*/
+struct Render {
+    /// where the font came from
+    spec: FontSpec,
     /// data from specified (or default) font
     font: FontData,
+    /// contents of the font file, if there is one
+    font_bytes: Option<Arc<[u8]>>,
+    /// the font file (or font library) the font came from
+    font_file: String,
+    /// characters the font has no glyphs for
+    rejected: Vec<char>,
+    /// how long finding and analyzing the font took
+    font_time: Duration,
+    /// what to write the text as
+    format: Format,
+    /// write a JSON report (of the text and how it was made) instead
+    report: bool,
     /// target dark-on-light (rather than light-on-dark) text
     invert: bool,
+    /// how much to multiply the contrast of images by
+    contrast: f32,
+    /// maximum output width in characters, if any
+    cols: Option<u32>,
+    /// maximum output height in lines, if any
+    rows: Option<u32>,
 }
 
-/**
-Arrange the font data, and the input and output streams according to the
-arguments supplied by the user; return a `Cfg` struct with these things.
-*/
-fn configure() -> Result<Cfg, ErrorShim> {
-    use fontconfig::{Fontconfig, Pattern};
-    use std::ffi::CString;
-    use std::fs::File;
+impl Render {
+    /**
+    Arrange rendering with `font` (which should be the font `args`
+    specifies) according to `args`.
+    */
+    fn new(args: &Args, font: fonts::Resolved) -> Result<Render, ErrorShim> {
+        let (cols, rows) = if args.fit_terminal {
+            match terminal_size() {
+                // Leave a line for the prompt after the image.
+                Some((w, h)) => (Some(w), Some(h.saturating_sub(1).max(1))),
+                None => {
+                    let estr = "Unable to determine the size of the terminal.".to_owned();
+                    return Err(ErrorShim(estr));
+                }
+            }
+        } else if let Some((w, h)) = args.fit {
+            (Some(w), Some(h))
+        } else {
+            (args.width, args.height)
+        };
 
-    let args = Args::parse();
+        let render = Render {
+            spec: FontSpec::from(args),
+            font: font.font,
+            font_bytes: font.bytes,
+            font_file: font.path,
+            rejected: font.rejected,
+            font_time: font.time,
+            format: output_format(args),
+            report: args.json,
+            invert: args.invert,
+            contrast: args.contrast,
+            cols,
+            rows,
+        };
+        if render.format == Format::Png {
+            // Better to find out now than once for every file.
+            render.glyphs()?;
+        }
+        Ok(render)
+    }
 
-    let source: Box<dyn Reread> = match args.source {
-        Some(path) => {
-            let f = File::open(path)?;
-            Box::new(f)
+    /*
+    Return the font this renders with, as it was resolved.
+    */
+    fn resolved(&self) -> fonts::Resolved {
+        fonts::Resolved {
+            font: self.font.clone(),
+            path: self.font_file.clone(),
+            rejected: self.rejected.clone(),
+            bytes: self.font_bytes.clone(),
+            time: self.font_time,
         }
-        None => {
-            // Huff in the entirety of stdin and return a `Cursor` over that
-            // buffer, because stdin can't be `Seek`.
-            let mut v: Vec<u8> = Vec::new();
-            std::io::stdin().lock().read_to_end(&mut v)?;
-            Box::new(Cursor::new(v))
+    }
+
+    /*
+    Return the font file's contents, for drawing its glyphs.
+    */
+    fn glyphs(&self) -> Result<&[u8], ErrorShim> {
+        match &self.font_bytes {
+            Some(bytes) => Ok(bytes),
+            None => {
+                let estr = "PNG output needs a font file, not a font library.".to_owned();
+                Err(ErrorShim(estr))
+            }
         }
-    };
+    }
+
+    /*
+    Return the options for rendering `image`, sized to fit.
+    */
+    fn options(&self, image: &Image) -> RenderOptions {
+        let opts = RenderOptions {
+            invert: self.invert,
+            ..RenderOptions::new()
+        };
+        opts.fit(&self.font, image.dimensions(), self.cols, self.rows)
+    }
 
-    let dest: Box<dyn Write> = match args.dest {
-        Some(path) => {
-            let f = File::create(path)?;
-            Box::new(f)
+    /**
+    Decode the image data in `source` (in color, if the output format
+    needs it), and adjust its contrast.
+    */
+    fn decode<R: BufRead + Seek>(&self, source: R) -> Result<Picture, ErrorShim> {
+        let start = Instant::now();
+        let mut pixels = if self.format.needs_color() {
+            Pixels::Color(ColorImage::auto(source)?)
+        } else {
+            Pixels::Gray(Image::auto(source)?)
+        };
+        if self.contrast != 1.0 {
+            pixels = match pixels {
+                Pixels::Gray(image) => Pixels::Gray(image.with_contrast(self.contrast)),
+                Pixels::Color(image) => Pixels::Color(image.with_contrast(self.contrast)),
+            };
         }
-        None => Box::new(std::io::stdout()),
-    };
+        Ok(Picture {
+            pixels,
+            time: start.elapsed(),
+        })
+    }
 
-    let fc = match Fontconfig::new() {
-        Some(fc) => fc,
-        None => {
-            let estr = "Unable to initialize fontconfig.".to_owned();
-            return Err(ErrorShim(estr));
+    /**
+    Decode the image data in `source` and write it as text to `dest`.
+    */
+    fn convert<R: BufRead + Seek, W: Write>(&self, source: R, dest: W) -> Result<(), ErrorShim> {
+        let picture = self.decode(source)?;
+        self.write(&picture, dest)
+    }
+
+    /**
+    Write `picture` as text, in the output format, to `dest`.
+    */
+    fn write<W: Write>(&self, picture: &Picture, dest: W) -> Result<(), ErrorShim> {
+        if self.report {
+            return report::write(self, picture, dest);
         }
+
+        let image = picture.image();
+        let font = &self.font;
+        let opts = self.options(image);
+
+        let rows = || ascii_art::render_with(image, font, &opts);
+
+        match (self.format, &picture.pixels) {
+            (Format::Ansi, Pixels::Color(image)) => {
+                let color = ColorOptions::new(ColorTarget::AnsiTrueColor);
+                ascii_art::write_color(image, font, &opts, &color, dest)?;
+            }
+            (Format::Text | Format::Ansi, _) => ascii_art::write_with(image, font, &opts, dest)?,
+            (Format::Html, _) => ascii_art::write_html(&rows(), font, self.invert, dest)?,
+            (Format::Svg, _) => ascii_art::write_svg(&rows(), font, self.invert, dest)?,
+            (Format::Png, _) => {
+                let fopts = FontOptions::default();
+                ascii_art::write_png(&rows(), self.glyphs()?, &fopts, font, self.invert, dest)?;
+            }
+            (Format::Json, _) => ascii_art::write_json(&rows(), dest)?,
+            (Format::Asciicast, _) => {
+                // A still image is a recording of a single frame.
+                let mut cast = AsciicastWriter::new(dest);
+                cast.frame(&rows().join("\n"), Duration::ZERO)?;
+                cast.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+Parse the command line and merge in the configuration file; return the
+resulting arguments, both before and after applying the settings file
+(if there is one).
+*/
+fn arguments() -> Result<(Args, Args), ErrorShim> {
+    // Values given on the command line take precedence over those from
+    // the configuration file, which take precedence over the defaults.
+    let matches = Args::command().get_matches();
+    let mut cli_args = match Args::from_arg_matches(&matches) {
+        Ok(args) => args,
+        Err(e) => e.exit(),
+    };
+    let config = match (&cli_args.config, settings::Config::default_path()) {
+        (Some(path), _) => Some(settings::Config::load(Path::new(path))?),
+        (None, Some(path)) if path.exists() => Some(settings::Config::load(&path)?),
+        _ => None,
     };
-    let mut pattern = Pattern::new(&fc);
-    let family = CString::new("family")?;
-    let family_name = CString::new(args.font.clone().into_bytes())?;
-    pattern.add_string(&family, &family_name);
-    let pattern = pattern.font_match();
-
-    let font_path = match pattern.filename() {
-        Some(p) => p,
-        None => {
-            let estr = format!(
-                "Unable to find matching font file for font \"{}\".",
-                &args.font
-            );
+    match &config {
+        Some(config) => {
+            let preset = cli_args.preset.clone();
+            config.apply(preset.as_deref(), &mut cli_args, &matches)?;
+        }
+        None if cli_args.preset.is_some() => {
+            let estr = "--preset needs a configuration file.".to_owned();
             return Err(ErrorShim(estr));
         }
-    };
+        None => {}
+    }
+
+    let mut args = cli_args.clone();
+    if let Some(path) = &args.settings {
+        settings::Settings::load(Path::new(path))?.apply(&mut args);
+    }
 
-    let mut font_bytes: Vec<u8> = Vec::new();
-    let mut f = File::open(&font_path)?;
-    f.read_to_end(&mut font_bytes)?;
+    Ok((cli_args, args))
+}
 
-    let chars = ascii_art::printable_ascii();
-    let font = match FontData::from_font_bytes(&font_bytes, args.pixels, &chars) {
-        Err(e) => {
-            let estr = format!("Error reading font file: {:?}", &e);
-            return Err(ErrorShim(estr));
+/**
+Arrange the font data, and the input and output streams according to the
+arguments supplied by the user (as returned by `arguments()`); return a
+`Cfg` struct with these things.
+*/
+fn configure(cli_args: Args, args: Args) -> Result<Cfg, ErrorShim> {
+    use std::fs::File;
+
+    let font = FontSpec::from(&args).resolve()?;
+    let render = Render::new(&args, font)?;
+
+    let job = match (args.command, args.out_dir) {
+        (Some(Command::View { files }), _) => Job::View(files),
+        (Some(Command::Play { file, loops, speed }), _) => Job::Play(play::Playback {
+            source: file.map(PathBuf::from),
+            loops,
+            speed,
+        }),
+        _ if args.watch => Job::Watch(Box::new(cli_args)),
+        (None, Some(out_dir)) if !args.inputs.is_empty() => {
+            let threads = match args.threads {
+                Some(n) => n,
+                None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            };
+            Job::Batch(batch::Batch {
+                inputs: args.inputs.iter().map(PathBuf::from).collect(),
+                recursive: args.recursive,
+                globs: args.globs,
+                out_dir: PathBuf::from(out_dir),
+                name: args.name,
+                ext: args
+                    .ext
+                    .unwrap_or_else(|| render.format.extension().to_owned()),
+                threads,
+            })
         }
-        Ok(Ok(fd)) => fd,
-        Ok(Err((fd, _))) => fd,
-    };
+        _ => {
+            let source: Box<dyn Reread> = match args.source {
+                Some(path) => {
+                    let f = File::open(path)?;
+                    Box::new(f)
+                }
+                None => {
+                    // Huff in the entirety of stdin and return a `Cursor`
+                    // over that buffer, because stdin can't be `Seek`.
+                    let mut v: Vec<u8> = Vec::new();
+                    std::io::stdin().lock().read_to_end(&mut v)?;
+                    Box::new(Cursor::new(v))
+                }
+            };
 
-    let invert = args.invert;
+            let dest: Box<dyn Write> = match args.dest {
+                Some(path) => {
+                    let f = File::create(path)?;
+                    Box::new(f)
+                }
+                None => Box::new(std::io::stdout()),
+            };
+
+            Job::Single { source, dest }
+        }
+    };
 
-    Ok(Cfg {
-        source,
-        dest,
-        font,
-        invert,
-    })
+    Ok(Cfg { job, render })
 }
 
 fn main() -> Result<(), ErrorShim> {
-    let cfg = configure()?;
+    let (cli_args, args) = arguments()?;
+    if args.list_fonts {
+        return fonts::list_fonts(&args);
+    } else if let Some(name) = &args.show_font {
+        return fonts::show_font(&args, name);
+    }
 
-    let img_reader = BufReader::new(cfg.source);
-    let image = Image::auto(img_reader)?;
+    let cfg = configure(cli_args, args)?;
 
-    if cfg.invert {
-        ascii_art::write_inverted(&image, &cfg.font, cfg.dest)?;
-    } else {
-        ascii_art::write(&image, &cfg.font, cfg.dest)?;
+    match cfg.job {
+        Job::Single { source, dest } => {
+            cfg.render.convert(BufReader::new(source), dest)?;
+        }
+        Job::Batch(batch) => {
+            if !batch.run(&cfg.render)? {
+                std::process::exit(1);
+            }
+        }
+        Job::Watch(args) => watch::run(*args, cfg.render)?,
+        Job::Play(playback) => playback.run(&cfg.render)?,
+        #[cfg(unix)]
+        Job::View(files) => view::run(&files, cfg.render)?,
+        #[cfg(not(unix))]
+        Job::View(_) => {
+            let estr = "Viewing is only supported on Unix-like systems.".to_owned();
+            return Err(ErrorShim(estr));
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit() {
+        assert_eq!(parse_fit("80x24"), Ok((80, 24)));
+        assert_eq!(parse_fit("80X24"), Ok((80, 24)));
+        assert_eq!(parse_fit(" 120 x 40 "), Ok((120, 40)));
+        for bad in [
+            "80",
+            "80x",
+            "x24",
+            "80x24x2",
+            "0x24",
+            "80x0",
+            "-1x24",
+            "eightyx24",
+        ] {
+            assert!(parse_fit(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let format = |argv: &[&str]| {
+            let argv = std::iter::once("img2ascii").chain(argv.iter().copied());
+            output_format(&Args::try_parse_from(argv).unwrap())
+        };
+
+        assert_eq!(format(&[]), Format::Text);
+        assert_eq!(format(&["-d", "art.html"]), Format::Html);
+        assert_eq!(format(&["-d", "art.unknown"]), Format::Text);
+        assert_eq!(format(&["-d", "art.html", "--format", "svg"]), Format::Svg);
+        assert_eq!(format(&["-d", "art.png", "--json"]), Format::Json);
+
+        // In batch mode, the extension is --ext, not the --dest's.
+        assert_eq!(
+            format(&["-o", "out", "a.jpg", "--ext", "ans"]),
+            Format::Ansi
+        );
+        assert_eq!(
+            format(&["-o", "out", "a.jpg", "--ext", "asc"]),
+            Format::Text
+        );
+        assert_eq!(
+            format(&[
+                "-o",
+                "out",
+                "a.jpg",
+                "--ext",
+                "asc",
+                "--format",
+                "asciicast"
+            ]),
+            Format::Asciicast
+        );
+    }
+
+    #[test]
+    fn show_font() {
+        let parse = |argv: &[&str]| {
+            Args::try_parse_from(std::iter::once("img2ascii").chain(argv.iter().copied()))
+        };
+
+        assert!(parse(&["--show-font", "Iosevka", "--font-lib", "fonts.lib"]).is_ok());
+        assert!(parse(&["--show-font", "Iosevka", "--font-file", "a.ttf"]).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+/*!
+Playing animated images and video streams in the terminal.
+
+Each frame is shown for its own delay (or, for video streams, one over
+the frame rate), scaled by the playback speed. Playback is scheduled
+against the clock, so time spent rendering doesn't slow it down; if it
+falls behind anyway, it carries on from there rather than rushing to
+catch up.
+
+To loop, the frames are kept (as rendered text) after the first time
+through. A stream from stdin could go on indefinitely, so it can only be
+played once.
+*/
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use ascii_art::{AnimationOptions, Animator, FrameReader, RenderOptions};
+
+use crate::{ErrorShim, Render};
+
+/// The longest sleep between checks for an interruption.
+const NAP: Duration = Duration::from_millis(20);
+
+/// Set by the `SIGINT` handler when the user presses Ctrl-C.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/*
+Arrange for `INTERRUPTED` to be set (instead of the program just ending,
+with the cursor still hidden) when the user presses Ctrl-C.
+*/
+#[cfg(unix)]
+fn catch_interrupt() -> Result<(), ErrorShim> {
+    // SAFETY: the handler only stores to an atomic, and `action` is
+    // otherwise zeroed (no flags, empty mask).
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut()) != 0 {
+            return Err(ErrorShim::from(std::io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn catch_interrupt() -> Result<(), ErrorShim> {
+    // SAFETY: the handler only stores to an atomic.
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+    Ok(())
+}
+
+/*
+The file or stream being played, which gives up reading (with an error)
+once interrupted, rather than waiting on for a stalled stream.
+*/
+struct Source<R>(R);
+
+impl<R: Read> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            // `SIGINT` isn't caught with `SA_RESTART`, so a read that's
+            // waiting returns with `Interrupted`, which `read_exact()` (and
+            // the like) would otherwise try again.
+            if INTERRUPTED.load(Ordering::Relaxed) {
+                return Err(std::io::Error::other("interrupted"));
+            }
+            match self.0.read(buf) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                result => return result,
+            }
+        }
+    }
+}
+
+/*
+Sleep until `when`, in short naps, so an interruption isn't kept
+waiting. Returns `false` if interrupted.
+*/
+fn sleep_until(when: Instant) -> bool {
+    loop {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return false;
+        }
+        match when.checked_duration_since(Instant::now()) {
+            Some(wait) if !wait.is_zero() => std::thread::sleep(wait.min(NAP)),
+            _ => return true,
+        }
+    }
+}
+
+/**
+What to play, and how.
+*/
+pub struct Playback {
+    /// file to play (or, if there isn't one, stdin)
+    pub source: Option<PathBuf>,
+    /// number of times to play it through (0 for forever)
+    pub loops: u32,
+    /// playback speed multiplier
+    pub speed: f64,
+}
+
+impl Playback {
+    /*
+    Write a frame of `text` as soon as it's `due`, then work out when the
+    next frame is due, `delay` later. Returns `false` if interrupted.
+    */
+    fn show<W: Write>(
+        &self,
+        text: &str,
+        due: &mut Instant,
+        delay: Duration,
+        out: &mut W,
+    ) -> Result<bool, ErrorShim> {
+        if !sleep_until(*due) {
+            return Ok(false);
+        }
+        out.write_all(text.as_bytes())?;
+        out.flush()?;
+
+        let now = Instant::now();
+        *due += delay.div_f64(self.speed);
+        if *due < now {
+            *due = now;
+        }
+        Ok(true)
+    }
+
+    /*
+    Play the `frames` (with their contrast multiplied by `contrast`),
+    rendered by `animator`, to `out`, as many times as asked (or until
+    interrupted).
+    */
+    fn play<R: BufRead, W: Write>(
+        &self,
+        frames: &mut FrameReader<R>,
+        animator: &mut Animator,
+        contrast: f32,
+        out: &mut W,
+    ) -> Result<(), ErrorShim> {
+        // Frames are rendered as they're read the first time through;
+        // if there are more times to go, the text is kept for replaying.
+        // (Only the first frame is drawn in full, so it all replays the
+        // same.)
+        let replay = self.loops != 1;
+        let mut rendered: Vec<(String, Duration)> = Vec::new();
+        let mut due = Instant::now();
+
+        loop {
+            let (img, delay) = match frames.next_timed_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                // Reading gave up because of the interruption.
+                Err(_) if INTERRUPTED.load(Ordering::Relaxed) => return Ok(()),
+                Err(e) => return Err(ErrorShim::from(e)),
+            };
+            let img = if contrast != 1.0 {
+                img.with_contrast(contrast)
+            } else {
+                img
+            };
+            let text = animator.update(&img);
+            if !self.show(&text, &mut due, delay, out)? {
+                return Ok(());
+            }
+            if replay {
+                rendered.push((text, delay));
+            }
+        }
+
+        let mut played = 1;
+        while !rendered.is_empty() && (self.loops == 0 || played < self.loops) {
+            for (text, delay) in rendered.iter() {
+                if !self.show(text, &mut due, *delay, out)? {
+                    return Ok(());
+                }
+            }
+            played += 1;
+        }
+
+        // Hold the last frame for its delay, too.
+        sleep_until(due);
+        Ok(())
+    }
+
+    /**
+    Play the animation or stream with `render`'s font and settings,
+    fitting it to the terminal if no size is given.
+
+    Fails if asked to loop a stream from stdin.
+    */
+    pub fn run(&self, render: &Render) -> Result<(), ErrorShim> {
+        let source: Box<dyn BufRead> = match &self.source {
+            Some(path) => Box::new(BufReader::new(Source(File::open(path)?))),
+            None if self.loops != 1 => {
+                return Err(ErrorShim(
+                    "--loop needs a file to play; a stream from stdin plays once".to_owned(),
+                ));
+            }
+            None => Box::new(BufReader::new(Source(std::io::stdin()))),
+        };
+        let mut frames = FrameReader::auto(source)?;
+
+        let (cols, rows) = match (render.cols, render.rows) {
+            (None, None) => match crate::terminal_size() {
+                // Leave a line for the prompt afterwards.
+                Some((w, h)) => (Some(w), Some(h.saturating_sub(1).max(1))),
+                None => (None, None),
+            },
+            size => size,
+        };
+        let opts = RenderOptions {
+            invert: render.invert,
+            ..RenderOptions::new()
+        };
+        let opts = opts.fit(&render.font, frames.dimensions(), cols, rows);
+        let anim = AnimationOptions {
+            diff: true,
+            ..AnimationOptions::default()
+        };
+        let mut animator = Animator::new(&render.font, &opts, &anim);
+
+        catch_interrupt()?;
+        let mut stdout = std::io::stdout().lock();
+        // Clear the screen and hide the cursor.
+        stdout.write_all(b"\x1b[2J\x1b[?25l")?;
+        let result = self.play(&mut frames, &mut animator, render.contrast, &mut stdout);
+
+        // However it ended, put the cursor back, below the last frame.
+        let (_, h) = animator.size();
+        write!(stdout, "\x1b[{};1H\x1b[?25h", h + 1)?;
+        stdout.flush()?;
+        result
+    }
+}
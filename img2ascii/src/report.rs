@@ -0,0 +1,107 @@
+/*!
+The JSON report `--json` writes: the rendered rows, along with the font
+and settings they were rendered with, and how long it all took.
+
+```json
+{"width":2,"height":1,"rows":["@%"],
+ "font":{"family":"Liberation Mono","style":"Regular","file":"/usr/share/fonts/...",
+         "pixels":12.0,"cell_width":7.2,"cell_height":14.0},
+ "polarity":"light-on-dark","contrast":1.0,"charset":" `.-'_,:~;*!...@","rejected":"",
+ "timing":{"font":0.011,"decode":0.004,"render":0.002}}
+```
+
+(The report itself is all on one line.)
+*/
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::{ErrorShim, Picture, Render};
+
+/**
+The font the rows were rendered with.
+*/
+#[derive(Serialize)]
+struct FontReport<'a> {
+    family: Option<&'a str>,
+    style: Option<&'a str>,
+    /// the font file (or font library) it came from
+    file: &'a str,
+    pixels: f32,
+    cell_width: f32,
+    cell_height: f32,
+}
+
+/**
+How long each stage took, in seconds.
+*/
+#[derive(Serialize)]
+struct Timing {
+    font: f64,
+    decode: f64,
+    render: f64,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    width: usize,
+    height: usize,
+    rows: &'a [String],
+    font: FontReport<'a>,
+    /// "light-on-dark" or (with `--invert`) "dark-on-light"
+    polarity: &'static str,
+    /// what the image's contrast was multiplied by
+    contrast: f32,
+    /// the characters rendered with, from darkest to lightest
+    charset: String,
+    /// characters the font has no glyphs for
+    rejected: String,
+    timing: Timing,
+}
+
+/**
+Render `picture` and write a report of it to `dest`.
+*/
+pub fn write<W: Write>(render: &Render, picture: &Picture, dest: W) -> Result<(), ErrorShim> {
+    let start = Instant::now();
+    let image = picture.image();
+    let opts = render.options(image);
+    let rows = ascii_art::render_with(image, &render.font, &opts);
+    let render_time = start.elapsed();
+
+    let font = &render.font;
+    let (cell_width, cell_height) = font.geometry();
+    let report = Report {
+        width: rows.iter().map(|r| r.chars().count()).max().unwrap_or(0),
+        height: rows.len(),
+        rows: &rows,
+        font: FontReport {
+            family: font.family(),
+            style: font.style(),
+            file: &render.font_file,
+            pixels: font.pixel_size().unwrap_or(render.spec.pixels),
+            cell_width,
+            cell_height,
+        },
+        polarity: if render.invert {
+            "dark-on-light"
+        } else {
+            "light-on-dark"
+        },
+        contrast: render.contrast,
+        charset: font.ramp().into_iter().map(|(c, _)| c).collect(),
+        rejected: render.rejected.iter().collect(),
+        timing: Timing {
+            font: render.font_time.as_secs_f64(),
+            decode: picture.time.as_secs_f64(),
+            render: render_time.as_secs_f64(),
+        },
+    };
+
+    let mut w = BufWriter::new(dest);
+    serde_json::to_writer(&mut w, &report)?;
+    writeln!(w)?;
+    w.flush()?;
+    Ok(())
+}
@@ -0,0 +1,422 @@
+/*!
+Settings and configuration files: rendering options kept in TOML files,
+rather than given on the command line.
+
+```toml
+font = "Anonymous Pro"
+pixels = 16
+invert = true
+charset = " .:-=+*#%@"
+contrast = 1.5
+fit = "80x24"
+```
+
+The keys are the long names of the corresponding command-line options.
+Those in a settings file replace the command-line values; those in the
+configuration file are only used where no value is given on the command
+line. The configuration file can also contain named presets, each a table
+of more settings, which take precedence over the ones at the top level:
+
+```toml
+font = "Iosevka"
+
+[presets.big]
+pixels = 24
+fit-terminal = true
+```
+*/
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use serde::Deserialize;
+
+use crate::{parse_charset, parse_contrast, parse_fit, Args, ErrorShim};
+
+/**
+The options that can be given in a settings file.
+*/
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    pub font: Option<String>,
+    pub font_file: Option<String>,
+    pub font_lib: Option<String>,
+    pub pixels: Option<f32>,
+    pub invert: Option<bool>,
+    pub charset: Option<String>,
+    pub contrast: Option<f32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fit: Option<String>,
+    pub fit_terminal: Option<bool>,
+}
+
+impl Settings {
+    /**
+    Read the settings file at `path`.
+    */
+    pub fn load(path: &Path) -> Result<Settings, ErrorShim> {
+        let settings: Settings = read_toml(path)?;
+        settings
+            .check()
+            .map_err(|e| ErrorShim(format!("{}: {}", path.display(), &e)))?;
+        Ok(settings)
+    }
+
+    /*
+    Check the values that clap would otherwise have checked.
+    */
+    fn check(&self) -> Result<(), String> {
+        if let Some(charset) = &self.charset {
+            parse_charset(charset).map_err(|e| format!("charset: {}", &e))?;
+        }
+        if let Some(contrast) = self.contrast {
+            parse_contrast(&contrast.to_string()).map_err(|e| format!("contrast: {}", &e))?;
+        }
+        if let Some(fit) = &self.fit {
+            parse_fit(fit).map_err(|e| format!("fit: {}", &e))?;
+        }
+
+        // Options clap won't take together can't be given together here,
+        // either (though a preset can still replace any of them).
+        let font_file = self.font_file.is_some();
+        let (width, height) = (self.width.is_some(), self.height.is_some());
+        let fit = self.fit.is_some();
+        let fit_terminal = self.fit_terminal == Some(true);
+        let conflicts = [
+            ("font-file", font_file, "font", self.font.is_some()),
+            ("font-file", font_file, "font-lib", self.font_lib.is_some()),
+            ("fit", fit, "width", width),
+            ("fit", fit, "height", height),
+            ("fit-terminal", fit_terminal, "width", width),
+            ("fit-terminal", fit_terminal, "height", height),
+            ("fit-terminal", fit_terminal, "fit", fit),
+        ];
+        for (a, a_given, b, b_given) in conflicts {
+            if a_given && b_given {
+                return Err(format!("{} can't be used with {}", a, b));
+            }
+        }
+        Ok(())
+    }
+
+    /**
+    Return these settings, less any whose values were given on the command
+    line (as recorded in `matches`). A font file given on the command line
+    overrides any font settings; a font name overrides a font file, but
+    still gets taken from a font library given here (and a font library
+    overrides a font file, but still gets the font name given here).
+    Giving one way of sizing the output on the command line overrides all
+    of them.
+    */
+    pub fn unless_given(&self, matches: &ArgMatches) -> Settings {
+        let given = |names: &[&str]| names.iter().any(|n| matches.occurrences_of(n) > 0);
+        let mut settings = self.clone();
+
+        if given(&["font-file"]) {
+            settings.font = None;
+            settings.font_lib = None;
+        }
+        if given(&["font", "font-file", "font-lib"]) {
+            settings.font_file = None;
+        }
+        if given(&["font"]) {
+            settings.font = None;
+        }
+        if given(&["font-lib"]) {
+            settings.font_lib = None;
+        }
+        if given(&["pixels"]) {
+            settings.pixels = None;
+        }
+        if given(&["invert", "no-invert"]) {
+            settings.invert = None;
+        }
+        if given(&["charset"]) {
+            settings.charset = None;
+        }
+        if given(&["contrast"]) {
+            settings.contrast = None;
+        }
+        if given(&["width", "height", "fit", "fit-terminal", "no-fit-terminal"]) {
+            settings.width = None;
+            settings.height = None;
+            settings.fit = None;
+            settings.fit_terminal = None;
+        }
+        settings
+    }
+
+    /**
+    Replace the values in `args` with any given here. Giving one way of
+    choosing the font (or sizing the output) overrides the others.
+    */
+    pub fn apply(&self, args: &mut Args) {
+        if let Some(font) = &self.font {
+            args.font = font.clone();
+            args.font_file = None;
+        }
+        if let Some(path) = &self.font_file {
+            args.font_file = Some(path.clone());
+            args.font_lib = None;
+        }
+        if let Some(path) = &self.font_lib {
+            args.font_lib = Some(path.clone());
+            args.font_file = None;
+        }
+        if let Some(pixels) = self.pixels {
+            args.pixels = pixels;
+        }
+        if let Some(invert) = self.invert {
+            args.invert = invert;
+        }
+        if let Some(charset) = &self.charset {
+            args.charset = Some(charset.clone());
+        }
+        if let Some(contrast) = self.contrast {
+            args.contrast = contrast;
+        }
+
+        let fit = self.fit.as_deref().and_then(|f| parse_fit(f).ok());
+        let sized = self.width.is_some()
+            || self.height.is_some()
+            || fit.is_some()
+            || self.fit_terminal.is_some();
+        if sized {
+            args.width = self.width;
+            args.height = self.height;
+            args.fit = fit;
+            args.fit_terminal = self.fit_terminal.unwrap_or(false);
+        }
+    }
+}
+
+fn read_toml<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, ErrorShim> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| ErrorShim(format!("{}: {}", path.display(), &e)))?;
+    toml::from_str(&text).map_err(|e| ErrorShim(format!("{}: {}", path.display(), &e)))
+}
+
+/**
+The configuration file: settings, and named presets of more settings.
+*/
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(flatten)]
+    pub settings: Settings,
+    #[serde(default)]
+    pub presets: BTreeMap<String, Settings>,
+}
+
+impl Config {
+    /**
+    Return where the configuration file goes if it isn't given:
+    `$XDG_CONFIG_HOME/img2ascii/config.toml`, where `XDG_CONFIG_HOME`
+    defaults to `$HOME/.config`.
+    */
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("img2ascii").join("config.toml"))
+    }
+
+    /**
+    Read the configuration file at `path`.
+    */
+    pub fn load(path: &Path) -> Result<Config, ErrorShim> {
+        let config: Config = read_toml(path)?;
+        config
+            .settings
+            .check()
+            .map_err(|e| ErrorShim(format!("{}: {}", path.display(), &e)))?;
+        for (name, preset) in config.presets.iter() {
+            preset
+                .check()
+                .map_err(|e| ErrorShim(format!("{}: preset {}: {}", path.display(), name, &e)))?;
+        }
+        Ok(config)
+    }
+
+    /**
+    Apply the configuration (and the named `preset`, if any) to `args`,
+    wherever no value was given on the command line (as recorded in
+    `matches`).
+    */
+    pub fn apply(
+        &self,
+        preset: Option<&str>,
+        args: &mut Args,
+        matches: &ArgMatches,
+    ) -> Result<(), ErrorShim> {
+        self.settings.unless_given(matches).apply(args);
+
+        if let Some(name) = preset {
+            match self.presets.get(name) {
+                Some(preset) => preset.unless_given(matches).apply(args),
+                None => {
+                    let names: Vec<&str> = self.presets.keys().map(|k| k.as_str()).collect();
+                    let estr = format!(
+                        "No preset named \"{}\" (there are: {}).",
+                        name,
+                        names.join(", ")
+                    );
+                    return Err(ErrorShim(estr));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+
+    /*
+    Parse the command line `argv`, then apply the configuration file
+    `config` (and any preset the command line picks) to it.
+    */
+    fn configure(config: &str, argv: &[&str]) -> Result<Args, ErrorShim> {
+        let config: Config = toml::from_str(config).unwrap();
+        let matches = Args::command()
+            .try_get_matches_from(std::iter::once("img2ascii").chain(argv.iter().copied()))
+            .unwrap();
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        let preset = args.preset.clone();
+        config.apply(preset.as_deref(), &mut args, &matches)?;
+        Ok(args)
+    }
+
+    #[test]
+    fn precedence() {
+        let config = r#"
+            pixels = 14
+            font = "Iosevka"
+
+            [presets.big]
+            pixels = 24
+        "#;
+
+        let args = configure(config, &[]).unwrap();
+        assert_eq!((args.font.as_str(), args.pixels), ("Iosevka", 14.0));
+        assert_eq!(configure(config, &["-p", "10"]).unwrap().pixels, 10.0);
+        assert_eq!(configure(config, &["-P", "big"]).unwrap().pixels, 24.0);
+        assert_eq!(
+            configure(config, &["-P", "big", "-p", "10"])
+                .unwrap()
+                .pixels,
+            10.0
+        );
+        assert!(configure(config, &["-P", "small"]).is_err());
+
+        // A settings file replaces even what's given on the command line.
+        let mut args = configure(config, &["-p", "10", "-i"]).unwrap();
+        let settings: Settings = toml::from_str("pixels = 8\ninvert = false").unwrap();
+        settings.apply(&mut args);
+        assert_eq!((args.pixels, args.invert), (8.0, false));
+    }
+
+    #[test]
+    fn flags() {
+        let config = "invert = true\nfit-terminal = true";
+
+        let args = configure(config, &[]).unwrap();
+        assert!(args.invert && args.fit_terminal);
+        assert!(!configure(config, &["--no-invert"]).unwrap().invert);
+        assert!(configure(config, &["--no-invert", "-i"]).unwrap().invert);
+        assert!(!configure("", &["-i", "--no-invert"]).unwrap().invert);
+
+        let args = configure(config, &["--no-fit-terminal"]).unwrap();
+        assert!(!args.fit_terminal);
+        assert_eq!((args.width, args.height, args.fit), (None, None, None));
+        let args = configure(config, &["--width", "40"]).unwrap();
+        assert!(!args.fit_terminal);
+        assert_eq!(args.width, Some(40));
+    }
+
+    #[test]
+    fn tone() {
+        let config = r#"
+            charset = " .:-=+*#%@"
+            contrast = 1.5
+
+            [presets.flat]
+            contrast = 0.5
+        "#;
+
+        let args = configure(config, &[]).unwrap();
+        assert_eq!(args.charset.as_deref(), Some(" .:-=+*#%@"));
+        assert_eq!(args.contrast, 1.5);
+        assert_eq!(configure(config, &["-P", "flat"]).unwrap().contrast, 0.5);
+        let args = configure(config, &["--charset", " #", "--contrast", "2"]).unwrap();
+        assert_eq!((args.charset.as_deref(), args.contrast), (Some(" #"), 2.0));
+        let args = configure("", &[]).unwrap();
+        assert_eq!((args.charset, args.contrast), (None, 1.0));
+    }
+
+    #[test]
+    fn fonts() {
+        // A font library given on the command line still gets the font
+        // name from the configuration, and vice versa.
+        let args = configure("font = \"Anonymous Pro\"", &["--font-lib", "fonts.lib"]).unwrap();
+        assert_eq!(args.font, "Anonymous Pro");
+        assert_eq!(args.font_lib.as_deref(), Some("fonts.lib"));
+        let args = configure("font-lib = \"fonts.lib\"", &["-f", "Iosevka"]).unwrap();
+        assert_eq!(args.font, "Iosevka");
+        assert_eq!(args.font_lib.as_deref(), Some("fonts.lib"));
+
+        // A font file overrides everything else, in either direction.
+        let config = "font = \"Anonymous Pro\"\nfont-lib = \"fonts.lib\"";
+        let args = configure(config, &["--font-file", "a.ttf"]).unwrap();
+        assert_eq!(args.font_file.as_deref(), Some("a.ttf"));
+        assert_eq!(args.font_lib, None);
+        let args = configure("font-file = \"a.ttf\"", &["-f", "Iosevka"]).unwrap();
+        assert_eq!((args.font.as_str(), args.font_file), ("Iosevka", None));
+        let args = configure("font-file = \"a.ttf\"", &["--font-lib", "fonts.lib"]).unwrap();
+        assert_eq!(args.font_file, None);
+        assert_eq!(args.font_lib.as_deref(), Some("fonts.lib"));
+    }
+
+    #[test]
+    fn checks() {
+        assert!(toml::from_str::<Settings>("colour = true").is_err());
+        for bad in [
+            "charset = \"\"",
+            "contrast = 0",
+            "contrast = -1.5",
+            "contrast = nan",
+        ] {
+            let settings: Settings = toml::from_str(bad).unwrap();
+            assert!(settings.check().is_err(), "{:?}", bad);
+        }
+        let settings: Settings = toml::from_str("fit = \"80 by 24\"").unwrap();
+        assert!(settings.check().is_err());
+        let settings: Settings = toml::from_str("fit = \"80x24\"").unwrap();
+        assert!(settings.check().is_ok());
+
+        // The combinations clap rejects are rejected here, too.
+        for bad in [
+            "width = 40\nfit = \"80x24\"",
+            "height = 20\nfit = \"80x24\"",
+            "width = 40\nfit-terminal = true",
+            "fit = \"80x24\"\nfit-terminal = true",
+            "font-file = \"a.ttf\"\nfont = \"Iosevka\"",
+            "font-file = \"a.ttf\"\nfont-lib = \"fonts.lib\"",
+        ] {
+            let settings: Settings = toml::from_str(bad).unwrap();
+            assert!(settings.check().is_err(), "{:?}", bad);
+        }
+        for good in [
+            "width = 40\nheight = 20",
+            "width = 40\nfit-terminal = false",
+            "font = \"Iosevka\"\nfont-lib = \"fonts.lib\"",
+        ] {
+            let settings: Settings = toml::from_str(good).unwrap();
+            assert!(settings.check().is_ok(), "{:?}", good);
+        }
+    }
+}
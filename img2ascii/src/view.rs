@@ -0,0 +1,557 @@
+/*!
+Viewing images full-screen in the terminal, with keys to zoom, pan, and
+adjust the rendering, and to step through several files.
+
+The terminal is switched to its alternate screen (so whatever was there
+is back when the viewer exits) and put in raw mode, so keys arrive as
+they're pressed. The image is redrawn whenever anything changes,
+including the size of the terminal.
+*/
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use ascii_art::{FontData, Image, RenderOptions};
+
+use crate::{ErrorShim, Render};
+
+/// How much each press of a zoom key zooms in or out.
+const ZOOM_STEP: f32 = 1.25;
+/// How far in the view can be zoomed.
+const MAX_ZOOM: f32 = 32.0;
+/// How far (as a fraction of the view) each press of a pan key moves.
+const PAN_STEP: f32 = 0.1;
+/// How much each press of a contrast key changes the contrast.
+const CONTRAST_STEP: f32 = 1.1;
+
+/// Character sets to cycle through (after the font's own), if there's a
+/// font file to analyze them from.
+const CHARSETS: &[(&str, &str)] = &[
+    ("simple", " .:-=+*#%@"),
+    ("blocks", " \u{2591}\u{2592}\u{2593}\u{2588}"),
+];
+
+/// How long (in milliseconds) to wait for the rest of an escape sequence.
+const ESCAPE_WAIT: libc::c_int = 50;
+
+const HELP: &str = "+/- zoom, arrows pan, i invert, c chars, [/] contrast, n/p file, q quit";
+
+/// The write end of the pipe the `SIGWINCH` handler writes a byte to when
+/// the terminal changes size.
+static RESIZE_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_resize(_: libc::c_int) {
+    let fd = RESIZE_PIPE.load(Ordering::Relaxed);
+    // SAFETY: `write()` is async-signal-safe. The pipe is non-blocking, and
+    // if it's full, there are wakeups waiting already.
+    unsafe {
+        libc::write(fd, [0u8].as_ptr().cast(), 1);
+    }
+}
+
+fn last_error() -> ErrorShim {
+    ErrorShim::from(std::io::Error::last_os_error())
+}
+
+/**
+The terminal, in raw mode and showing its alternate screen, until this is
+dropped.
+*/
+struct Screen {
+    saved: libc::termios,
+}
+
+impl Screen {
+    fn enter() -> Result<Screen, ErrorShim> {
+        // SAFETY: `isatty()` just checks the file descriptors.
+        let ttys = unsafe {
+            libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1
+        };
+        if !ttys {
+            let estr = "Viewing needs a terminal.".to_owned();
+            return Err(ErrorShim(estr));
+        }
+
+        // SAFETY: `tcgetattr()` fills in the `termios` it's given.
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Err(last_error());
+        }
+        let mut raw = saved;
+        // No echo, no line buffering, and no signals from Ctrl-C (which
+        // quits, through the usual route, so the terminal gets restored).
+        raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        // SAFETY: `raw` is a valid `termios`.
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(last_error());
+        }
+
+        // Switch to the alternate screen and hide the cursor.
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l")?;
+        stdout.flush()?;
+        Ok(Screen { saved })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let mut stdout = std::io::stdout().lock();
+        let _ = stdout.write_all(b"\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+        // SAFETY: `saved` is the `termios` `tcgetattr()` filled in.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.saved);
+        }
+    }
+}
+
+/*
+Arrange for a byte to be written to a pipe whenever the terminal changes
+size, and return the pipe's read end, for `read_keys()` to wait on along
+with the terminal. (A resize that comes while the view is being drawn
+still wakes the wait that follows, which a flag checked before waiting
+could miss.)
+*/
+fn catch_resize() -> Result<RawFd, ErrorShim> {
+    let mut fds: [libc::c_int; 2] = [-1; 2];
+    // SAFETY: `fds` has room for the two descriptors `pipe()` fills in, and
+    // the handler only writes to the pipe (which is set up by the time the
+    // handler is installed); `action` is otherwise zeroed (no flags, empty
+    // mask).
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(last_error());
+        }
+        for fd in fds {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0
+                || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) != 0
+                || libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) != 0
+            {
+                return Err(last_error());
+            }
+        }
+        RESIZE_PIPE.store(fds[1], Ordering::Relaxed);
+
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_resize as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGWINCH, &action, std::ptr::null_mut()) != 0 {
+            return Err(last_error());
+        }
+    }
+    Ok(fds[0])
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    Char(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Escape,
+}
+
+/*
+Read whatever's waiting (or, if nothing is, wait for something) from the
+terminal, adding it to `bytes`. Returns `false` if the wait was
+interrupted.
+*/
+fn read_more(bytes: &mut Vec<u8>) -> Result<bool, ErrorShim> {
+    let mut buff = [0u8; 64];
+    // SAFETY: at most `buff.len()` bytes are written to `buff`.
+    let n = unsafe { libc::read(libc::STDIN_FILENO, buff.as_mut_ptr().cast(), buff.len()) };
+    if n < 0 {
+        let e = std::io::Error::last_os_error();
+        if e.kind() == std::io::ErrorKind::Interrupted {
+            return Ok(false);
+        }
+        return Err(ErrorShim::from(e));
+    }
+    bytes.extend_from_slice(&buff[..n as usize]);
+    Ok(true)
+}
+
+/*
+Return whether `bytes` ends partway through an escape sequence.
+*/
+fn incomplete(bytes: &[u8]) -> bool {
+    match bytes.iter().rposition(|b| *b == 0x1b) {
+        Some(n) => match &bytes[n + 1..] {
+            [] | [b'['] | [b'O'] => true,
+            [b'[', params @ ..] => !params.iter().any(|b| (0x40..0x7f).contains(b)),
+            _ => false,
+        },
+        None => false,
+    }
+}
+
+/*
+Wait for keys to be pressed, and return them. Returns no keys if the
+terminal changed size (or the wait was otherwise interrupted), as given
+by a byte on the `resized` pipe from `catch_resize()`.
+*/
+fn read_keys(resized: RawFd) -> Result<Vec<Key>, ErrorShim> {
+    let mut pfds = [
+        libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: resized,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    // SAFETY: `pfds` is two valid `pollfd`s.
+    if unsafe { libc::poll(pfds.as_mut_ptr(), 2, -1) } < 0 {
+        let e = std::io::Error::last_os_error();
+        if e.kind() == std::io::ErrorKind::Interrupted {
+            return Ok(Vec::new());
+        }
+        return Err(ErrorShim::from(e));
+    }
+    if pfds[1].revents != 0 {
+        // Empty the pipe; any number of resizes needs just one redraw.
+        let mut buff = [0u8; 64];
+        // SAFETY: at most `buff.len()` bytes are written to `buff`, and the
+        // pipe is non-blocking.
+        while unsafe { libc::read(resized, buff.as_mut_ptr().cast(), buff.len()) } > 0 {}
+        return Ok(Vec::new());
+    }
+
+    let mut bytes: Vec<u8> = Vec::new();
+    if !read_more(&mut bytes)? {
+        return Ok(Vec::new());
+    }
+
+    // The rest of an escape sequence may be a moment behind (especially
+    // over a network); if it doesn't come, it was the escape key.
+    while incomplete(&bytes) {
+        let mut pfd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `pfd` is one valid `pollfd`.
+        if unsafe { libc::poll(&mut pfd, 1, ESCAPE_WAIT) } <= 0 || !read_more(&mut bytes)? {
+            break;
+        }
+    }
+    Ok(parse_keys(&bytes))
+}
+
+/*
+Turn bytes read from the terminal into keys, recognizing the escape
+sequences for the arrow and page keys.
+*/
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut n = 0;
+    while n < bytes.len() {
+        let rest = &bytes[n..];
+        let (key, len) = match rest {
+            [0x1b, b'[' | b'O', b'A', ..] => (Key::Up, 3),
+            [0x1b, b'[' | b'O', b'B', ..] => (Key::Down, 3),
+            [0x1b, b'[' | b'O', b'C', ..] => (Key::Right, 3),
+            [0x1b, b'[' | b'O', b'D', ..] => (Key::Left, 3),
+            [0x1b, b'[', b'5', b'~', ..] => (Key::PageUp, 4),
+            [0x1b, b'[', b'6', b'~', ..] => (Key::PageDown, 4),
+            // Some other sequence; skip it.
+            [0x1b, b'[', ..] => {
+                let end = rest[2..].iter().position(|b| (0x40..0x7f).contains(b));
+                n += end.map_or(rest.len(), |e| e + 3);
+                continue;
+            }
+            [0x1b, ..] => (Key::Escape, 1),
+            [b, ..] => (Key::Char(*b), 1),
+            [] => break,
+        };
+        keys.push(key);
+        n += len;
+    }
+    keys
+}
+
+/**
+What's being viewed, and how.
+*/
+struct Viewer<'a> {
+    files: &'a [String],
+    /// which of the `files` is showing
+    index: usize,
+    /// the current file, decoded (or why it couldn't be)
+    image: Result<Image, String>,
+    render: Render,
+    /// the font analyzed for each character set, once it's been needed
+    fonts: Vec<Option<Result<FontData, String>>>,
+    charset: usize,
+    invert: bool,
+    zoom: f32,
+    /// the center of the view, as fractions of the image's width and height
+    center: (f32, f32),
+    contrast: f32,
+}
+
+impl Viewer<'_> {
+    fn load(&mut self) {
+        let path = &self.files[self.index];
+        self.image = File::open(path)
+            .map_err(ErrorShim::from)
+            .and_then(|f| Image::auto(BufReader::new(f)).map_err(ErrorShim::from))
+            .map_err(|e| e.0);
+        self.zoom = 1.0;
+        self.center = (0.5, 0.5);
+    }
+
+    fn charset_name(&self) -> &str {
+        match self.charset {
+            0 => "font",
+            n => CHARSETS[n - 1].0,
+        }
+    }
+
+    /*
+    Return the font for the current character set, analyzing it first if
+    it hasn't been yet.
+    */
+    fn font(&mut self) -> Result<&FontData, String> {
+        if self.charset == 0 {
+            return Ok(&self.render.font);
+        }
+        if self.fonts[self.charset].is_none() {
+            let chars: Vec<char> = CHARSETS[self.charset - 1].1.chars().collect();
+            let font = match &self.render.font_bytes {
+                Some(bytes) => {
+                    match FontData::from_font_bytes(bytes, self.render.spec.pixels, &chars) {
+                        Ok(Ok(font)) | Ok(Err((font, _))) => Ok(font),
+                        Err(e) => Err(format!("{}", &e)),
+                    }
+                }
+                None => Err("other characters need a font file".to_owned()),
+            };
+            self.fonts[self.charset] = Some(font);
+        }
+        match &self.fonts[self.charset] {
+            Some(Ok(font)) => Ok(font),
+            Some(Err(e)) => Err(e.clone()),
+            None => unreachable!(),
+        }
+    }
+
+    /*
+    Move the center of the view by `dx` and `dy` steps.
+    */
+    fn pan(&mut self, dx: f32, dy: f32) {
+        // Keep the view within the image.
+        let margin = 0.5 / self.zoom;
+        let (x, y) = self.center;
+        self.center = (
+            (x + dx * PAN_STEP / self.zoom).clamp(margin, 1.0 - margin),
+            (y + dy * PAN_STEP / self.zoom).clamp(margin, 1.0 - margin),
+        );
+    }
+
+    fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.clamp(1.0, MAX_ZOOM);
+        self.pan(0.0, 0.0);
+    }
+
+    /*
+    Handle a key press, returning whether to keep going.
+    */
+    fn key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(b'q') | Key::Char(3) | Key::Escape => return false,
+            Key::Char(b'+') | Key::Char(b'=') => self.set_zoom(self.zoom * ZOOM_STEP),
+            Key::Char(b'-') | Key::Char(b'_') => self.set_zoom(self.zoom / ZOOM_STEP),
+            Key::Char(b'0') => {
+                self.set_zoom(1.0);
+                self.contrast = self.render.contrast;
+            }
+            Key::Left | Key::Char(b'h') => self.pan(-1.0, 0.0),
+            Key::Right | Key::Char(b'l') => self.pan(1.0, 0.0),
+            Key::Up | Key::Char(b'k') => self.pan(0.0, -1.0),
+            Key::Down | Key::Char(b'j') => self.pan(0.0, 1.0),
+            Key::Char(b'i') => self.invert = !self.invert,
+            Key::Char(b'c') => self.charset = (self.charset + 1) % self.fonts.len(),
+            Key::Char(b']') => self.contrast *= CONTRAST_STEP,
+            Key::Char(b'[') => self.contrast /= CONTRAST_STEP,
+            Key::Char(b'n') | Key::Char(b' ') | Key::PageDown
+                if self.index + 1 < self.files.len() =>
+            {
+                self.index += 1;
+                self.load();
+            }
+            Key::Char(b'p') | Key::Char(0x7f) | Key::PageUp if self.index > 0 => {
+                self.index -= 1;
+                self.load();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /*
+    Render the visible part of the image to fit in `cols` by `rows`
+    characters, returning the rows of text (or why it couldn't be).
+    */
+    fn render(&mut self, cols: u32, rows: u32) -> Result<Vec<String>, String> {
+        let (invert, zoom, center, contrast) = (self.invert, self.zoom, self.center, self.contrast);
+        let image = match &self.image {
+            Ok(image) => image,
+            Err(e) => return Err(e.clone()),
+        };
+        let (w, h) = image.dimensions();
+        let (view_w, view_h) = ((w as f32 / zoom).max(1.0), (h as f32 / zoom).max(1.0));
+        let x = (center.0 * w as f32 - view_w / 2.0).clamp(0.0, w as f32 - view_w);
+        let y = (center.1 * h as f32 - view_h / 2.0).clamp(0.0, h as f32 - view_h);
+        let view = image
+            .crop(x as u32, y as u32, view_w as u32, view_h as u32)
+            .with_contrast(contrast);
+
+        let font = self.font()?;
+        let opts = RenderOptions {
+            invert,
+            ..RenderOptions::new()
+        };
+        let opts = opts.fit(font, view.dimensions(), Some(cols), Some(rows));
+        Ok(ascii_art::render_with(&view, font, &opts))
+    }
+
+    /*
+    Draw the view, centered in the terminal, with a status line at the
+    bottom.
+    */
+    fn draw(&mut self) -> Result<(), ErrorShim> {
+        let (cols, rows) = crate::terminal_size().unwrap_or((80, 24));
+        let mut screen: Vec<u8> = Vec::new();
+        screen.extend_from_slice(b"\x1b[H\x1b[2J");
+
+        let text_rows = rows.saturating_sub(1).max(1);
+        let status = match self.render(cols, text_rows) {
+            Ok(text) => {
+                let width = text.first().map_or(0, |r| r.chars().count()) as u32;
+                let top = (text_rows - text.len() as u32) / 2;
+                let left = cols.saturating_sub(width) / 2;
+                for (n, row) in text.iter().enumerate() {
+                    write!(screen, "\x1b[{};{}H{}", top + n as u32 + 1, left + 1, row)?;
+                }
+                format!(
+                    "zoom {:.2}x, contrast {:.2}, {} chars{}",
+                    self.zoom,
+                    self.contrast,
+                    self.charset_name(),
+                    if self.invert { ", inverted" } else { "" }
+                )
+            }
+            Err(e) => e,
+        };
+
+        let name = Path::new(&self.files[self.index])
+            .file_name()
+            .unwrap_or_default();
+        let status = format!(
+            "{} ({}/{}): {} | {}",
+            name.to_string_lossy(),
+            self.index + 1,
+            self.files.len(),
+            status,
+            HELP
+        );
+        let status: String = status.chars().take(cols as usize).collect();
+        write!(
+            screen,
+            "\x1b[{};1H\x1b[7m{:<w$}\x1b[0m",
+            rows,
+            status,
+            w = cols as usize
+        )?;
+
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&screen)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/**
+View `files` full-screen in the terminal, starting with the first,
+rendered with `render` (whose size settings are ignored; the view always
+fits the terminal), until the user quits.
+*/
+pub fn run(files: &[String], render: Render) -> Result<(), ErrorShim> {
+    let (invert, contrast) = (render.invert, render.contrast);
+    let mut viewer = Viewer {
+        files,
+        index: 0,
+        image: Err(String::new()),
+        render,
+        fonts: vec![None; CHARSETS.len() + 1],
+        charset: 0,
+        invert,
+        zoom: 1.0,
+        center: (0.5, 0.5),
+        contrast,
+    };
+    viewer.load();
+
+    let _screen = Screen::enter()?;
+    let resized = catch_resize()?;
+    loop {
+        viewer.draw()?;
+        // No keys means the terminal changed size, so it's redrawn.
+        for key in read_keys(resized)?.into_iter() {
+            if !viewer.key(key) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        assert!(!incomplete(b""));
+        assert!(!incomplete(b"q"));
+        assert!(incomplete(b"\x1b"));
+        assert!(incomplete(b"q\x1b["));
+        assert!(incomplete(b"\x1bO"));
+        assert!(incomplete(b"\x1b[5"));
+        assert!(incomplete(b"\x1b[A\x1b[1;2"));
+        assert!(!incomplete(b"\x1b[A"));
+        assert!(!incomplete(b"\x1b[5~"));
+        assert!(!incomplete(b"\x1bq"));
+    }
+
+    #[test]
+    fn keys() {
+        use Key::*;
+
+        assert_eq!(parse_keys(b""), vec![]);
+        assert_eq!(parse_keys(b"q+"), vec![Char(b'q'), Char(b'+')]);
+        assert_eq!(
+            parse_keys(b"\x1b[A\x1b[B\x1bOC\x1bOD"),
+            vec![Up, Down, Right, Left]
+        );
+        assert_eq!(parse_keys(b"\x1b[5~\x1b[6~"), vec![PageUp, PageDown]);
+        assert_eq!(parse_keys(b"\x1b"), vec![Escape]);
+        assert_eq!(parse_keys(b"\x1bq"), vec![Escape, Char(b'q')]);
+        // Other sequences (like F5, or shift-up) are skipped whole.
+        assert_eq!(parse_keys(b"\x1b[15~n\x1b[1;2A"), vec![Char(b'n')]);
+        assert_eq!(parse_keys(b"p\x1b[1;2"), vec![Char(b'p')]);
+    }
+}
@@ -0,0 +1,278 @@
+/*!
+Re-rendering the source image whenever it (or the settings file) changes.
+
+On Linux, files are watched with inotify. Editors often save files by
+writing a new file and renaming it over the old one, so it's the
+directories containing the files that are watched, for files in them
+being written or moved into place. Elsewhere, the files' modification
+times are polled.
+*/
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::settings::Settings;
+use crate::{Args, ErrorShim, Render};
+
+/// How long to wait for more changes after one is noticed, so that a
+/// burst of writes only triggers one re-render.
+const SETTLE: Duration = Duration::from_millis(100);
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    use super::SETTLE;
+    use crate::ErrorShim;
+
+    fn last_error() -> ErrorShim {
+        ErrorShim::from(std::io::Error::last_os_error())
+    }
+
+    /**
+    Watches a set of files for changes.
+    */
+    pub struct Watcher {
+        fd: libc::c_int,
+        // The watch descriptor of each watched file's directory, and the
+        // file's name.
+        files: Vec<(libc::c_int, Vec<u8>)>,
+    }
+
+    impl Watcher {
+        pub fn new(paths: &[PathBuf]) -> Result<Watcher, ErrorShim> {
+            // SAFETY: no pointers involved.
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(last_error());
+            }
+            let mut watcher = Watcher {
+                fd,
+                files: Vec::new(),
+            };
+
+            for path in paths.iter() {
+                let dir = match path.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir,
+                    _ => Path::new("."),
+                };
+                let name = path.file_name().unwrap_or_default().as_bytes().to_vec();
+                let dir = CString::new(dir.as_os_str().as_bytes())?;
+                let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+                // SAFETY: `dir` is a valid, NUL-terminated string. Watching
+                // the same directory twice returns the same descriptor.
+                let wd = unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), mask) };
+                if wd < 0 {
+                    return Err(last_error());
+                }
+                watcher.files.push((wd, name));
+            }
+
+            Ok(watcher)
+        }
+
+        /*
+        Read a batch of events, adding the indices of any watched files
+        they concern to `changed`.
+        */
+        fn read_events(&self, changed: &mut Vec<usize>) -> Result<(), ErrorShim> {
+            let mut buff = [0u8; 4096];
+            // SAFETY: the kernel writes at most `buff.len()` bytes to `buff`.
+            let n = unsafe { libc::read(self.fd, buff.as_mut_ptr().cast(), buff.len()) };
+            if n < 0 {
+                return Err(last_error());
+            }
+
+            let header = std::mem::size_of::<libc::inotify_event>();
+            let mut offset = 0usize;
+            while offset + header <= n as usize {
+                // SAFETY: the kernel only writes whole events, and
+                // `read_unaligned()` doesn't care about alignment.
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(buff[offset..].as_ptr().cast()) };
+                let name = &buff[offset + header..offset + header + event.len as usize];
+                // The name is padded with NULs.
+                let name = match name.iter().position(|b| *b == 0) {
+                    Some(end) => &name[..end],
+                    None => name,
+                };
+                for (n, (wd, file)) in self.files.iter().enumerate() {
+                    if *wd == event.wd && file.as_slice() == name && !changed.contains(&n) {
+                        changed.push(n);
+                    }
+                }
+                offset += header + event.len as usize;
+            }
+            Ok(())
+        }
+
+        /**
+        Wait until any of the files change, returning the indices (in the
+        slice passed to `new()`) of the ones that did.
+        */
+        pub fn wait(&mut self) -> Result<Vec<usize>, ErrorShim> {
+            let mut changed: Vec<usize> = Vec::new();
+            while changed.is_empty() {
+                self.read_events(&mut changed)?;
+            }
+
+            // Gather up any more changes that come in quick succession.
+            loop {
+                let mut pfd = libc::pollfd {
+                    fd: self.fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                // SAFETY: `pfd` is one valid `pollfd`.
+                let ready = unsafe { libc::poll(&mut pfd, 1, SETTLE.as_millis() as libc::c_int) };
+                if ready < 0 {
+                    return Err(last_error());
+                } else if ready == 0 {
+                    break;
+                }
+                self.read_events(&mut changed)?;
+            }
+
+            Ok(changed)
+        }
+    }
+
+    impl Drop for Watcher {
+        fn drop(&mut self) {
+            // SAFETY: `fd` is ours, and not used again.
+            unsafe {
+                libc::close(self.fd);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod poll {
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    use super::SETTLE;
+    use crate::ErrorShim;
+
+    fn modified(path: &PathBuf) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /**
+    Watches a set of files for changes.
+    */
+    pub struct Watcher {
+        paths: Vec<PathBuf>,
+        times: Vec<Option<SystemTime>>,
+    }
+
+    impl Watcher {
+        pub fn new(paths: &[PathBuf]) -> Result<Watcher, ErrorShim> {
+            Ok(Watcher {
+                paths: paths.to_vec(),
+                times: paths.iter().map(modified).collect(),
+            })
+        }
+
+        /**
+        Wait until any of the files change, returning the indices (in the
+        slice passed to `new()`) of the ones that did.
+        */
+        pub fn wait(&mut self) -> Result<Vec<usize>, ErrorShim> {
+            loop {
+                std::thread::sleep(2 * SETTLE);
+                let times: Vec<Option<SystemTime>> = self.paths.iter().map(modified).collect();
+                let changed: Vec<usize> = (0..times.len())
+                    .filter(|n| times[*n] != self.times[*n])
+                    .collect();
+                self.times = times;
+                if !changed.is_empty() {
+                    return Ok(changed);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+use inotify::Watcher;
+#[cfg(not(target_os = "linux"))]
+use poll::Watcher;
+
+/*
+Render the image at `source` to `dest` (or, if there isn't one, to the
+terminal, replacing whatever was there).
+*/
+fn show(render: &Render, source: &Path, dest: Option<&Path>) -> Result<(), ErrorShim> {
+    let picture = render.decode(BufReader::new(File::open(source)?))?;
+    match dest {
+        Some(path) => render.write(&picture, File::create(path)?),
+        None => {
+            // Render it all first, so the screen is only blank for a moment.
+            let mut text: Vec<u8> = Vec::new();
+            render.write(&picture, &mut text)?;
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(b"\x1b[H\x1b[2J")?;
+            stdout.write_all(&text)?;
+            stdout.flush()?;
+            Ok(())
+        }
+    }
+}
+
+/*
+Return the `Render` for the command-line `args` with the settings file
+(if there is one) applied on top, reusing the font from `prev` if it
+hasn't changed.
+*/
+fn reload(args: &Args, prev: &Render) -> Result<Render, ErrorShim> {
+    let mut args = args.clone();
+    if let Some(path) = &args.settings {
+        Settings::load(Path::new(path))?.apply(&mut args);
+    }
+
+    let spec = crate::FontSpec::from(&args);
+    if spec == prev.spec {
+        Render::new(&args, prev.resolved())
+    } else {
+        Render::new(&args, spec.resolve()?)
+    }
+}
+
+/**
+Render `args.source` with `render`, then again (according to the
+command-line `args` and the settings file, if any) every time it or the
+settings file changes, until interrupted. Errors along the way (like an
+image file that's only half written) are reported, but don't stop it.
+*/
+pub fn run(args: Args, render: Render) -> Result<(), ErrorShim> {
+    let source = match &args.source {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let estr = "Watching requires a --source file.".to_owned();
+            return Err(ErrorShim(estr));
+        }
+    };
+    let dest = args.dest.as_ref().map(PathBuf::from);
+    let mut paths = vec![source.clone()];
+    paths.extend(args.settings.iter().map(PathBuf::from));
+    let mut watcher = Watcher::new(&paths)?;
+
+    let mut render = render;
+    loop {
+        if let Err(e) = show(&render, &source, dest.as_deref()) {
+            eprintln!("{}: {:?}", source.display(), &e);
+        }
+
+        watcher.wait()?;
+        // The terminal may have changed size, too, so always reload.
+        match reload(&args, &render) {
+            Ok(r) => render = r,
+            Err(e) => eprintln!("{:?}", &e),
+        }
+    }
+}
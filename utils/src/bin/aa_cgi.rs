@@ -31,7 +31,7 @@ You can see this program in action at
 use std::collections::HashMap;
 use std::io::{BufReader, Cursor};
 
-use ascii_art::{FontData, Image};
+use ascii_art::{FontData, FontLibrary, Image};
 use dumb_cgi::{Body, EmptyResponse, FullResponse, Request};
 
 /// Location of font data library.
@@ -40,7 +40,7 @@ const LIB_PATH: &str = "/home/dan/svc/ascii_art/fonts.json";
 /**
 Load, deserialize, and return the font data library.
 */
-fn load_library() -> Result<HashMap<String, HashMap<u16, FontData>>, String> {
+fn load_library() -> Result<FontLibrary, String> {
     let f = match std::fs::File::open(LIB_PATH) {
         Ok(f) => f,
         Err(e) => {
@@ -48,14 +48,10 @@ fn load_library() -> Result<HashMap<String, HashMap<u16, FontData>>, String> {
         }
     };
 
-    let lib: HashMap<String, HashMap<u16, FontData>> = match serde_json::from_reader(&f) {
-        Ok(x) => x,
-        Err(e) => {
-            return Err(format!("error deserializing font lib: {}", &e));
-        }
-    };
-
-    Ok(lib)
+    match FontLibrary::load(BufReader::new(f)) {
+        Ok(lib) => Ok(lib),
+        Err(e) => Err(format!("error loading font lib: {}", &e)),
+    }
 }
 
 /**
@@ -158,15 +154,10 @@ fn list_response() -> ! {
         }
     };
 
-    let mut list_map: HashMap<String, Vec<u16>> = HashMap::with_capacity(lib.len());
+    let mut list_map: HashMap<String, Vec<u16>> = HashMap::new();
 
-    for (font_name, size_map) in lib.iter() {
-        let font_name = String::from(font_name);
-        let mut sizes: Vec<u16> = Vec::with_capacity(size_map.len());
-        for (k, _) in size_map.iter() {
-            sizes.push(*k);
-        }
-        list_map.insert(font_name, sizes);
+    for font_name in lib.families() {
+        list_map.insert(String::from(font_name), lib.sizes(font_name));
     }
 
     let response_data: String = match serde_json::to_string_pretty(&list_map) {
@@ -200,11 +191,11 @@ fn render_from_server_font(
 ) -> Result<FullResponse, String> {
     let fonts = load_library()?;
 
-    let family = fonts
-        .get(font_name)
-        .ok_or(format!("No font data matching \"{}\".", font_name))?;
+    if fonts.sizes(font_name).is_empty() {
+        return Err(format!("No font data matching \"{}\".", font_name));
+    }
 
-    let font = family.get(&size).ok_or(format!(
+    let font = fonts.get(font_name, size).ok_or(format!(
         "No data for font \"{}\" at size \"{}\".",
         font_name, size
     ))?;
@@ -1,13 +1,12 @@
 /*!
 Generate a library file of `FontData`.
 
-Usage: `librarify <filename.json>`
+Usage: `librarify <filename>`
 
 This program will read a series of font names and sizes from the standard
-input, then write a JSON file of a serialized
-`HashMap<String, HashMap<u16, FontData>>`
-that contains a library of font information suitable for transferring to
-a system that may not have the given fonts installed.
+input, then write an `ascii_art::FontLibrary` containing font information
+suitable for transferring to a system that may not have the given fonts
+installed.
 
 The input format is one font per line, with the font name followed by a
 comma, then the list of pixel sizes for that font to be rendered in:
@@ -20,24 +19,11 @@ Terminus, 8 9 10 12 24
 ...etc.
 ```
 
-The produced JSON data will have the following format:
-
-```json
-{
-    "Inconsolata": {
-        8: FontData { ... },
-        9: FontData { ... },
-        ...etc.
-    },
-    "Liberation Mono" {
-        8: FontData { ... },
-        9: FontData { ... },
-        ...etc.
-    },
-
-    ...etc.
-}
-```
+If the output filename ends in `.json`, the library will be saved as a
+plain JSON document (which tools like `jq` can read); otherwise it will
+be saved in `ascii_art`'s container format, with the (smaller and
+faster-loading) binary encoding. Either way, it can be read back with
+`FontLibrary::load()`.
 
 The program will also produce, on the standard output, a list of font
 names generated. Fontconfig's matchy algorithm is weird and might not
@@ -48,7 +34,7 @@ to check whether you've gotten the fonts you want.
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::fs::File;
-use std::io::{BufRead, Write};
+use std::io::{BufRead, BufWriter};
 
 use ascii_art::*;
 use fontconfig::{Fontconfig, Pattern};
@@ -141,16 +127,17 @@ fn get_fc_font_info(fc: &Fontconfig, name: &str) -> Result<(String, String), &'s
 
 /**
 Given a font file path, a slice of pixel sizes, and a set of characters to
-use to make generate the `FontData`, return a `HashMap<u16, FontData>` with
-the sizes as keys. The second element of the returned tuple is a list of
+use to make generate the `FontData`, return a `HashMap` with the sizes as
+keys and, as values, the `FontData` along with the characters the font
+had no glyphs for. The second element of the returned tuple is a list of
 error messgaes produced (if any).
 */
 fn make_sized_data_for_font(
     fname: &str,
     sizes: &[u16],
     chars: &[char],
-) -> (HashMap<u16, FontData>, Vec<String>) {
-    let mut map: HashMap<u16, FontData> = HashMap::new();
+) -> (HashMap<u16, (FontData, Vec<char>)>, Vec<String>) {
+    let mut map: HashMap<u16, (FontData, Vec<char>)> = HashMap::new();
     let font_bytes = match std::fs::read(fname) {
         Ok(v) => v,
         Err(e) => {
@@ -169,16 +156,16 @@ fn make_sized_data_for_font(
                 errs.push(estr);
             }
             Ok(res) => {
-                let fd = match res {
-                    Ok(fd) => fd,
+                let (fd, bads) = match res {
+                    Ok(fd) => (fd, Vec::new()),
                     Err((fd, bads)) => {
                         let estr =
                             format!("\"{}\" at size {}: no coverage of {:?}", fname, *siz, &bads);
                         errs.push(estr);
-                        fd
+                        (fd, bads)
                     }
                 };
-                map.insert(*siz, fd);
+                map.insert(*siz, (fd, bads));
             }
         }
     }
@@ -207,8 +194,8 @@ fn main() -> Result<(), ErrorShim> {
     // of characters.
     let chars = printable_ascii();
     // Holds all the important data we're generating; will ultimately
-    // get serialized.
-    let mut main_map: HashMap<String, HashMap<u16, FontData>> = HashMap::new();
+    // get saved.
+    let mut library = FontLibrary::new();
 
     for (line_n, line) in std::io::stdin().lock().lines().enumerate() {
         // If there is an error in an input line, just go ahead and die.
@@ -253,11 +240,14 @@ fn main() -> Result<(), ErrorShim> {
         for err in errs.drain(..) {
             eprintln!("Error from input line {}: {}", &line_n, &err);
         }
-        main_map.insert(actual_name.clone(), map);
+        for (size, (fd, bads)) in map.into_iter() {
+            let info = EntryInfo::new(Some(&fname), &chars, &bads);
+            library.insert(&actual_name, size, fd, info);
+        }
         font_name_pairs.push((name_str, actual_name));
     }
 
-    if main_map.is_empty() {
+    if library.is_empty() {
         println!("No useable data generated; no output file written.");
     } else {
         println!();
@@ -265,9 +255,12 @@ fn main() -> Result<(), ErrorShim> {
             println!("{} <= \"{}\"", fc_name, user_name);
         }
 
-        let mut f = File::create(&outfile)?;
-        serde_json::to_writer(&mut f, &main_map)?;
-        f.flush()?;
+        let f = BufWriter::new(File::create(&outfile)?);
+        if outfile.ends_with(".json") {
+            library.save_json(f)?;
+        } else {
+            library.save(f, Encoding::Binary)?;
+        }
     }
 
     Ok(())